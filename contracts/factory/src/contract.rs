@@ -1,26 +1,66 @@
 use crate::{
-    msg::{HandleMsg, InitMsg, QueryMsg},
-    state::{config, config_read, State},
+    msg::{HandleMsg, InitMsg, ListingPolicyResponse, QueryMsg, ReceiveMsg},
+    state::{
+        allow_list, allow_list_read, config, config_read, deny_list, deny_list_read, pair_codes,
+        pair_codes_read, pairs_by_token, pairs_by_token_read, pending_pair, pending_pair_read, Fee,
+        ListingMode, PairCode, State,
+    },
+};
+use common::pair::{
+    validate_assets, validate_padding_block_size, AssetMeta, ContractLink, ContractStatus, Curve,
+    Pair, PairHandleMsg, PairInfo, PairInitMsg, ReserveVisibility, SwapLimits, SwapMode,
+    DEFAULT_PADDING_BLOCK_SIZE,
 };
-use common::pair::{AssetMeta, Pair, PairInitMsg};
 use cosmwasm_std::{
-    log, to_binary, Api, Binary, Env, Extern, HandleResponse, InitResponse, Querier, StdError,
-    StdResult, Storage, WasmMsg,
+    from_binary, log, to_binary, Api, BankMsg, Binary, Coin, CosmosMsg, Env, Extern,
+    HandleResponse, HumanAddr, InitResponse, Querier, StdError, StdResult, Storage, Uint128,
+    WasmMsg,
 };
 use cosmwasm_storage::{PrefixedStorage, ReadonlyPrefixedStorage};
-use secret_toolkit::storage::{AppendStore, AppendStoreMut};
+use secret_toolkit::{
+    snip20,
+    storage::{AppendStore, AppendStoreMut},
+    utils::{pad_handle_result, pad_query_result},
+};
+
+pub const BLOCK_SIZE: usize = 256;
+pub const NATIVE_DENOM: &str = "uscrt";
+
 pub fn init<S: Storage, A: Api, Q: Querier>(
     deps: &mut Extern<S, A, Q>,
-    _env: Env,
+    env: Env,
     msg: InitMsg,
 ) -> StdResult<InitResponse> {
+    let admin = msg.admin.unwrap_or(env.message.sender);
     let state = State {
+        treasury: msg.treasury.unwrap_or_else(|| admin.clone()),
+        admin,
+        pending_admin: None,
         pair_code_id: msg.pair_code_id,
+        pair_code_hash: msg.pair_code_hash,
+        pair_version: msg.pair_version,
+        creation_fee: msg.creation_fee,
+        listing_mode: msg.listing_mode.unwrap_or(ListingMode::Open),
+        padding_block_size: msg.padding_block_size.unwrap_or(DEFAULT_PADDING_BLOCK_SIZE),
+        status: ContractStatus::Normal,
     };
 
+    validate_version(&state.pair_version)?;
+    validate_padding_block_size(state.padding_block_size)?;
+
     config(&mut deps.storage).save(&state)?;
+    pair_codes(&mut deps.storage).save(&vec![PairCode {
+        code_id: state.pair_code_id,
+        code_hash: state.pair_code_hash.clone(),
+        version: state.pair_version.clone(),
+    }])?;
+    allow_list(&mut deps.storage).save(&vec![])?;
+    deny_list(&mut deps.storage).save(&vec![])?;
 
-    Ok(InitResponse::default())
+    Ok(InitResponse {
+        messages: register_fee_token(&state.creation_fee, env.contract_code_hash)?,
+        log: vec![],
+    })
 }
 
 pub fn handle<S: Storage, A: Api, Q: Querier>(
@@ -28,85 +68,803 @@ pub fn handle<S: Storage, A: Api, Q: Querier>(
     env: Env,
     msg: HandleMsg,
 ) -> StdResult<HandleResponse> {
-    match msg {
-        HandleMsg::CreatePair { assets_meta } => try_handle_create_pair(deps, env, assets_meta),
-    }
+    let block_size = config_read(&deps.storage).load()?.padding_block_size;
+    let response = match msg {
+        HandleMsg::CreatePair {
+            assets_meta,
+            version,
+            curve,
+            swap_mode,
+            ..
+        } => try_handle_create_pair(deps, env, assets_meta, version, curve, swap_mode),
+        HandleMsg::Receive {
+            from, amount, msg, ..
+        } => try_receive(deps, env, from, amount, msg),
+        HandleMsg::UpdateConfig {
+            treasury,
+            padding_block_size,
+            ..
+        } => try_update_config(deps, env, treasury, padding_block_size),
+        HandleMsg::RegisterPairCode {
+            code_id,
+            code_hash,
+            version,
+            ..
+        } => try_register_pair_code(deps, env, code_id, code_hash, version),
+        HandleMsg::RegisterPair {} => try_register_pair(deps, env),
+        HandleMsg::MigrateLiquidity {
+            from_pair,
+            to_pair,
+            min_share,
+            ..
+        } => try_migrate_liquidity(deps, env, from_pair, to_pair, min_share),
+        HandleMsg::RampAmplification {
+            pair,
+            future_amp,
+            future_time,
+            ..
+        } => try_ramp_amplification(deps, env, pair, future_amp, future_time),
+        HandleMsg::SetReserveVisibility {
+            pair, visibility, ..
+        } => try_set_reserve_visibility(deps, env, pair, visibility),
+        HandleMsg::SetSwapLimits { pair, limits, .. } => {
+            try_set_swap_limits(deps, env, pair, limits)
+        }
+        HandleMsg::SetStatus { pair, status, .. } => try_set_status(deps, env, pair, status),
+        HandleMsg::SetCreationFee { creation_fee, .. } => {
+            try_set_creation_fee(deps, env, creation_fee)
+        }
+        HandleMsg::SetListingMode { listing_mode, .. } => {
+            try_set_listing_mode(deps, env, listing_mode)
+        }
+        HandleMsg::UpdateAllowList { add, remove, .. } => {
+            try_update_allow_list(deps, env, add, remove)
+        }
+        HandleMsg::UpdateDenyList { add, remove, .. } => {
+            try_update_deny_list(deps, env, add, remove)
+        }
+        HandleMsg::ChangeAdmin { address, .. } => try_change_admin(deps, env, address),
+        HandleMsg::AcceptAdmin { .. } => try_accept_admin(deps, env),
+    };
+    pad_handle_result(response, block_size as usize)
 }
 pub fn query<S: Storage, A: Api, Q: Querier>(
     deps: &Extern<S, A, Q>,
     msg: QueryMsg,
 ) -> StdResult<Binary> {
-    match msg {
+    let block_size = config_read(&deps.storage).load()?.padding_block_size;
+    let response = match msg {
         QueryMsg::Config {} => to_binary(&query_config(deps)?),
-        QueryMsg::Pair { assets_meta } => to_binary(&query_pair(deps, assets_meta)?),
-    }
+        QueryMsg::Pair {
+            assets_meta,
+            version,
+        } => to_binary(&query_pair(deps, assets_meta, version)?),
+        QueryMsg::PairsByToken { token } => to_binary(&query_pairs_by_token(deps, token)?),
+        QueryMsg::PairCodes {} => to_binary(&pair_codes_read(&deps.storage).load()?),
+        QueryMsg::ListingPolicy {} => to_binary(&query_listing_policy(deps)?),
+    };
+    pad_query_result(response, block_size as usize)
 }
 
 pub fn try_handle_create_pair<S: Storage, A: Api, Q: Querier>(
     deps: &mut Extern<S, A, Q>,
     env: Env,
-    assets: [AssetMeta; 2],
+    assets: Vec<AssetMeta>,
+    version: Option<String>,
+    curve: Option<Curve>,
+    swap_mode: Option<SwapMode>,
+) -> StdResult<HandleResponse> {
+    let config = config_read(&deps.storage).load()?;
+
+    let mut fee_msgs = match &config.creation_fee {
+        None => {
+            if !env.message.sent_funds.is_empty() {
+                return Err(StdError::generic_err("Pair creation takes no funds"));
+            }
+            vec![]
+        }
+        Some(Fee::Native { amount }) => {
+            let fee = vec![Coin {
+                denom: NATIVE_DENOM.to_string(),
+                amount: *amount,
+            }];
+            if env.message.sent_funds != fee {
+                return Err(StdError::generic_err(format!(
+                    "Pair creation fee is exactly {}{}",
+                    amount, NATIVE_DENOM
+                )));
+            }
+            vec![BankMsg::Send {
+                from_address: env.contract.address.clone(),
+                to_address: config.treasury.clone(),
+                amount: fee,
+            }
+            .into()]
+        }
+        Some(Fee::Token { contract_addr, .. }) => {
+            return Err(StdError::generic_err(format!(
+                "Pair creation fee must be paid by sending {} to the factory",
+                contract_addr
+            )));
+        }
+    };
+
+    let creator = env.message.sender.clone();
+    let mut res = create_pair(deps, env, creator, assets, version, curve, swap_mode)?;
+    fee_msgs.append(&mut res.messages);
+    res.messages = fee_msgs;
+    Ok(res)
+}
+
+pub fn try_receive<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    from: HumanAddr,
+    amount: Uint128,
+    msg: Option<Binary>,
+) -> StdResult<HandleResponse> {
+    let msg: ReceiveMsg = from_binary(&msg.ok_or_else(|| StdError::generic_err("Missing msg"))?)?;
+    let config = config_read(&deps.storage).load()?;
+
+    match msg {
+        ReceiveMsg::CreatePair {
+            assets_meta,
+            version,
+            curve,
+            swap_mode,
+        } => {
+            let fee_msg = match &config.creation_fee {
+                Some(Fee::Token {
+                    contract_addr,
+                    token_code_hash,
+                    amount: fee,
+                }) if *contract_addr == env.message.sender => {
+                    if amount < *fee {
+                        return Err(StdError::generic_err(format!(
+                            "Pair creation fee is {} {}",
+                            fee, contract_addr
+                        )));
+                    }
+                    snip20::transfer_msg(
+                        config.treasury.clone(),
+                        amount,
+                        None,
+                        None,
+                        BLOCK_SIZE,
+                        token_code_hash.clone(),
+                        contract_addr.clone(),
+                    )?
+                }
+                _ => return Err(StdError::unauthorized()),
+            };
+
+            let mut res = create_pair(deps, env, from, assets_meta, version, curve, swap_mode)?;
+            res.messages.insert(0, fee_msg);
+            Ok(res)
+        }
+    }
+}
+
+fn create_pair<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    creator: HumanAddr,
+    assets: Vec<AssetMeta>,
+    version: Option<String>,
+    curve: Option<Curve>,
+    swap_mode: Option<SwapMode>,
 ) -> StdResult<HandleResponse> {
     let config = config_read(&deps.storage).load()?;
+    enforce_normal_status(&config)?;
+    validate_assets(&assets)?;
+    enforce_listing_policy(&deps.storage, &config, &creator, &assets)?;
+
+    let code = pair_code(&deps.storage, &version.unwrap_or(config.pair_version))?;
 
     let mut pairs_store: PrefixedStorage<S> =
         PrefixedStorage::multilevel(&[b"pairs"], &mut deps.storage);
     let mut pairs_store: AppendStoreMut<Pair, PrefixedStorage<S>> =
         AppendStoreMut::attach_or_create(&mut pairs_store)?;
 
-    if let Some(_) = pairs_store
-        .iter()
-        .rev()
-        .find(|x| x.as_ref().unwrap().assets == assets)
-    {
+    // Pairs are looked up by assets and version only, so the curve doesn't tell them apart.
+    if pairs_store.iter().rev().any(|x| {
+        let x = x.as_ref().unwrap();
+        x.has_assets(&assets) && x.version == code.version
+    }) {
         return Err(StdError::generic_err("Pair already exists"));
     }
-    let pair = Pair::new(&assets, env.contract.address.clone(), deps.api);
+    // The pair's address is only known once it registers itself, see `try_register_pair`.
+    let pair = Pair::new(
+        &assets,
+        code.version.clone(),
+        env.contract.address.clone(),
+        deps.api,
+    );
 
     pairs_store.push(&pair)?;
+    let index = pairs_store.len() - 1;
+    pending_pair(&mut deps.storage).save(&index)?;
 
+    let name = assets
+        .iter()
+        .map(|asset| asset.to_string())
+        .collect::<Vec<_>>()
+        .join("-");
     let init_msg = WasmMsg::Instantiate {
-        code_id: config.pair_code_id,
-        callback_code_hash: env.contract_code_hash,
+        code_id: code.code_id,
+        callback_code_hash: code.code_hash,
         send: vec![],
-        label: format!("pair-{}-{}", pair.assets[0], pair.assets[1]),
+        label: format!("pair-{}-{}", name, pair.version),
         msg: to_binary(&PairInitMsg {
             assets_meta: assets.clone(),
+            version: code.version,
+            factory: ContractLink {
+                address: env.contract.address,
+                code_hash: env.contract_code_hash,
+            },
+            curve: curve.unwrap_or_default(),
+            swap_mode: swap_mode.unwrap_or_default(),
+            padding_block_size: Some(config.padding_block_size),
         })?,
     };
 
     Ok(HandleResponse {
         messages: vec![init_msg.into()],
+        log: vec![log("action", "create_pair"), log("pair", name)],
+        data: None,
+    })
+}
+
+pub fn try_register_pair<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+) -> StdResult<HandleResponse> {
+    let index = pending_pair_read(&deps.storage)
+        .may_load()?
+        .ok_or_else(StdError::unauthorized)?;
+    pending_pair(&mut deps.storage).remove();
+
+    let mut pairs_store: PrefixedStorage<S> =
+        PrefixedStorage::multilevel(&[b"pairs"], &mut deps.storage);
+    let mut pairs_store: AppendStoreMut<Pair, PrefixedStorage<S>> =
+        AppendStoreMut::attach_or_create(&mut pairs_store)?;
+
+    let mut pair = pairs_store.get_at(index)?;
+    pair.contract_addr = env.message.sender;
+    pairs_store.set_at(index, &pair)?;
+
+    for asset in pair.assets.iter() {
+        let key = asset.to_raw(deps.api)?;
+        let mut indexes = pairs_by_token_read(&deps.storage)
+            .may_load(key.as_bytes())?
+            .unwrap_or_default();
+        indexes.push(index);
+        pairs_by_token(&mut deps.storage).save(key.as_bytes(), &indexes)?;
+    }
+
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "register_pair"),
+            log("pair_addr", &pair.contract_addr),
+        ],
+        data: None,
+    })
+}
+
+pub fn try_register_pair_code<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    code_id: u64,
+    code_hash: String,
+    version: String,
+) -> StdResult<HandleResponse> {
+    let mut state = config_read(&deps.storage).load()?;
+    enforce_admin(&state, &env)?;
+    validate_version(&version)?;
+
+    let mut codes = pair_codes_read(&deps.storage).load()?;
+    if codes.iter().any(|code| code.version == version) {
+        return Err(StdError::generic_err(format!(
+            "Pair version {} already registered",
+            version
+        )));
+    }
+    codes.push(PairCode {
+        code_id,
+        code_hash: code_hash.clone(),
+        version: version.clone(),
+    });
+    pair_codes(&mut deps.storage).save(&codes)?;
+
+    state.pair_code_id = code_id;
+    state.pair_code_hash = code_hash;
+    state.pair_version = version.clone();
+    config(&mut deps.storage).save(&state)?;
+
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![log("action", "register_pair_code"), log("version", version)],
+        data: None,
+    })
+}
+
+pub fn try_migrate_liquidity<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    from_pair: HumanAddr,
+    to_pair: HumanAddr,
+    min_share: Option<Uint128>,
+) -> StdResult<HandleResponse> {
+    enforce_normal_status(&config_read(&deps.storage).load()?)?;
+    let from = find_pair(&deps.storage, |pair| pair.contract_addr == from_pair)?;
+    let to = find_pair(&deps.storage, |pair| pair.contract_addr == to_pair)?;
+    if from != to || from_pair == to_pair {
+        return Err(StdError::generic_err(
+            "Liquidity can only be migrated between pairs of the same assets",
+        ));
+    }
+
+    let msg = WasmMsg::Execute {
+        contract_addr: from_pair.clone(),
+        callback_code_hash: pair_code(&deps.storage, &from.version)?.code_hash,
+        msg: to_binary(&PairHandleMsg::MigrateLiquidity {
+            owner: env.message.sender,
+            new_pair: ContractLink {
+                address: to_pair.clone(),
+                code_hash: pair_code(&deps.storage, &to.version)?.code_hash,
+            },
+            min_share,
+        })?,
+        send: vec![],
+    };
+
+    Ok(HandleResponse {
+        messages: vec![msg.into()],
         log: vec![
-            log("action", "create_pair"),
-            log("pair", format!("{}-{}", &assets[0], &assets[1])),
+            log("action", "migrate_liquidity"),
+            log("from_pair", from_pair),
+            log("to_pair", to_pair),
         ],
         data: None,
     })
 }
 
+pub fn try_ramp_amplification<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    pair_addr: HumanAddr,
+    future_amp: u64,
+    future_time: u64,
+) -> StdResult<HandleResponse> {
+    let config = config_read(&deps.storage).load()?;
+    enforce_admin(&config, &env)?;
+
+    let pair = find_pair(&deps.storage, |pair| pair.contract_addr == pair_addr)?;
+    let msg = WasmMsg::Execute {
+        contract_addr: pair_addr.clone(),
+        callback_code_hash: pair_code(&deps.storage, &pair.version)?.code_hash,
+        msg: to_binary(&PairHandleMsg::RampAmplification {
+            future_amp,
+            future_time,
+        })?,
+        send: vec![],
+    };
+
+    Ok(HandleResponse {
+        messages: vec![msg.into()],
+        log: vec![
+            log("action", "ramp_amplification"),
+            log("pair", pair_addr),
+            log("future_amp", future_amp),
+        ],
+        data: None,
+    })
+}
+
+pub fn try_set_reserve_visibility<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    pair_addr: HumanAddr,
+    visibility: ReserveVisibility,
+) -> StdResult<HandleResponse> {
+    let config = config_read(&deps.storage).load()?;
+    enforce_admin(&config, &env)?;
+
+    let pair = find_pair(&deps.storage, |pair| pair.contract_addr == pair_addr)?;
+    let msg = WasmMsg::Execute {
+        contract_addr: pair_addr.clone(),
+        callback_code_hash: pair_code(&deps.storage, &pair.version)?.code_hash,
+        msg: to_binary(&PairHandleMsg::SetReserveVisibility { visibility })?,
+        send: vec![],
+    };
+
+    Ok(HandleResponse {
+        messages: vec![msg.into()],
+        log: vec![
+            log("action", "set_reserve_visibility"),
+            log("pair", pair_addr),
+        ],
+        data: None,
+    })
+}
+
+pub fn try_set_swap_limits<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    pair_addr: HumanAddr,
+    limits: SwapLimits,
+) -> StdResult<HandleResponse> {
+    let config = config_read(&deps.storage).load()?;
+    enforce_admin(&config, &env)?;
+
+    let pair = find_pair(&deps.storage, |pair| pair.contract_addr == pair_addr)?;
+    let msg = WasmMsg::Execute {
+        contract_addr: pair_addr.clone(),
+        callback_code_hash: pair_code(&deps.storage, &pair.version)?.code_hash,
+        msg: to_binary(&PairHandleMsg::SetSwapLimits { limits })?,
+        send: vec![],
+    };
+
+    Ok(HandleResponse {
+        messages: vec![msg.into()],
+        log: vec![log("action", "set_swap_limits"), log("pair", pair_addr)],
+        data: None,
+    })
+}
+
+pub fn try_set_status<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    pair_addr: Option<HumanAddr>,
+    status: ContractStatus,
+) -> StdResult<HandleResponse> {
+    let mut state = config_read(&deps.storage).load()?;
+    enforce_admin(&state, &env)?;
+
+    let pairs = match pair_addr {
+        Some(pair_addr) => vec![find_pair(&deps.storage, |pair| {
+            pair.contract_addr == pair_addr
+        })?],
+        None => {
+            state.status = status;
+            config(&mut deps.storage).save(&state)?;
+
+            let pairs_store: ReadonlyPrefixedStorage<S> =
+                ReadonlyPrefixedStorage::multilevel(&[b"pairs"], &deps.storage);
+            match AppendStore::<Pair, _, _>::attach(&pairs_store) {
+                Some(pairs_store) => pairs_store?.iter().collect::<StdResult<_>>()?,
+                None => vec![],
+            }
+        }
+    };
+
+    let mut messages = vec![];
+    let mut log_entries = vec![
+        log("action", "set_status"),
+        log("status", format!("{:?}", status)),
+    ];
+    for pair in pairs {
+        messages.push(
+            WasmMsg::Execute {
+                contract_addr: pair.contract_addr.clone(),
+                callback_code_hash: pair_code(&deps.storage, &pair.version)?.code_hash,
+                msg: to_binary(&PairHandleMsg::SetStatus { status })?,
+                send: vec![],
+            }
+            .into(),
+        );
+        log_entries.push(log("pair", pair.contract_addr));
+    }
+
+    Ok(HandleResponse {
+        messages,
+        log: log_entries,
+        data: None,
+    })
+}
+
+pub fn try_update_config<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    treasury: Option<HumanAddr>,
+    padding_block_size: Option<u32>,
+) -> StdResult<HandleResponse> {
+    let mut state = config_read(&deps.storage).load()?;
+    enforce_admin(&state, &env)?;
+
+    if let Some(treasury) = treasury {
+        state.treasury = treasury;
+    }
+    if let Some(padding_block_size) = padding_block_size {
+        validate_padding_block_size(padding_block_size)?;
+        state.padding_block_size = padding_block_size;
+    }
+    config(&mut deps.storage).save(&state)?;
+
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![log("action", "update_config")],
+        data: None,
+    })
+}
+
+pub fn try_set_creation_fee<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    creation_fee: Option<Fee>,
+) -> StdResult<HandleResponse> {
+    let mut state = config_read(&deps.storage).load()?;
+    enforce_admin(&state, &env)?;
+
+    state.creation_fee = creation_fee;
+    config(&mut deps.storage).save(&state)?;
+
+    Ok(HandleResponse {
+        messages: register_fee_token(&state.creation_fee, env.contract_code_hash)?,
+        log: vec![log("action", "set_creation_fee")],
+        data: None,
+    })
+}
+
+pub fn try_change_admin<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    address: HumanAddr,
+) -> StdResult<HandleResponse> {
+    let mut state = config_read(&deps.storage).load()?;
+    enforce_admin(&state, &env)?;
+
+    state.pending_admin = Some(address.clone());
+    config(&mut deps.storage).save(&state)?;
+
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![log("action", "change_admin"), log("pending_admin", address)],
+        data: None,
+    })
+}
+
+pub fn try_accept_admin<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+) -> StdResult<HandleResponse> {
+    let mut state = config_read(&deps.storage).load()?;
+
+    match state.pending_admin {
+        Some(ref pending) if *pending == env.message.sender => {}
+        _ => return Err(StdError::unauthorized()),
+    }
+    state.admin = env.message.sender;
+    state.pending_admin = None;
+    config(&mut deps.storage).save(&state)?;
+
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![log("action", "accept_admin"), log("admin", &state.admin)],
+        data: None,
+    })
+}
+
+pub fn try_set_listing_mode<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    listing_mode: ListingMode,
+) -> StdResult<HandleResponse> {
+    let mut state = config_read(&deps.storage).load()?;
+    enforce_admin(&state, &env)?;
+
+    state.listing_mode = listing_mode;
+    config(&mut deps.storage).save(&state)?;
+
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![log("action", "set_listing_mode")],
+        data: None,
+    })
+}
+
+pub fn try_update_allow_list<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    add: Vec<AssetMeta>,
+    remove: Vec<AssetMeta>,
+) -> StdResult<HandleResponse> {
+    let state = config_read(&deps.storage).load()?;
+    enforce_admin(&state, &env)?;
+
+    let mut list = allow_list_read(&deps.storage).load()?;
+    update_token_list(&mut list, add, remove);
+    allow_list(&mut deps.storage).save(&list)?;
+
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![log("action", "update_allow_list")],
+        data: None,
+    })
+}
+
+pub fn try_update_deny_list<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    add: Vec<AssetMeta>,
+    remove: Vec<AssetMeta>,
+) -> StdResult<HandleResponse> {
+    let state = config_read(&deps.storage).load()?;
+    enforce_admin(&state, &env)?;
+
+    let mut list = deny_list_read(&deps.storage).load()?;
+    update_token_list(&mut list, add, remove);
+    deny_list(&mut deps.storage).save(&list)?;
+
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![log("action", "update_deny_list")],
+        data: None,
+    })
+}
+
+fn update_token_list(list: &mut Vec<AssetMeta>, add: Vec<AssetMeta>, remove: Vec<AssetMeta>) {
+    list.retain(|token| !remove.contains(token));
+    for token in add {
+        if !list.contains(&token) {
+            list.push(token);
+        }
+    }
+}
+
+fn enforce_listing_policy<S: Storage>(
+    storage: &S,
+    config: &State,
+    creator: &HumanAddr,
+    assets: &[AssetMeta],
+) -> StdResult<()> {
+    let denied = deny_list_read(storage).load()?;
+    if let Some(token) = assets.iter().find(|token| denied.contains(token)) {
+        return Err(StdError::generic_err(format!(
+            "Token {} cannot be listed",
+            token
+        )));
+    }
+
+    match config.listing_mode {
+        ListingMode::Open => Ok(()),
+        ListingMode::AdminOnly if *creator == config.admin => Ok(()),
+        ListingMode::AdminOnly => Err(StdError::unauthorized()),
+        ListingMode::AllowList => {
+            let allowed = allow_list_read(storage).load()?;
+            match assets.iter().find(|token| !allowed.contains(token)) {
+                Some(token) => Err(StdError::generic_err(format!(
+                    "Token {} is not on the allow-list",
+                    token
+                ))),
+                None => Ok(()),
+            }
+        }
+    }
+}
+
+fn pair_code<S: Storage>(storage: &S, version: &str) -> StdResult<PairCode> {
+    pair_codes_read(storage)
+        .load()?
+        .into_iter()
+        .find(|code| code.version == version)
+        .ok_or_else(|| StdError::not_found(format!("Pair version {}", version)))
+}
+
+/// Accepts `major.minor.patch` versions.
+fn validate_version(version: &str) -> StdResult<()> {
+    let parts: Vec<&str> = version.split('.').collect();
+    if parts.len() != 3 || parts.iter().any(|part| part.parse::<u32>().is_err()) {
+        return Err(StdError::generic_err(format!(
+            "Invalid pair version {}, expected major.minor.patch",
+            version
+        )));
+    }
+    Ok(())
+}
+
+fn find_pair<S: Storage, F: Fn(&Pair) -> bool>(storage: &S, predicate: F) -> StdResult<Pair> {
+    let pairs_store: ReadonlyPrefixedStorage<S> =
+        ReadonlyPrefixedStorage::multilevel(&[b"pairs"], storage);
+    let pairs_store = AppendStore::<Pair, _, _>::attach(&pairs_store)
+        .unwrap_or_else(|| Err(StdError::generic_err("No pairs created")))?;
+
+    for pair in pairs_store.iter().rev() {
+        let pair = pair?;
+        if predicate(&pair) {
+            return Ok(pair);
+        }
+    }
+    Err(StdError::not_found("Pair not found"))
+}
+
+fn register_fee_token(fee: &Option<Fee>, code_hash: String) -> StdResult<Vec<CosmosMsg>> {
+    match fee {
+        Some(Fee::Token {
+            contract_addr,
+            token_code_hash,
+            ..
+        }) => Ok(vec![snip20::register_receive_msg(
+            code_hash,
+            None,
+            BLOCK_SIZE,
+            token_code_hash.clone(),
+            contract_addr.clone(),
+        )?]),
+        _ => Ok(vec![]),
+    }
+}
+
+fn enforce_normal_status(state: &State) -> StdResult<()> {
+    if state.status != ContractStatus::Normal {
+        return Err(StdError::generic_err(format!(
+            "The factory is stopped ({:?})",
+            state.status
+        )));
+    }
+    Ok(())
+}
+
+fn enforce_admin(state: &State, env: &Env) -> StdResult<()> {
+    if state.admin != env.message.sender {
+        return Err(StdError::unauthorized());
+    }
+    Ok(())
+}
+
 pub fn query_config<S: Storage, A: Api, Q: Querier>(deps: &Extern<S, A, Q>) -> StdResult<State> {
     config_read(&deps.storage).load()
 }
-pub fn query_pair<S: Storage, A: Api, Q: Querier>(
+pub fn query_listing_policy<S: Storage, A: Api, Q: Querier>(
     deps: &Extern<S, A, Q>,
-    assets: [AssetMeta; 2],
-) -> StdResult<Pair> {
+) -> StdResult<ListingPolicyResponse> {
+    Ok(ListingPolicyResponse {
+        listing_mode: config_read(&deps.storage).load()?.listing_mode,
+        allow_list: allow_list_read(&deps.storage).load()?,
+        deny_list: deny_list_read(&deps.storage).load()?,
+    })
+}
+
+pub fn query_pairs_by_token<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    token: AssetMeta,
+) -> StdResult<Vec<PairInfo>> {
+    let key = token.to_raw(deps.api)?;
+    let indexes = pairs_by_token_read(&deps.storage)
+        .may_load(key.as_bytes())?
+        .unwrap_or_default();
+    if indexes.is_empty() {
+        return Ok(vec![]);
+    }
+
     let pairs_store: ReadonlyPrefixedStorage<S> =
         ReadonlyPrefixedStorage::multilevel(&[b"pairs"], &deps.storage);
     let pairs_store = AppendStore::<Pair, _, _>::attach(&pairs_store)
-        .unwrap_or_else(|| return Err(StdError::generic_err("No pairs created")))?;
-
-    let pair = pairs_store
-        .iter()
-        .rev()
-        .find(|x| x.as_ref().unwrap().assets == assets);
+        .unwrap_or_else(|| Err(StdError::generic_err("No pairs created")))?;
 
-    if let Some(pair) = pair {
-        Ok(pair?)
-    } else {
-        return Err(StdError::not_found("Pair not found"));
+    let codes = pair_codes_read(&deps.storage).load()?;
+    let mut pairs = vec![];
+    for index in indexes {
+        let pair = pairs_store.get_at(index)?;
+        let code_hash = codes
+            .iter()
+            .find(|code| code.version == pair.version)
+            .map(|code| code.code_hash.clone())
+            .unwrap_or_default();
+        pairs.push(PairInfo { pair, code_hash });
     }
+    Ok(pairs)
+}
+
+pub fn query_pair<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    assets: Vec<AssetMeta>,
+    version: Option<String>,
+) -> StdResult<Pair> {
+    find_pair(&deps.storage, |pair| {
+        pair.has_assets(&assets) && version.iter().all(|v| *v == pair.version)
+    })
 }
 
 #[cfg(test)]
@@ -119,45 +877,103 @@ mod tests {
     };
 
     use super::*;
+    use cosmwasm_std::coins;
+
+    fn init_msg() -> InitMsg {
+        InitMsg {
+            pair_code_id: 23123123,
+            pair_code_hash: "pair_code_hash".to_string(),
+            pair_version: "1.0.0".to_string(),
+            admin: None,
+            creation_fee: None,
+            treasury: None,
+            listing_mode: None,
+            padding_block_size: None,
+        }
+    }
+
+    fn assets_meta() -> Vec<AssetMeta> {
+        vec![
+            AssetMeta::Token {
+                contract_addr: HumanAddr::from("token_a"),
+                token_code_hash: "token_code_hash".to_string(),
+            },
+            AssetMeta::Token {
+                contract_addr: HumanAddr::from("token_b"),
+                token_code_hash: "token_code_hash".to_string(),
+            },
+        ]
+    }
 
     #[test]
     fn proper_initialization() {
         let mut deps = mock_dependencies(20, &[]);
 
-        let msg = InitMsg {
-            pair_code_id: 23123123,
-        };
+        let msg = init_msg();
         let env = mock_env("creator", &[]);
 
         let _res = init(&mut deps, env, msg).unwrap();
+
+        let res = query(&deps, QueryMsg::Config {}).unwrap();
+        assert_eq!(res.len() % DEFAULT_PADDING_BLOCK_SIZE as usize, 0);
     }
 
     #[test]
     fn create_pair() {
         let mut deps = mock_dependencies(20, &[]);
 
-        let msg = InitMsg {
-            pair_code_id: 23123123,
-        };
+        let msg = init_msg();
         let env = mock_env("creator", &[]);
 
         let _res = init(&mut deps, env.clone(), msg).unwrap();
-        let assets_meta = [
-            AssetMeta::Token {
-                contract_addr: HumanAddr::from(MOCK_CONTRACT_ADDR),
-            },
+        let duplicate = AssetMeta::Token {
+            contract_addr: HumanAddr::from(MOCK_CONTRACT_ADDR),
+            token_code_hash: "token_code_hash".to_string(),
+        };
+        let msg = HandleMsg::CreatePair {
+            assets_meta: vec![duplicate.clone(), duplicate.clone()],
+            version: None,
+            curve: None,
+            swap_mode: None,
+            padding: None,
+        };
+        assert!(handle(&mut deps, env.clone(), msg).is_err());
+
+        let assets_meta = vec![
+            duplicate,
             AssetMeta::Token {
-                contract_addr: HumanAddr::from(MOCK_CONTRACT_ADDR),
+                contract_addr: HumanAddr::from("token_b"),
+                token_code_hash: "token_code_hash".to_string(),
             },
         ];
         let msg = HandleMsg::CreatePair {
             assets_meta: assets_meta.clone(),
+            version: None,
+            curve: None,
+            swap_mode: None,
+            padding: None,
         };
+        let res = handle(
+            &mut deps,
+            mock_env("creator", &coins(1, "uscrt")),
+            msg.clone(),
+        );
+        assert_eq!(
+            res.unwrap_err(),
+            StdError::generic_err("Pair creation takes no funds")
+        );
 
-        let _res = handle(&mut deps, env, msg).unwrap();
+        let res = handle(&mut deps, env, msg).unwrap();
+        match &res.messages[0] {
+            CosmosMsg::Wasm(WasmMsg::Instantiate {
+                callback_code_hash, ..
+            }) => assert_eq!(callback_code_hash, "pair_code_hash"),
+            _ => panic!("expected pair instantiation"),
+        }
 
         let msg = QueryMsg::Pair {
             assets_meta: assets_meta.clone(),
+            version: None,
         };
 
         let res = query(&deps, msg).unwrap();
@@ -165,4 +981,442 @@ mod tests {
         println!("{:?}", value);
         assert_eq!(assets_meta[0], value.assets[0]);
     }
+
+    #[test]
+    fn admin_transfer() {
+        let mut deps = mock_dependencies(20, &[]);
+
+        let msg = init_msg();
+        let _res = init(&mut deps, mock_env("creator", &[]), msg).unwrap();
+
+        let msg = HandleMsg::UpdateConfig {
+            treasury: Some(HumanAddr::from("new_treasury")),
+            padding_block_size: None,
+            padding: None,
+        };
+        let res = handle(&mut deps, mock_env("anyone", &[]), msg);
+        assert_eq!(res.unwrap_err(), StdError::unauthorized());
+
+        let msg = HandleMsg::ChangeAdmin {
+            address: HumanAddr::from("new_admin"),
+            padding: None,
+        };
+        let _res = handle(&mut deps, mock_env("creator", &[]), msg).unwrap();
+
+        let res = handle(
+            &mut deps,
+            mock_env("anyone", &[]),
+            HandleMsg::AcceptAdmin { padding: None },
+        );
+        assert_eq!(res.unwrap_err(), StdError::unauthorized());
+
+        let _res = handle(
+            &mut deps,
+            mock_env("new_admin", &[]),
+            HandleMsg::AcceptAdmin { padding: None },
+        )
+        .unwrap();
+
+        let msg = HandleMsg::UpdateConfig {
+            treasury: Some(HumanAddr::from("new_treasury")),
+            padding_block_size: Some(64),
+            padding: None,
+        };
+        let _res = handle(&mut deps, mock_env("new_admin", &[]), msg).unwrap();
+
+        let state: State = from_binary(&query(&deps, QueryMsg::Config {}).unwrap()).unwrap();
+        assert_eq!(state.admin, HumanAddr::from("new_admin"));
+        assert_eq!(state.pending_admin, None);
+        assert_eq!(state.treasury, HumanAddr::from("new_treasury"));
+        assert_eq!(state.padding_block_size, 64);
+    }
+
+    #[test]
+    fn native_creation_fee() {
+        let mut deps = mock_dependencies(20, &[]);
+
+        let mut msg = init_msg();
+        msg.creation_fee = Some(Fee::Native {
+            amount: Uint128(100),
+        });
+        msg.treasury = Some(HumanAddr::from("treasury"));
+        let _res = init(&mut deps, mock_env("creator", &[]), msg).unwrap();
+
+        let msg = HandleMsg::CreatePair {
+            assets_meta: assets_meta(),
+            version: None,
+            curve: None,
+            swap_mode: None,
+            padding: None,
+        };
+        let res = handle(
+            &mut deps,
+            mock_env("anyone", &coins(99, "uscrt")),
+            msg.clone(),
+        );
+        assert!(res.is_err());
+        // Anything but the fee itself would be stuck in the factory.
+        let overpaid = mock_env("anyone", &coins(101, "uscrt"));
+        assert!(handle(&mut deps, overpaid, msg.clone()).is_err());
+        let mut funds = coins(100, "uscrt");
+        funds.extend(coins(1, "uatom"));
+        let res = handle(&mut deps, mock_env("anyone", &funds), msg.clone());
+        assert_eq!(
+            res.unwrap_err(),
+            StdError::generic_err("Pair creation fee is exactly 100uscrt")
+        );
+
+        let res = handle(&mut deps, mock_env("anyone", &coins(100, "uscrt")), msg).unwrap();
+        assert_eq!(res.messages.len(), 2);
+        assert_eq!(
+            res.messages[0],
+            CosmosMsg::Bank(BankMsg::Send {
+                from_address: HumanAddr::from(MOCK_CONTRACT_ADDR),
+                to_address: HumanAddr::from("treasury"),
+                amount: coins(100, "uscrt"),
+            })
+        );
+    }
+
+    #[test]
+    fn token_creation_fee() {
+        let mut deps = mock_dependencies(20, &[]);
+
+        let mut msg = init_msg();
+        msg.creation_fee = Some(Fee::Token {
+            contract_addr: HumanAddr::from("fee_token"),
+            token_code_hash: "fee_token_hash".to_string(),
+            amount: Uint128(100),
+        });
+        let res = init(&mut deps, mock_env("creator", &[]), msg).unwrap();
+        assert_eq!(res.messages.len(), 1);
+
+        let msg = HandleMsg::CreatePair {
+            assets_meta: assets_meta(),
+            version: None,
+            curve: None,
+            swap_mode: None,
+            padding: None,
+        };
+        let res = handle(&mut deps, mock_env("anyone", &[]), msg);
+        assert!(res.is_err());
+
+        let receive = |amount: u128| HandleMsg::Receive {
+            sender: HumanAddr::from("anyone"),
+            from: HumanAddr::from("anyone"),
+            amount: Uint128(amount),
+            msg: Some(
+                to_binary(&ReceiveMsg::CreatePair {
+                    assets_meta: assets_meta(),
+                    version: None,
+                    curve: None,
+                    swap_mode: None,
+                })
+                .unwrap(),
+            ),
+        };
+        let res = handle(&mut deps, mock_env("other_token", &[]), receive(100));
+        assert_eq!(res.unwrap_err(), StdError::unauthorized());
+
+        let res = handle(&mut deps, mock_env("fee_token", &[]), receive(99));
+        assert!(res.is_err());
+
+        let res = handle(&mut deps, mock_env("fee_token", &[]), receive(100)).unwrap();
+        assert_eq!(res.messages.len(), 2);
+    }
+
+    #[test]
+    fn listing_policy() {
+        let mut deps = mock_dependencies(20, &[]);
+
+        let _res = init(&mut deps, mock_env("creator", &[]), init_msg()).unwrap();
+        let (token_a, token_b) = (assets_meta()[0].clone(), assets_meta()[1].clone());
+        let msg = HandleMsg::CreatePair {
+            assets_meta: assets_meta(),
+            version: None,
+            curve: None,
+            swap_mode: None,
+            padding: None,
+        };
+
+        let deny = HandleMsg::UpdateDenyList {
+            add: vec![token_a.clone()],
+            remove: vec![],
+            padding: None,
+        };
+        let res = handle(&mut deps, mock_env("anyone", &[]), deny.clone());
+        assert_eq!(res.unwrap_err(), StdError::unauthorized());
+        let _res = handle(&mut deps, mock_env("creator", &[]), deny).unwrap();
+        let res = handle(&mut deps, mock_env("creator", &[]), msg.clone());
+        assert!(res.is_err());
+
+        let undeny = HandleMsg::UpdateDenyList {
+            add: vec![],
+            remove: vec![token_a.clone()],
+            padding: None,
+        };
+        let _res = handle(&mut deps, mock_env("creator", &[]), undeny).unwrap();
+
+        let mode = HandleMsg::SetListingMode {
+            listing_mode: ListingMode::AdminOnly,
+            padding: None,
+        };
+        let _res = handle(&mut deps, mock_env("creator", &[]), mode).unwrap();
+        let res = handle(&mut deps, mock_env("anyone", &[]), msg.clone());
+        assert_eq!(res.unwrap_err(), StdError::unauthorized());
+
+        let mode = HandleMsg::SetListingMode {
+            listing_mode: ListingMode::AllowList,
+            padding: None,
+        };
+        let _res = handle(&mut deps, mock_env("creator", &[]), mode).unwrap();
+        let allow = HandleMsg::UpdateAllowList {
+            add: vec![token_a.clone()],
+            remove: vec![],
+            padding: None,
+        };
+        let _res = handle(&mut deps, mock_env("creator", &[]), allow).unwrap();
+        let res = handle(&mut deps, mock_env("anyone", &[]), msg.clone());
+        assert!(res.is_err());
+
+        let allow = HandleMsg::UpdateAllowList {
+            add: vec![token_b.clone()],
+            remove: vec![],
+            padding: None,
+        };
+        let _res = handle(&mut deps, mock_env("creator", &[]), allow).unwrap();
+        let _res = handle(&mut deps, mock_env("anyone", &[]), msg).unwrap();
+
+        let res = query(&deps, QueryMsg::ListingPolicy {}).unwrap();
+        let policy: ListingPolicyResponse = from_binary(&res).unwrap();
+        assert_eq!(policy.listing_mode, ListingMode::AllowList);
+        assert_eq!(policy.allow_list, vec![token_a, token_b]);
+        assert!(policy.deny_list.is_empty());
+    }
+
+    #[test]
+    fn pair_versions_and_migration() {
+        let mut deps = mock_dependencies(20, &[]);
+
+        let _res = init(&mut deps, mock_env("creator", &[]), init_msg()).unwrap();
+
+        let create = HandleMsg::CreatePair {
+            assets_meta: assets_meta(),
+            version: None,
+            curve: None,
+            swap_mode: None,
+            padding: None,
+        };
+        let _res = handle(&mut deps, mock_env("anyone", &[]), create.clone()).unwrap();
+        let _res = handle(
+            &mut deps,
+            mock_env("pair_v1", &[]),
+            HandleMsg::RegisterPair {},
+        )
+        .unwrap();
+
+        let res = handle(
+            &mut deps,
+            mock_env("intruder", &[]),
+            HandleMsg::RegisterPair {},
+        );
+        assert_eq!(res.unwrap_err(), StdError::unauthorized());
+
+        let res = handle(&mut deps, mock_env("anyone", &[]), create.clone());
+        assert!(res.is_err());
+
+        let register = HandleMsg::RegisterPairCode {
+            code_id: 2,
+            code_hash: "pair_code_hash_v2".to_string(),
+            version: "2.0.0".to_string(),
+            padding: None,
+        };
+        let _res = handle(&mut deps, mock_env("creator", &[]), register).unwrap();
+
+        let res = handle(&mut deps, mock_env("anyone", &[]), create).unwrap();
+        match &res.messages[0] {
+            CosmosMsg::Wasm(WasmMsg::Instantiate {
+                code_id,
+                callback_code_hash,
+                ..
+            }) => {
+                assert_eq!(*code_id, 2);
+                assert_eq!(callback_code_hash, "pair_code_hash_v2");
+            }
+            _ => panic!("expected pair instantiation"),
+        }
+        let _res = handle(
+            &mut deps,
+            mock_env("pair_v2", &[]),
+            HandleMsg::RegisterPair {},
+        )
+        .unwrap();
+
+        let msg = QueryMsg::Pair {
+            assets_meta: assets_meta(),
+            version: Some("1.0.0".to_string()),
+        };
+        let pair: Pair = from_binary(&query(&deps, msg).unwrap()).unwrap();
+        assert_eq!(pair.contract_addr, HumanAddr::from("pair_v1"));
+
+        let msg = QueryMsg::Pair {
+            assets_meta: assets_meta(),
+            version: None,
+        };
+        let pair: Pair = from_binary(&query(&deps, msg).unwrap()).unwrap();
+        assert_eq!(pair.contract_addr, HumanAddr::from("pair_v2"));
+        assert_eq!(pair.version, "2.0.0");
+
+        let msg = QueryMsg::PairsByToken {
+            token: assets_meta()[1].clone(),
+        };
+        let pairs: Vec<PairInfo> = from_binary(&query(&deps, msg).unwrap()).unwrap();
+        assert_eq!(pairs.len(), 2);
+        assert_eq!(pairs[1].pair.contract_addr, HumanAddr::from("pair_v2"));
+        assert_eq!(pairs[1].code_hash, "pair_code_hash_v2");
+
+        let migrate = HandleMsg::MigrateLiquidity {
+            from_pair: HumanAddr::from("pair_v1"),
+            to_pair: HumanAddr::from("pair_v2"),
+            min_share: Some(Uint128(1_000)),
+            padding: None,
+        };
+        let res = handle(&mut deps, mock_env("lp", &[]), migrate).unwrap();
+        assert_eq!(
+            res.messages,
+            vec![WasmMsg::Execute {
+                contract_addr: HumanAddr::from("pair_v1"),
+                callback_code_hash: "pair_code_hash".to_string(),
+                msg: to_binary(&PairHandleMsg::MigrateLiquidity {
+                    owner: HumanAddr::from("lp"),
+                    new_pair: ContractLink {
+                        address: HumanAddr::from("pair_v2"),
+                        code_hash: "pair_code_hash_v2".to_string(),
+                    },
+                    min_share: Some(Uint128(1_000)),
+                })
+                .unwrap(),
+                send: vec![],
+            }
+            .into()]
+        );
+    }
+
+    #[test]
+    fn contract_status() {
+        let mut deps = mock_dependencies(20, &[]);
+
+        let _res = init(&mut deps, mock_env("creator", &[]), init_msg()).unwrap();
+        let create = HandleMsg::CreatePair {
+            assets_meta: assets_meta(),
+            version: None,
+            curve: None,
+            swap_mode: None,
+            padding: None,
+        };
+        let _res = handle(&mut deps, mock_env("anyone", &[]), create.clone()).unwrap();
+        let _res = handle(&mut deps, mock_env("pair", &[]), HandleMsg::RegisterPair {}).unwrap();
+
+        let set_status = |pair: Option<&str>, status| HandleMsg::SetStatus {
+            pair: pair.map(HumanAddr::from),
+            status,
+            padding: None,
+        };
+        let set_status_msg = |status| -> CosmosMsg {
+            WasmMsg::Execute {
+                contract_addr: HumanAddr::from("pair"),
+                callback_code_hash: "pair_code_hash".to_string(),
+                msg: to_binary(&PairHandleMsg::SetStatus { status }).unwrap(),
+                send: vec![],
+            }
+            .into()
+        };
+
+        let msg = set_status(Some("pair"), ContractStatus::StopTrading);
+        let res = handle(&mut deps, mock_env("anyone", &[]), msg.clone());
+        assert_eq!(res.unwrap_err(), StdError::unauthorized());
+        let res = handle(&mut deps, mock_env("creator", &[]), msg).unwrap();
+        assert_eq!(
+            res.messages,
+            vec![set_status_msg(ContractStatus::StopTrading)]
+        );
+        let state = query_config(&deps).unwrap();
+        assert_eq!(state.status, ContractStatus::Normal);
+
+        // Stopping everything reaches every pair and stops the factory itself.
+        let msg = set_status(None, ContractStatus::StopAll);
+        let res = handle(&mut deps, mock_env("creator", &[]), msg).unwrap();
+        assert_eq!(res.messages, vec![set_status_msg(ContractStatus::StopAll)]);
+        let state = query_config(&deps).unwrap();
+        assert_eq!(state.status, ContractStatus::StopAll);
+        let res = handle(&mut deps, mock_env("anyone", &[]), create.clone());
+        assert_eq!(
+            res.unwrap_err(),
+            StdError::generic_err("The factory is stopped (StopAll)")
+        );
+
+        let msg = set_status(None, ContractStatus::Normal);
+        let _res = handle(&mut deps, mock_env("creator", &[]), msg).unwrap();
+        let register = HandleMsg::RegisterPairCode {
+            code_id: 2,
+            code_hash: "pair_code_hash_v2".to_string(),
+            version: "2.0.0".to_string(),
+            padding: None,
+        };
+        let _res = handle(&mut deps, mock_env("creator", &[]), register).unwrap();
+        let _res = handle(&mut deps, mock_env("anyone", &[]), create).unwrap();
+    }
+
+    #[test]
+    fn stable_pair() {
+        let mut deps = mock_dependencies(20, &[]);
+
+        let _res = init(&mut deps, mock_env("creator", &[]), init_msg()).unwrap();
+
+        let create = HandleMsg::CreatePair {
+            assets_meta: assets_meta(),
+            version: None,
+            curve: Some(Curve::StableSwap { amp: 100 }),
+            swap_mode: None,
+            padding: None,
+        };
+        let res = handle(&mut deps, mock_env("anyone", &[]), create).unwrap();
+        match &res.messages[0] {
+            CosmosMsg::Wasm(WasmMsg::Instantiate { msg, .. }) => {
+                let msg: PairInitMsg = from_binary(msg).unwrap();
+                assert_eq!(msg.curve, Curve::StableSwap { amp: 100 });
+            }
+            _ => panic!("expected pair instantiation"),
+        }
+        let _res = handle(
+            &mut deps,
+            mock_env("stable_pair", &[]),
+            HandleMsg::RegisterPair {},
+        )
+        .unwrap();
+
+        let ramp = HandleMsg::RampAmplification {
+            pair: HumanAddr::from("stable_pair"),
+            future_amp: 200,
+            future_time: 1_700_000_000,
+            padding: None,
+        };
+        let res = handle(&mut deps, mock_env("anyone", &[]), ramp.clone());
+        assert_eq!(res.unwrap_err(), StdError::unauthorized());
+
+        let res = handle(&mut deps, mock_env("creator", &[]), ramp).unwrap();
+        assert_eq!(
+            res.messages,
+            vec![CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: HumanAddr::from("stable_pair"),
+                callback_code_hash: "pair_code_hash".to_string(),
+                msg: to_binary(&PairHandleMsg::RampAmplification {
+                    future_amp: 200,
+                    future_time: 1_700_000_000,
+                })
+                .unwrap(),
+                send: vec![],
+            })]
+        );
+    }
 }
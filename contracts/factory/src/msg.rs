@@ -1,22 +1,162 @@
-use common::pair::AssetMeta;
+use common::pair::{AssetMeta, ContractStatus, Curve, ReserveVisibility, SwapLimits, SwapMode};
+use cosmwasm_std::{Binary, HumanAddr, Uint128};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
+use crate::state::{Fee, ListingMode};
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub struct InitMsg {
     pub pair_code_id: u64,
+    pub pair_code_hash: String,
+    pub pair_version: String,
+    /// Defaults to the instantiator.
+    pub admin: Option<HumanAddr>,
+    pub creation_fee: Option<Fee>,
+    /// Defaults to the admin.
+    pub treasury: Option<HumanAddr>,
+    /// Defaults to `ListingMode::Open`.
+    pub listing_mode: Option<ListingMode>,
+    /// Defaults to `common::pair::DEFAULT_PADDING_BLOCK_SIZE`.
+    pub padding_block_size: Option<u32>,
 }
 
+/// Messages sent by users take an optional `padding`, which is ignored, to hide the length of
+/// the rest of the message.
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum HandleMsg {
-    CreatePair { assets_meta: [AssetMeta; 2] },
+    /// There is at most one pair of the same assets and version, whatever its curve or swap
+    /// mode, so that `QueryMsg::Pair` always finds a single pair.
+    CreatePair {
+        assets_meta: Vec<AssetMeta>,
+        /// Defaults to the current pair version.
+        version: Option<String>,
+        /// Defaults to `Curve::ConstantProduct`.
+        curve: Option<Curve>,
+        /// Defaults to `SwapMode::Instant`.
+        swap_mode: Option<SwapMode>,
+        padding: Option<String>,
+    },
+    Receive {
+        sender: HumanAddr,
+        from: HumanAddr,
+        amount: Uint128,
+        msg: Option<Binary>,
+    },
+    /// New pair code comes in through `RegisterPairCode`, as a new version, since pairs already
+    /// created keep running the code of theirs.
+    UpdateConfig {
+        treasury: Option<HumanAddr>,
+        /// Only applies to pairs created from now on.
+        padding_block_size: Option<u32>,
+        padding: Option<String>,
+    },
+    /// Adds a pair code version and makes it the default for new pairs. Registered versions
+    /// never change.
+    RegisterPairCode {
+        code_id: u64,
+        code_hash: String,
+        version: String,
+        padding: Option<String>,
+    },
+    RegisterPair {},
+    /// Moves all of the sender's liquidity from `from_pair` to `to_pair`, which must pool the
+    /// same assets. Fails if `to_pair` mints fewer than `min_share` liquidity tokens for it.
+    MigrateLiquidity {
+        from_pair: HumanAddr,
+        to_pair: HumanAddr,
+        min_share: Option<Uint128>,
+        padding: Option<String>,
+    },
+    /// Moves a StableSwap pair's amplification coefficient linearly to `future_amp` by
+    /// `future_time`.
+    RampAmplification {
+        pair: HumanAddr,
+        future_amp: u64,
+        future_time: u64,
+        padding: Option<String>,
+    },
+    /// Sets what `pair`'s public queries reveal of its reserves.
+    SetReserveVisibility {
+        pair: HumanAddr,
+        visibility: ReserveVisibility,
+        padding: Option<String>,
+    },
+    /// Caps the size and price impact of each swap against `pair`.
+    SetSwapLimits {
+        pair: HumanAddr,
+        limits: SwapLimits,
+        padding: Option<String>,
+    },
+    /// Sets `pair`'s status, or without a pair the factory's own and every pair's, overriding
+    /// any set on a single pair before.
+    SetStatus {
+        pair: Option<HumanAddr>,
+        status: ContractStatus,
+        padding: Option<String>,
+    },
+    SetCreationFee {
+        creation_fee: Option<Fee>,
+        padding: Option<String>,
+    },
+    SetListingMode {
+        listing_mode: ListingMode,
+        padding: Option<String>,
+    },
+    UpdateAllowList {
+        add: Vec<AssetMeta>,
+        remove: Vec<AssetMeta>,
+        padding: Option<String>,
+    },
+    UpdateDenyList {
+        add: Vec<AssetMeta>,
+        remove: Vec<AssetMeta>,
+        padding: Option<String>,
+    },
+    ChangeAdmin {
+        address: HumanAddr,
+        padding: Option<String>,
+    },
+    AcceptAdmin {
+        padding: Option<String>,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ReceiveMsg {
+    CreatePair {
+        assets_meta: Vec<AssetMeta>,
+        version: Option<String>,
+        curve: Option<Curve>,
+        swap_mode: Option<SwapMode>,
+    },
 }
 
+/// All public, so unlike a pair's the factory's queries take no viewing key or permit.
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum QueryMsg {
     Config {},
-    Pair { assets_meta: [AssetMeta; 2] },
+    /// Returns the most recent pair for `assets_meta`, or the one with `version`.
+    Pair {
+        assets_meta: Vec<AssetMeta>,
+        version: Option<String>,
+    },
+    /// Every registered pair that includes `token`, as `common::pair::PairInfo`s.
+    PairsByToken {
+        token: AssetMeta,
+    },
+    PairCodes {},
+    ListingPolicy {},
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct ListingPolicyResponse {
+    pub listing_mode: ListingMode,
+    pub allow_list: Vec<AssetMeta>,
+    pub deny_list: Vec<AssetMeta>,
 }
@@ -1,14 +1,72 @@
-use cosmwasm_std::Storage;
-use cosmwasm_storage::{singleton, singleton_read, ReadonlySingleton, Singleton};
+use common::pair::{AssetMeta, ContractStatus};
+use cosmwasm_std::{HumanAddr, Storage, Uint128};
+use cosmwasm_storage::{
+    bucket, bucket_read, singleton, singleton_read, Bucket, ReadonlyBucket, ReadonlySingleton,
+    Singleton,
+};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 const CONFIG_KEY: &[u8] = b"config";
+const ALLOW_LIST_KEY: &[u8] = b"allow_list";
+const DENY_LIST_KEY: &[u8] = b"deny_list";
+const PAIR_CODES_KEY: &[u8] = b"pair_codes";
+const PENDING_PAIR_KEY: &[u8] = b"pending_pair";
+const PREFIX_PAIRS_BY_TOKEN: &[u8] = b"pairs_by_token";
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub struct State {
+    pub admin: HumanAddr,
+    /// Set by `ChangeAdmin`, becomes `admin` once it calls `AcceptAdmin`.
+    pub pending_admin: Option<HumanAddr>,
     pub pair_code_id: u64,
+    pub pair_code_hash: String,
+    /// Version of `pair_code_id`, used for pairs created without an explicit version.
+    pub pair_version: String,
+    /// Charged on every `CreatePair` and forwarded to `treasury`.
+    pub creation_fee: Option<Fee>,
+    pub treasury: HumanAddr,
+    pub listing_mode: ListingMode,
+    /// Responses of the factory and the pairs it creates are padded to a multiple of this many
+    /// bytes.
+    pub padding_block_size: u32,
+    /// Set by `SetStatus` without a pair. Anything but `Normal` stops pair creation and liquidity
+    /// migration, and was last applied to every pair.
+    pub status: ContractStatus,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ListingMode {
+    /// Anyone can create a pair.
+    Open,
+    /// Only the admin can create pairs.
+    AdminOnly,
+    /// Anyone can create a pair, as long as both tokens are on the allow-list.
+    AllowList,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct PairCode {
+    pub code_id: u64,
+    pub code_hash: String,
+    /// Semantic version, e.g. `1.2.0`.
+    pub version: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Fee {
+    /// Paid in `uscrt` through `sent_funds`, which must hold exactly the fee and nothing else.
+    Native { amount: Uint128 },
+    /// Paid by sending the SNIP-20 to the factory with a `create_pair` receive msg.
+    Token {
+        contract_addr: HumanAddr,
+        token_code_hash: String,
+        amount: Uint128,
+    },
 }
 
 pub fn config<S: Storage>(storage: &mut S) -> Singleton<S, State> {
@@ -18,3 +76,49 @@ pub fn config<S: Storage>(storage: &mut S) -> Singleton<S, State> {
 pub fn config_read<S: Storage>(storage: &S) -> ReadonlySingleton<S, State> {
     singleton_read(storage, CONFIG_KEY)
 }
+
+/// Tokens that can be paired in `ListingMode::AllowList`.
+pub fn allow_list<S: Storage>(storage: &mut S) -> Singleton<S, Vec<AssetMeta>> {
+    singleton(storage, ALLOW_LIST_KEY)
+}
+
+pub fn allow_list_read<S: Storage>(storage: &S) -> ReadonlySingleton<S, Vec<AssetMeta>> {
+    singleton_read(storage, ALLOW_LIST_KEY)
+}
+
+/// Tokens that can never be paired, whatever the listing mode.
+pub fn deny_list<S: Storage>(storage: &mut S) -> Singleton<S, Vec<AssetMeta>> {
+    singleton(storage, DENY_LIST_KEY)
+}
+
+pub fn deny_list_read<S: Storage>(storage: &S) -> ReadonlySingleton<S, Vec<AssetMeta>> {
+    singleton_read(storage, DENY_LIST_KEY)
+}
+
+/// Every pair code version the factory can instantiate.
+pub fn pair_codes<S: Storage>(storage: &mut S) -> Singleton<S, Vec<PairCode>> {
+    singleton(storage, PAIR_CODES_KEY)
+}
+
+pub fn pair_codes_read<S: Storage>(storage: &S) -> ReadonlySingleton<S, Vec<PairCode>> {
+    singleton_read(storage, PAIR_CODES_KEY)
+}
+
+/// Index of the pair being instantiated, until it calls back with `RegisterPair`.
+pub fn pending_pair<S: Storage>(storage: &mut S) -> Singleton<S, u32> {
+    singleton(storage, PENDING_PAIR_KEY)
+}
+
+pub fn pending_pair_read<S: Storage>(storage: &S) -> ReadonlySingleton<S, u32> {
+    singleton_read(storage, PENDING_PAIR_KEY)
+}
+
+/// Indexes into the `pairs` store of every registered pair of a token, keyed by the token's
+/// canonical address.
+pub fn pairs_by_token<S: Storage>(storage: &mut S) -> Bucket<S, Vec<u32>> {
+    bucket(PREFIX_PAIRS_BY_TOKEN, storage)
+}
+
+pub fn pairs_by_token_read<S: Storage>(storage: &S) -> ReadonlyBucket<S, Vec<u32>> {
+    bucket_read(PREFIX_PAIRS_BY_TOKEN, storage)
+}
@@ -0,0 +1,462 @@
+use cosmwasm_std::{StdError, StdResult, Storage, Uint128};
+
+use crate::{
+    math::{mul_div, mul_div_up, FEE_DENOMINATOR, FEE_NUMERATOR},
+    state::{ticks, ticks_read, ConcentratedPool, RangePosition, Tick},
+};
+
+/// Each tick is a 0.01% step in price. The range keeps squared prices within the Q64.64
+/// price accumulators.
+pub const MIN_TICK: i32 = -400_000;
+pub const MAX_TICK: i32 = 400_000;
+pub const MAX_TICK_SPACING: u32 = 16_384;
+
+/// One in Q64.64.
+pub const Q64: u128 = 1 << 64;
+
+/// `1 / sqrt(1.0001)^(2^i)` in Q64.64, for each bit `i` of a tick.
+const SQRT_RATIO_FACTORS: [u128; 19] = [
+    0xfffcb933bd6fad37,
+    0xfff97272373d4132,
+    0xfff2e50f5f656932,
+    0xffe5caca7e10e4e6,
+    0xffcb9843d60f6159,
+    0xff973b41fa98c081,
+    0xff2ea16466c96a38,
+    0xfe5dee046a99a2a8,
+    0xfcbe86c7900a88ae,
+    0xf987a7253ac41317,
+    0xf3392b0822b70005,
+    0xe7159475a2c29b74,
+    0xd097f3bdfd2022b8,
+    0xa9f746462d870fdf,
+    0x70d869a156d2a1b8,
+    0x31be135f97d08fd9,
+    0x9aa508b5b7a84e1,
+    0x5d6af8dedb8119,
+    0x2216e584f5fa,
+];
+
+/// `sqrt(1.0001^tick)` in Q64.64.
+pub fn sqrt_price_at_tick(tick: i32) -> StdResult<u128> {
+    if !(MIN_TICK..=MAX_TICK).contains(&tick) {
+        return Err(StdError::generic_err(format!(
+            "Ticks are between {} and {}",
+            MIN_TICK, MAX_TICK
+        )));
+    }
+
+    let abs = tick.unsigned_abs();
+    let mut ratio = Q64;
+    for (bit, factor) in SQRT_RATIO_FACTORS.iter().enumerate() {
+        if abs & (1 << bit) != 0 {
+            ratio = mul_div(ratio, *factor, Q64)?;
+        }
+    }
+    if tick > 0 {
+        return mul_div(Q64, Q64, ratio);
+    }
+    Ok(ratio)
+}
+
+/// The highest tick whose square root price is at most `sqrt_price`.
+pub fn tick_at_sqrt_price(sqrt_price: u128) -> StdResult<i32> {
+    if sqrt_price < sqrt_price_at_tick(MIN_TICK)? {
+        return Err(StdError::generic_err("Price out of range"));
+    }
+
+    let (mut low, mut high) = (MIN_TICK, MAX_TICK);
+    while low < high {
+        let mid = low + (high - low + 1) / 2;
+        if sqrt_price_at_tick(mid)? <= sqrt_price {
+            low = mid;
+        } else {
+            high = mid - 1;
+        }
+    }
+    Ok(low)
+}
+
+/// Amount of the first asset `liquidity` is worth between two square root prices.
+pub fn amount_0_delta(
+    sqrt_a: u128,
+    sqrt_b: u128,
+    liquidity: u128,
+    round_up: bool,
+) -> StdResult<u128> {
+    let (lower, upper) = (sqrt_a.min(sqrt_b), sqrt_a.max(sqrt_b));
+    let scaled = rounded(liquidity, Q64, lower, round_up)?;
+    rounded(scaled, upper - lower, upper, round_up)
+}
+
+/// Amount of the second asset `liquidity` is worth between two square root prices.
+pub fn amount_1_delta(
+    sqrt_a: u128,
+    sqrt_b: u128,
+    liquidity: u128,
+    round_up: bool,
+) -> StdResult<u128> {
+    let (lower, upper) = (sqrt_a.min(sqrt_b), sqrt_a.max(sqrt_b));
+    rounded(liquidity, upper - lower, Q64, round_up)
+}
+
+/// The most liquidity between `lower_tick` and `upper_tick` that `amounts` pay for at the
+/// pool's price.
+pub fn liquidity_for_amounts(
+    pool: &ConcentratedPool,
+    lower_tick: i32,
+    upper_tick: i32,
+    amounts: &[Uint128],
+) -> StdResult<u128> {
+    let sqrt_lower = sqrt_price_at_tick(lower_tick)?;
+    let sqrt_upper = sqrt_price_at_tick(upper_tick)?;
+    let sqrt_price = pool.sqrt_price.u128();
+    // One unit less than given, so that rounding the deposits up keeps them within `amounts`.
+    let (amount_0, amount_1) = (
+        amounts[0].u128().saturating_sub(1),
+        amounts[1].u128().saturating_sub(1),
+    );
+
+    let liquidity_0 = |lower: u128| {
+        mul_div(
+            mul_div(amount_0, lower, Q64)?,
+            sqrt_upper,
+            sqrt_upper - lower,
+        )
+    };
+    let liquidity_1 = |upper: u128| mul_div(amount_1, Q64, upper - sqrt_lower);
+    if sqrt_price <= sqrt_lower {
+        liquidity_0(sqrt_lower)
+    } else if sqrt_price < sqrt_upper {
+        Ok(liquidity_0(sqrt_price)?.min(liquidity_1(sqrt_price)?))
+    } else {
+        liquidity_1(sqrt_upper)
+    }
+}
+
+/// Amounts of each asset `liquidity` between `lower_tick` and `upper_tick` is worth at the
+/// pool's price.
+pub fn amounts_for_liquidity(
+    pool: &ConcentratedPool,
+    lower_tick: i32,
+    upper_tick: i32,
+    liquidity: u128,
+    round_up: bool,
+) -> StdResult<Vec<Uint128>> {
+    let sqrt_lower = sqrt_price_at_tick(lower_tick)?;
+    let sqrt_upper = sqrt_price_at_tick(upper_tick)?;
+    let sqrt_price = pool.sqrt_price.u128();
+
+    let (amount_0, amount_1) = if pool.tick < lower_tick {
+        (
+            amount_0_delta(sqrt_lower, sqrt_upper, liquidity, round_up)?,
+            0,
+        )
+    } else if pool.tick < upper_tick {
+        (
+            amount_0_delta(sqrt_price, sqrt_upper, liquidity, round_up)?,
+            amount_1_delta(sqrt_lower, sqrt_price, liquidity, round_up)?,
+        )
+    } else {
+        (
+            0,
+            amount_1_delta(sqrt_lower, sqrt_upper, liquidity, round_up)?,
+        )
+    };
+    Ok(vec![Uint128(amount_0), Uint128(amount_1)])
+}
+
+/// Swaps `offer_amount` of the first asset if `zero_for_one`, else of the second, moving the
+/// price of `pool` across as many ticks as it takes. Returns the amount of the other asset
+/// and the ticks crossed, which the caller saves along with `pool` to commit the swap.
+pub fn swap<S: Storage>(
+    storage: &S,
+    pool: &mut ConcentratedPool,
+    zero_for_one: bool,
+    offer_amount: u128,
+) -> StdResult<(u128, Vec<(i32, Tick)>)> {
+    let (limit_tick, fee_index) = if zero_for_one {
+        (MIN_TICK, 0)
+    } else {
+        (MAX_TICK, 1)
+    };
+    let limit = sqrt_price_at_tick(limit_tick)?;
+
+    let mut remaining = offer_amount;
+    let mut return_amount = 0u128;
+    let mut crossed = vec![];
+    while remaining > 0 && pool.sqrt_price.u128() != limit {
+        let initialized = if zero_for_one {
+            pool.initialized_ticks
+                .iter()
+                .rev()
+                .find(|tick| **tick <= pool.tick)
+        } else {
+            pool.initialized_ticks
+                .iter()
+                .find(|tick| **tick > pool.tick)
+        }
+        .copied();
+        let next_tick = initialized.unwrap_or(limit_tick);
+        let target = sqrt_price_at_tick(next_tick)?;
+
+        let step = swap_step(
+            pool.sqrt_price.u128(),
+            target,
+            pool.liquidity.u128(),
+            remaining,
+        )?;
+        remaining -= step.amount_in + step.fee;
+        return_amount += step.amount_out;
+        if !pool.liquidity.is_zero() {
+            let growth = mul_div(step.fee, Q64, pool.liquidity.u128())?;
+            pool.fee_growth_global[fee_index] = Uint128(
+                pool.fee_growth_global[fee_index]
+                    .u128()
+                    .wrapping_add(growth),
+            );
+        }
+        pool.sqrt_price = Uint128(step.sqrt_price);
+
+        if step.sqrt_price != target {
+            pool.tick = tick_at_sqrt_price(step.sqrt_price)?;
+            continue;
+        }
+        if let Some(tick) = initialized {
+            let mut data = ticks_read(storage).load(&tick.to_be_bytes())?;
+            for (outside, global) in data
+                .fee_growth_outside
+                .iter_mut()
+                .zip(pool.fee_growth_global.iter())
+            {
+                *outside = Uint128(global.u128().wrapping_sub(outside.u128()));
+            }
+            let (entering, leaving) = if zero_for_one {
+                (data.liquidity_removed, data.liquidity_added)
+            } else {
+                (data.liquidity_added, data.liquidity_removed)
+            };
+            pool.liquidity = ((pool.liquidity + entering) - leaving)?;
+            crossed.push((tick, data));
+        }
+        pool.tick = if zero_for_one {
+            next_tick - 1
+        } else {
+            next_tick
+        };
+    }
+
+    if remaining > 0 {
+        return Err(StdError::generic_err("Not enough liquidity for this swap"));
+    }
+    Ok((return_amount, crossed))
+}
+
+/// Adds `liquidity` to `position`, or removes it, crediting the position with the fees its
+/// liquidity earned so far. Returns the amounts of each asset `liquidity` is worth, rounded in
+/// the pool's favour.
+pub fn update_position<S: Storage>(
+    storage: &mut S,
+    pool: &mut ConcentratedPool,
+    position: &mut RangePosition,
+    liquidity: u128,
+    add: bool,
+) -> StdResult<Vec<Uint128>> {
+    let lower = update_tick(storage, pool, position.lower_tick, liquidity, add, true)?;
+    let upper = update_tick(storage, pool, position.upper_tick, liquidity, add, false)?;
+
+    let inside = fee_growth_inside(pool, position, &lower, &upper);
+    position.fees_owed = fees_owed(position, &inside)?;
+    position.fee_growth_inside_last = inside;
+
+    let active = position.lower_tick <= pool.tick && pool.tick < position.upper_tick;
+    if add {
+        position.liquidity += Uint128(liquidity);
+        if active {
+            pool.liquidity += Uint128(liquidity);
+        }
+    } else {
+        position.liquidity = (position.liquidity - Uint128(liquidity))?;
+        if active {
+            pool.liquidity = (pool.liquidity - Uint128(liquidity))?;
+        }
+    }
+
+    amounts_for_liquidity(
+        pool,
+        position.lower_tick,
+        position.upper_tick,
+        liquidity,
+        add,
+    )
+}
+
+/// Fees `position` could collect now, including those earned since its last update.
+pub fn pending_fees<S: Storage>(
+    storage: &S,
+    pool: &ConcentratedPool,
+    position: &RangePosition,
+) -> StdResult<Vec<Uint128>> {
+    let store = ticks_read(storage);
+    let lower = store.load(&position.lower_tick.to_be_bytes())?;
+    let upper = store.load(&position.upper_tick.to_be_bytes())?;
+
+    fees_owed(position, &fee_growth_inside(pool, position, &lower, &upper))
+}
+
+fn fees_owed(position: &RangePosition, fee_growth_inside: &[Uint128]) -> StdResult<Vec<Uint128>> {
+    let mut fees = position.fees_owed.clone();
+    for i in 0..2 {
+        let growth = fee_growth_inside[i]
+            .u128()
+            .wrapping_sub(position.fee_growth_inside_last[i].u128());
+        fees[i] += Uint128(mul_div(growth, position.liquidity.u128(), Q64)?);
+    }
+    Ok(fees)
+}
+
+/// Fee growth between the ticks of `position`, from the growth outside of them.
+fn fee_growth_inside(
+    pool: &ConcentratedPool,
+    position: &RangePosition,
+    lower: &Tick,
+    upper: &Tick,
+) -> Vec<Uint128> {
+    (0..2)
+        .map(|i| {
+            let global = pool.fee_growth_global[i].u128();
+            let below = if pool.tick >= position.lower_tick {
+                lower.fee_growth_outside[i].u128()
+            } else {
+                global.wrapping_sub(lower.fee_growth_outside[i].u128())
+            };
+            let above = if pool.tick < position.upper_tick {
+                upper.fee_growth_outside[i].u128()
+            } else {
+                global.wrapping_sub(upper.fee_growth_outside[i].u128())
+            };
+            Uint128(global.wrapping_sub(below).wrapping_sub(above))
+        })
+        .collect()
+}
+
+/// Adds or removes `liquidity` bounded by `tick` from below if `lower`, else from above,
+/// initializing the tick on first use and clearing it once nothing uses it.
+fn update_tick<S: Storage>(
+    storage: &mut S,
+    pool: &mut ConcentratedPool,
+    tick: i32,
+    liquidity: u128,
+    add: bool,
+    lower: bool,
+) -> StdResult<Tick> {
+    let key = tick.to_be_bytes();
+    let mut data = match ticks_read(storage).may_load(&key)? {
+        Some(data) => data,
+        None => {
+            // All growth so far is taken to have happened below the tick.
+            let fee_growth_outside = if tick <= pool.tick {
+                pool.fee_growth_global.clone()
+            } else {
+                vec![Uint128::zero(); 2]
+            };
+            if let Err(index) = pool.initialized_ticks.binary_search(&tick) {
+                pool.initialized_ticks.insert(index, tick);
+            }
+            Tick {
+                fee_growth_outside,
+                ..Tick::default()
+            }
+        }
+    };
+
+    let side = if lower {
+        &mut data.liquidity_added
+    } else {
+        &mut data.liquidity_removed
+    };
+    *side = if add {
+        *side + Uint128(liquidity)
+    } else {
+        (*side - Uint128(liquidity))?
+    };
+
+    if data.liquidity_added.is_zero() && data.liquidity_removed.is_zero() {
+        ticks(storage).remove(&key);
+        pool.initialized_ticks
+            .retain(|initialized| *initialized != tick);
+    } else {
+        ticks(storage).save(&key, &data)?;
+    }
+    Ok(data)
+}
+
+struct SwapStep {
+    sqrt_price: u128,
+    amount_in: u128,
+    amount_out: u128,
+    fee: u128,
+}
+
+/// Swaps up to `remaining`, fee included, within one range of constant `liquidity`, moving
+/// the price towards `target` but not past it.
+fn swap_step(
+    sqrt_price: u128,
+    target: u128,
+    liquidity: u128,
+    remaining: u128,
+) -> StdResult<SwapStep> {
+    let zero_for_one = sqrt_price >= target;
+    let remaining_less_fee = mul_div(remaining, FEE_DENOMINATOR - FEE_NUMERATOR, FEE_DENOMINATOR)?;
+
+    let to_target = if zero_for_one {
+        amount_0_delta(target, sqrt_price, liquidity, true)?
+    } else {
+        amount_1_delta(sqrt_price, target, liquidity, true)?
+    };
+    let (next, amount_in) = if remaining_less_fee >= to_target {
+        (target, to_target)
+    } else {
+        // Rounded so that the price moves less, against the trader.
+        let next = if zero_for_one {
+            let denominator = liquidity + mul_div(remaining_less_fee, sqrt_price, Q64)?;
+            mul_div_up(liquidity, sqrt_price, denominator)?
+        } else {
+            sqrt_price + mul_div(remaining_less_fee, Q64, liquidity)?
+        };
+        let amount_in = if zero_for_one {
+            amount_0_delta(next, sqrt_price, liquidity, true)?
+        } else {
+            amount_1_delta(sqrt_price, next, liquidity, true)?
+        };
+        (next, amount_in.min(remaining_less_fee))
+    };
+
+    let amount_out = if zero_for_one {
+        amount_1_delta(next, sqrt_price, liquidity, false)?
+    } else {
+        amount_0_delta(sqrt_price, next, liquidity, false)?
+    };
+    // Whatever is left of `remaining` when the step ends short of `target` is fee.
+    let fee = if next == target {
+        mul_div_up(amount_in, FEE_NUMERATOR, FEE_DENOMINATOR - FEE_NUMERATOR)?
+            .min(remaining - amount_in)
+    } else {
+        remaining - amount_in
+    };
+
+    Ok(SwapStep {
+        sqrt_price: next,
+        amount_in,
+        amount_out,
+        fee,
+    })
+}
+
+fn rounded(a: u128, b: u128, c: u128, round_up: bool) -> StdResult<u128> {
+    if round_up {
+        mul_div_up(a, b, c)
+    } else {
+        mul_div(a, b, c)
+    }
+}
@@ -1,97 +1,4970 @@
-use common::pair::{AssetMeta, Pair, PairInitMsg as InitMsg};
+use common::pair::{
+    validate_assets, validate_padding_block_size, Asset, AssetMeta, ContractLink, ContractStatus,
+    Curve, FactoryHandleMsg, FlashSwapReceiverMsg, Pair, PairInitMsg as InitMsg, ReserveVisibility,
+    SimulationResponse, SwapLimits, SwapMode, DEFAULT_PADDING_BLOCK_SIZE, MAX_BPS,
+};
 use cosmwasm_std::{
-    to_binary, Api, Binary, Env, Extern, HandleResponse, HumanAddr, InitResponse, Querier,
-    StdError, StdResult, Storage,
+    from_binary, log, to_binary, Api, Binary, BlockInfo, CanonicalAddr, CosmosMsg, Env, Extern,
+    HandleResponse, HumanAddr, InitResponse, Querier, StdError, StdResult, Storage, Uint128,
+    WasmMsg,
+};
+use cosmwasm_storage::{PrefixedStorage, ReadonlyPrefixedStorage};
+use secret_toolkit::{
+    permit::{self, Permission, Permit, RevokedPermits},
+    snip20,
+    storage::{AppendStore, AppendStoreMut},
+    utils::{pad_handle_result, pad_query_result},
 };
 
 use crate::{
-    msg::{HandleMsg, QueryMsg},
-    state::{config, config_read},
+    concentrated,
+    math::{
+        accumulate_price, compute_share, compute_swap, compute_withdrawal, flash_swap_repaid, mul,
+        mul_div, mul_div_up, FEE_DENOMINATOR, FEE_NUMERATOR, PRICE_PRECISION,
+    },
+    msg::{
+        BatchResponse, DepthBucket, HandleAnswer, HandleMsg, HistoryResponse, OrderBookResponse,
+        OrderResponse, OrdersResponse, PoolResponse, PositionResponse, PricesResponse, QueryMsg,
+        QueryWithPermit, RangePositionResponse, ReceiveMsg, TwapResponse,
+    },
+    stable,
+    state::{
+        balances, balances_read, batch, batch_read, concentrated, concentrated_read, config,
+        config_read, curve, curve_read, delayed_reserves, delayed_reserves_read, factory,
+        factory_read, fee_growth, fee_growth_read, flash_swap, flash_swap_read, lp_fees,
+        lp_fees_read, observations, observations_read, order_book, order_book_read, orders,
+        orders_read, owner_orders, owner_orders_read, padding_block_size, padding_block_size_read,
+        positions, positions_read, prices, prices_read, prng_seed, prng_seed_read,
+        reserve_visibility, reserve_visibility_read, reserves, reserves_read, share_supply,
+        share_supply_read, shares, shares_read, status, status_read, swap_limits, swap_limits_read,
+        swap_mode, swap_mode_read, ticks, viewing_keys, viewing_keys_read, Amplification, Batch,
+        ConcentratedPool, CurveState, DelayedReserves, FlashSwap, HistoryAction, HistoryEntry,
+        LimitOrder, LpFees, Observation, OpenOrder, OrderBook, Prices, RangePosition,
+        ReserveSnapshot, Reserves, SwapRequest, OBSERVATION_CAPACITY, PREFIX_HISTORY,
+        PREFIX_REVOKED_PERMITS,
+    },
+    viewing_key, weighted,
 };
 
+pub const BLOCK_SIZE: usize = 256;
+/// Bounds the order book every swap loads.
+pub const MAX_OPEN_ORDERS: usize = 256;
+/// Smallest limit order, in basis points of the pool's reserve of the offer asset, so that
+/// filling the order book ties up a share of the pool rather than dust.
+pub const MIN_ORDER_BPS: u16 = 1;
+/// Most limit orders a swap fills, leaving the rest to `HandleMsg::FillOrder`.
+pub const MAX_FILLS_PER_SWAP: usize = 8;
+/// Leading digits of their price the public order book groups orders by.
+pub const DEPTH_PRICE_DIGITS: u8 = 2;
+/// Leading digits of the amounts the public order book shows.
+pub const DEPTH_AMOUNT_DIGITS: u8 = 2;
+/// Fewest orders a price bucket of the public order book needs to be shown.
+pub const MIN_DEPTH_ORDERS: usize = 2;
+/// Bisection steps when looking for the part of an order that fills.
+const FILL_SEARCH_STEPS: u32 = 32;
+/// Bounds the work of settling a batch auction.
+pub const MAX_BATCH_SWAPS: usize = 64;
+/// Smallest batched swap, in basis points of the pool's reserve of the offer asset, so that a
+/// batch can't be filled with dust.
+pub const MIN_BATCH_SWAP_BPS: u16 = 1;
+/// Most history entries a query returns.
+pub const MAX_PAGE_SIZE: u32 = 100;
+/// Bounds the reserves a `ReserveVisibility::Delayed` pair keeps until they can be shown.
+pub const MAX_RESERVE_DELAY_BLOCKS: u64 = 100;
+/// Digits of the largest `Uint128`, less one.
+const MAX_SIGNIFICANT_DIGITS: u8 = 38;
+
 pub fn init<S: Storage, A: Api, Q: Querier>(
     deps: &mut Extern<S, A, Q>,
     env: Env,
     msg: InitMsg,
 ) -> StdResult<InitResponse> {
-    let pair = Pair::new(&msg.assets_meta, env.contract.address, deps.api);
+    validate_assets(&msg.assets_meta)?;
+    let block_size = msg.padding_block_size.unwrap_or(DEFAULT_PADDING_BLOCK_SIZE);
+    validate_padding_block_size(block_size)?;
+    padding_block_size(&mut deps.storage).save(&block_size)?;
+    prng_seed(&mut deps.storage).save(&viewing_key::initial_seed(&env))?;
+    let pair = Pair::new(
+        &msg.assets_meta,
+        msg.version,
+        env.contract.address,
+        deps.api,
+    );
+
+    let curve_state = match msg.curve {
+        Curve::ConstantProduct => {
+            if pair.assets.len() != 2 {
+                return Err(StdError::generic_err(
+                    "Constant product pools hold exactly two assets",
+                ));
+            }
+            CurveState::ConstantProduct
+        }
+        Curve::StableSwap { amp } => {
+            validate_amp(amp)?;
+            let mut decimals = vec![];
+            for asset in pair.assets.iter() {
+                let (contract_addr, token_code_hash) = token_info(asset);
+                decimals.push(
+                    snip20::token_info_query(
+                        &deps.querier,
+                        BLOCK_SIZE,
+                        token_code_hash,
+                        contract_addr,
+                    )?
+                    .decimals,
+                );
+            }
+            CurveState::StableSwap {
+                decimals,
+                amp: Amplification {
+                    initial: amp,
+                    future: amp,
+                    initial_time: env.block.time,
+                    future_time: env.block.time,
+                },
+            }
+        }
+        Curve::Weighted { weights } => {
+            if weights.len() != pair.assets.len() {
+                return Err(StdError::generic_err("Expected one weight per asset"));
+            }
+            let total = weights
+                .iter()
+                .try_fold(0u64, |total, weight| total.checked_add(*weight));
+            if total != Some(weighted::TOTAL_WEIGHT)
+                || weights.iter().any(|weight| *weight < weighted::MIN_WEIGHT)
+            {
+                return Err(StdError::generic_err(format!(
+                    "Weights must add up to {} and each be at least {}",
+                    weighted::TOTAL_WEIGHT,
+                    weighted::MIN_WEIGHT
+                )));
+            }
+            CurveState::Weighted { weights }
+        }
+        Curve::Concentrated {
+            tick_spacing,
+            initial_tick,
+        } => {
+            if pair.assets.len() != 2 {
+                return Err(StdError::generic_err(
+                    "Concentrated liquidity pools hold exactly two assets",
+                ));
+            }
+            if tick_spacing == 0 || tick_spacing > concentrated::MAX_TICK_SPACING {
+                return Err(StdError::generic_err(format!(
+                    "Tick spacing must be between 1 and {}",
+                    concentrated::MAX_TICK_SPACING
+                )));
+            }
+            concentrated(&mut deps.storage).save(&ConcentratedPool {
+                sqrt_price: Uint128(concentrated::sqrt_price_at_tick(initial_tick)?),
+                tick: initial_tick,
+                liquidity: Uint128::zero(),
+                fee_growth_global: vec![Uint128::zero(); 2],
+                initialized_ticks: vec![],
+                position_count: 0,
+            })?;
+            CurveState::Concentrated { tick_spacing }
+        }
+    };
+
+    if let SwapMode::BatchAuction { epoch_blocks } = msg.swap_mode {
+        if epoch_blocks == 0 {
+            return Err(StdError::generic_err(
+                "Batch auction epochs must be at least one block",
+            ));
+        }
+        if pair.assets.len() != 2 {
+            return Err(StdError::generic_err(
+                "Batch auction pools hold exactly two assets",
+            ));
+        }
+    }
 
     config(&mut deps.storage).save(&pair)?;
+    factory(&mut deps.storage).save(&msg.factory)?;
+    swap_mode(&mut deps.storage).save(&msg.swap_mode)?;
+    reserve_visibility(&mut deps.storage).save(&ReserveVisibility::Exact)?;
+    status(&mut deps.storage).save(&ContractStatus::Normal)?;
+    swap_limits(&mut deps.storage).save(&SwapLimits::default())?;
+    curve(&mut deps.storage).save(&curve_state)?;
+    reserves(&mut deps.storage).save(&Reserves {
+        amounts: vec![Uint128::zero(); pair.assets.len()],
+        total_share: Uint128::zero(),
+    })?;
+    let mut state = Prices {
+        price_cumulative: vec![Uint128::zero(); pair.assets.len()],
+        last_update: env.block.time,
+        observation_count: 0,
+    };
+    record_observation(&mut deps.storage, &mut state)?;
+    prices(&mut deps.storage).save(&state)?;
+    order_book(&mut deps.storage).save(&OrderBook::default())?;
+    fee_growth(&mut deps.storage).save(&vec![Uint128::zero(); pair.assets.len()])?;
+    balances(&mut deps.storage).save(&vec![Uint128::zero(); pair.assets.len()])?;
+    share_supply(&mut deps.storage).save(&Uint128::zero())?;
 
-    Ok(InitResponse::default())
+    let mut messages = vec![];
+    for asset in pair.assets.iter() {
+        let (contract_addr, token_code_hash) = token_info(asset);
+        messages.push(snip20::register_receive_msg(
+            env.contract_code_hash.clone(),
+            None,
+            BLOCK_SIZE,
+            token_code_hash,
+            contract_addr,
+        )?);
+    }
+    messages.push(
+        WasmMsg::Execute {
+            contract_addr: msg.factory.address,
+            callback_code_hash: msg.factory.code_hash,
+            msg: to_binary(&FactoryHandleMsg::RegisterPair {})?,
+            send: vec![],
+        }
+        .into(),
+    );
+
+    Ok(InitResponse {
+        messages,
+        log: vec![],
+    })
 }
 
 pub fn query<S: Storage, A: Api, Q: Querier>(
     deps: &Extern<S, A, Q>,
     msg: QueryMsg,
 ) -> StdResult<Binary> {
-    match msg {
-        QueryMsg::Pair {} => to_binary(&query_pair(&deps)?),
-        QueryMsg::Pool {} => to_binary(&query_pool(&deps)?),
-    }
+    let block_size = padding_block_size_read(&deps.storage).load()?;
+    let response = match msg {
+        QueryMsg::Pair {} => to_binary(&query_pair(deps)?),
+        QueryMsg::Pool {} => to_binary(&query_pool(deps)?),
+        QueryMsg::Curve {} => to_binary(&curve_read(&deps.storage).load()?),
+        QueryMsg::SwapMode {} => to_binary(&swap_mode_read(&deps.storage).load()?),
+        QueryMsg::Simulation { offer, ask_asset } => {
+            to_binary(&query_simulation(deps, offer, ask_asset)?)
+        }
+        QueryMsg::Prices {} => to_binary(&query_prices(deps)?),
+        QueryMsg::ConcentratedPool {} => to_binary(&concentrated_read(&deps.storage).load()?),
+        QueryMsg::RangePosition {
+            position_id,
+            address,
+            key,
+        } => to_binary(&query_range_position(deps, position_id, address, key)?),
+        QueryMsg::OrderBook {} => to_binary(&query_order_book(deps)?),
+        QueryMsg::Order {
+            order_id,
+            address,
+            key,
+        } => to_binary(&query_order(deps, order_id, address, key)?),
+        QueryMsg::Orders { address, key } => to_binary(&query_orders(deps, address, key)?),
+        QueryMsg::Twap {
+            window_seconds,
+            now,
+        } => to_binary(&query_twap(deps, window_seconds, now)?),
+        QueryMsg::Batch {} => to_binary(&query_batch(deps)?),
+        QueryMsg::Status {} => to_binary(&status_read(&deps.storage).load()?),
+        QueryMsg::SwapLimits {} => to_binary(&swap_limits_read(&deps.storage).load()?),
+        QueryMsg::Position { address, key } => to_binary(&query_position(deps, address, key)?),
+        QueryMsg::History {
+            address,
+            key,
+            page,
+            page_size,
+        } => to_binary(&query_history(deps, address, key, page, page_size)?),
+        QueryMsg::ExactPool { address, key } => to_binary(&query_exact_pool(deps, address, key)?),
+        QueryMsg::WithPermit { permit, query } => query_with_permit(deps, permit, query),
+    };
+    pad_query_result(response, block_size as usize)
 }
 
 pub fn query_pair<S: Storage, A: Api, Q: Querier>(deps: &Extern<S, A, Q>) -> StdResult<Pair> {
     config_read(&deps.storage).load()
 }
 
-pub fn query_pool<S: Storage, A: Api, Q: Querier>(_deps: &Extern<S, A, Q>) -> StdResult<String> {
-    Ok(String::from("test"))
+pub fn query_pool<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+) -> StdResult<PoolResponse> {
+    pool_response(&deps.storage, public_reserves(&deps.storage)?)
+}
+
+pub fn query_exact_pool<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    address: HumanAddr,
+    key: String,
+) -> StdResult<PoolResponse> {
+    let owner = authenticate(deps, &address, &key)?;
+    exact_pool(&deps.storage, &owner)
+}
+
+/// The exact reserves, if `owner` provides liquidity.
+fn exact_pool<S: Storage>(storage: &S, owner: &CanonicalAddr) -> StdResult<PoolResponse> {
+    let share = shares_read(storage)
+        .may_load(owner.as_slice())?
+        .unwrap_or_else(Uint128::zero);
+    if share.is_zero() {
+        return Err(StdError::generic_err(
+            "Only liquidity providers can query the exact reserves",
+        ));
+    }
+    pool_response(storage, reserves_read(storage).load()?)
+}
+
+fn pool_response<S: Storage>(storage: &S, reserves: Reserves) -> StdResult<PoolResponse> {
+    let pair = config_read(storage).load()?;
+
+    Ok(PoolResponse {
+        assets: pair
+            .assets
+            .into_iter()
+            .zip(reserves.amounts)
+            .map(|(meta, amount)| Asset { meta, amount })
+            .collect(),
+        total_share: reserves.total_share,
+    })
+}
+
+pub fn query_simulation<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    offer: Asset,
+    ask_asset: Option<AssetMeta>,
+) -> StdResult<SimulationResponse> {
+    let pair = config_read(&deps.storage).load()?;
+    // Quoting off the exact reserves would give away what `ReserveVisibility` hides.
+    let pool = public_reserves(&deps.storage)?;
+    let curve_state = curve_read(&deps.storage).load()?;
+    // Queries don't see the block time, so a ramping amplification is taken as of the last
+    // reserve change.
+    let now = prices_read(&deps.storage).load()?.last_update;
+
+    let offer_index = asset_index(&pair, &offer.meta)?;
+    let ask_index = ask_index(&pair, offer_index, ask_asset)?;
+
+    Ok(SimulationResponse {
+        return_amount: swap_return(
+            &deps.storage,
+            &curve_state,
+            &pool,
+            offer_index,
+            ask_index,
+            offer.amount,
+            now,
+        )?,
+    })
+}
+
+pub fn query_prices<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+) -> StdResult<PricesResponse> {
+    let state = prices_read(&deps.storage).load()?;
+    let pool = public_reserves(&deps.storage)?;
+
+    Ok(PricesResponse {
+        price_cumulative: state.price_cumulative,
+        last_update: state.last_update,
+        reserves: pool.amounts,
+    })
+}
+
+pub fn query_range_position<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    position_id: u64,
+    address: HumanAddr,
+    key: String,
+) -> StdResult<RangePositionResponse> {
+    let owner = authenticate(deps, &address, &key)?;
+    range_position(deps, &owner, position_id)
+}
+
+/// `owner`'s position `position_id`, which looks the same as one that doesn't exist to anyone
+/// else.
+fn range_position<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    owner: &CanonicalAddr,
+    position_id: u64,
+) -> StdResult<RangePositionResponse> {
+    let pool = concentrated_read(&deps.storage).load()?;
+    let position = match positions_read(&deps.storage).may_load(&position_id.to_be_bytes())? {
+        Some(position) if position.owner == *owner => position,
+        _ => {
+            return Err(StdError::generic_err(format!(
+                "No position {} of {}",
+                position_id,
+                deps.api.human_address(owner)?
+            )))
+        }
+    };
+
+    Ok(RangePositionResponse {
+        lower_tick: position.lower_tick,
+        upper_tick: position.upper_tick,
+        liquidity: position.liquidity,
+        amounts: concentrated::amounts_for_liquidity(
+            &pool,
+            position.lower_tick,
+            position.upper_tick,
+            position.liquidity.u128(),
+            false,
+        )?,
+        fees: concentrated::pending_fees(&deps.storage, &pool, &position)?,
+    })
+}
+
+pub fn query_batch<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+) -> StdResult<BatchResponse> {
+    let epoch_blocks = batch_epoch_blocks(&deps.storage)?;
+
+    Ok(match batch_read(&deps.storage).may_load()? {
+        Some(pending) => BatchResponse {
+            swap_count: pending.swaps.len() as u64,
+            settle_height: Some((pending.epoch + 1) * epoch_blocks),
+        },
+        None => BatchResponse {
+            swap_count: 0,
+            settle_height: None,
+        },
+    })
+}
+
+pub fn query_position<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    address: HumanAddr,
+    key: String,
+) -> StdResult<PositionResponse> {
+    let owner = authenticate(deps, &address, &key)?;
+    position(deps, &owner)
+}
+
+pub fn query_history<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    address: HumanAddr,
+    key: String,
+    page: u32,
+    page_size: u32,
+) -> StdResult<HistoryResponse> {
+    let owner = authenticate(deps, &address, &key)?;
+    history(&deps.storage, &owner, page, page_size)
+}
+
+/// The canonical `address`, if `key` is its viewing key.
+fn authenticate<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    address: &HumanAddr,
+    key: &str,
+) -> StdResult<CanonicalAddr> {
+    let owner = deps.api.canonical_address(address)?;
+    let stored = viewing_keys_read(&deps.storage).may_load(owner.as_slice())?;
+    if !viewing_key::matches(key, stored.as_ref()) {
+        return Err(StdError::generic_err(
+            "Wrong viewing key for this address or viewing key not set",
+        ));
+    }
+    Ok(owner)
+}
+
+pub fn query_with_permit<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    permit: Permit,
+    query: QueryWithPermit,
+) -> StdResult<Binary> {
+    let pair = config_read(&deps.storage).load()?;
+    let account = permit::validate(deps, PREFIX_REVOKED_PERMITS, &permit, pair.contract_addr)?;
+    let owner = deps.api.canonical_address(&account)?;
+
+    match query {
+        QueryWithPermit::Position {} => {
+            if !permit.check_permission(&Permission::Balance) {
+                return Err(StdError::generic_err(format!(
+                    "No permission to query the position, got permissions {:?}",
+                    permit.params.permissions
+                )));
+            }
+            to_binary(&position(deps, &owner)?)
+        }
+        QueryWithPermit::History { page, page_size } => {
+            if !permit.check_permission(&Permission::History) {
+                return Err(StdError::generic_err(format!(
+                    "No permission to query the history, got permissions {:?}",
+                    permit.params.permissions
+                )));
+            }
+            to_binary(&history(&deps.storage, &owner, page, page_size)?)
+        }
+        QueryWithPermit::ExactPool {} => {
+            if !permit.check_permission(&Permission::Balance) {
+                return Err(StdError::generic_err(format!(
+                    "No permission to query the exact reserves, got permissions {:?}",
+                    permit.params.permissions
+                )));
+            }
+            to_binary(&exact_pool(&deps.storage, &owner)?)
+        }
+        QueryWithPermit::Orders {} => {
+            if !permit.check_permission(&Permission::Balance) {
+                return Err(StdError::generic_err(format!(
+                    "No permission to query the orders, got permissions {:?}",
+                    permit.params.permissions
+                )));
+            }
+            to_binary(&open_orders(&deps.storage, &owner)?)
+        }
+        QueryWithPermit::RangePosition { position_id } => {
+            if !permit.check_permission(&Permission::Balance) {
+                return Err(StdError::generic_err(format!(
+                    "No permission to query the position, got permissions {:?}",
+                    permit.params.permissions
+                )));
+            }
+            to_binary(&range_position(deps, &owner, position_id)?)
+        }
+    }
+}
+
+/// A page of `owner`'s history, newest first.
+fn history<S: Storage>(
+    storage: &S,
+    owner: &CanonicalAddr,
+    page: u32,
+    page_size: u32,
+) -> StdResult<HistoryResponse> {
+    if page_size == 0 || page_size > MAX_PAGE_SIZE {
+        return Err(StdError::generic_err(format!(
+            "Page size must be between 1 and {}",
+            MAX_PAGE_SIZE
+        )));
+    }
+
+    let store = ReadonlyPrefixedStorage::multilevel(&[PREFIX_HISTORY, owner.as_slice()], storage);
+    let store = match AppendStore::<HistoryEntry, _>::attach(&store) {
+        Some(store) => store?,
+        None => {
+            return Ok(HistoryResponse {
+                entries: vec![],
+                total: 0,
+            })
+        }
+    };
+    // In u64, as the product of two u32 overflows a wasm32 usize.
+    let start = page as u64 * page_size as u64;
+    let entries = if start >= store.len() as u64 {
+        vec![]
+    } else {
+        store
+            .iter()
+            .rev()
+            .skip(start as usize)
+            .take(page_size as usize)
+            .collect::<StdResult<Vec<_>>>()?
+    };
+
+    Ok(HistoryResponse {
+        entries,
+        total: store.len() as u64,
+    })
+}
+
+fn position<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    owner: &CanonicalAddr,
+) -> StdResult<PositionResponse> {
+    let pool = reserves_read(&deps.storage).load()?;
+    let share = shares_read(&deps.storage)
+        .may_load(owner.as_slice())?
+        .unwrap_or_else(Uint128::zero);
+    let amounts = if share.is_zero() {
+        vec![Uint128::zero(); pool.amounts.len()]
+    } else {
+        compute_withdrawal(share, &pool.amounts, pool.total_share)?
+    };
+
+    Ok(PositionResponse {
+        share,
+        amounts,
+        fees: lp_fees_at(&deps.storage, owner, share)?.fees_accrued,
+    })
+}
+
+pub fn query_order_book<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+) -> StdResult<OrderBookResponse> {
+    let pair = config_read(&deps.storage).load()?;
+    let book = order_book_read(&deps.storage).load()?;
+
+    // A bucket of a single order would show its size, and an exact sum would show the owner of
+    // one order the size of the others.
+    let mut depth: Vec<(DepthBucket, usize)> = vec![];
+    for open in book.open {
+        let order = orders_read(&deps.storage).load(&open.id.to_be_bytes())?;
+        let offer_asset = &pair.assets[open.offer_index as usize];
+        let ask_asset = &pair.assets[open.ask_index as usize];
+        let price = round_down(open.price, DEPTH_PRICE_DIGITS);
+        let bucket = depth.iter_mut().find(|(bucket, _)| {
+            bucket.offer_asset == *offer_asset
+                && bucket.ask_asset == *ask_asset
+                && bucket.price == price
+        });
+        match bucket {
+            Some((bucket, count)) => {
+                bucket.offer_remaining += order.offer_remaining;
+                *count += 1;
+            }
+            None => depth.push((
+                DepthBucket {
+                    offer_asset: offer_asset.clone(),
+                    ask_asset: ask_asset.clone(),
+                    price,
+                    offer_remaining: order.offer_remaining,
+                },
+                1,
+            )),
+        }
+    }
+
+    Ok(OrderBookResponse {
+        depth: depth
+            .into_iter()
+            .filter(|(_, count)| *count >= MIN_DEPTH_ORDERS)
+            .map(|(mut bucket, _)| {
+                bucket.offer_remaining = round_down(bucket.offer_remaining, DEPTH_AMOUNT_DIGITS);
+                bucket
+            })
+            .collect(),
+    })
+}
+
+pub fn query_order<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    order_id: u64,
+    address: HumanAddr,
+    key: String,
+) -> StdResult<OrderResponse> {
+    let owner = authenticate(deps, &address, &key)?;
+    let pair = config_read(&deps.storage).load()?;
+    match orders_read(&deps.storage).may_load(&order_id.to_be_bytes())? {
+        Some(order) if order.owner == owner => Ok(order_response(&pair, order_id, order)),
+        _ => Err(StdError::generic_err(format!(
+            "No order {} of {}",
+            order_id, address
+        ))),
+    }
+}
+
+pub fn query_orders<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    address: HumanAddr,
+    key: String,
+) -> StdResult<OrdersResponse> {
+    let owner = authenticate(deps, &address, &key)?;
+    open_orders(&deps.storage, &owner)
+}
+
+fn open_orders<S: Storage>(storage: &S, owner: &CanonicalAddr) -> StdResult<OrdersResponse> {
+    let pair = config_read(storage).load()?;
+    let order_ids = owner_orders_read(storage)
+        .may_load(owner.as_slice())?
+        .unwrap_or_default();
+
+    let mut orders = vec![];
+    for order_id in order_ids {
+        let order = orders_read(storage).load(&order_id.to_be_bytes())?;
+        orders.push(order_response(&pair, order_id, order));
+    }
+    Ok(OrdersResponse { orders })
+}
+
+fn order_response(pair: &Pair, order_id: u64, order: LimitOrder) -> OrderResponse {
+    OrderResponse {
+        order_id,
+        offer_asset: pair.assets[order.offer_index as usize].clone(),
+        ask_asset: pair.assets[order.ask_index as usize].clone(),
+        offer_amount: order.offer_amount,
+        ask_amount: order.ask_amount,
+        offer_remaining: order.offer_remaining,
+        received: order.received,
+    }
+}
+
+/// Averages the prices over the `window_seconds` before `now`, interpolating between the
+/// recorded observations. The averages are of the reserves `Pool` shows, so a pair that hides
+/// its reserves doesn't give them away here.
+///
+/// Queries don't see the block time. Without `now` the window ends at the last reserve change,
+/// which may be long ago on a quiet pair, and the price since then is left out. With it, the
+/// current reserves are taken to have held from the last change until `now`.
+pub fn query_twap<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    window_seconds: u64,
+    now: Option<u64>,
+) -> StdResult<TwapResponse> {
+    if window_seconds == 0 {
+        return Err(StdError::generic_err("Window must be at least one second"));
+    }
+
+    let mut state = prices_read(&deps.storage).load()?;
+    let end = now.unwrap_or(state.last_update);
+    if end < state.last_update {
+        return Err(StdError::generic_err(format!(
+            "The reserves last changed at {}, after the window's end",
+            state.last_update
+        )));
+    }
+    let pool = public_reserves(&deps.storage)?;
+    accumulate_prices(&deps.storage, &mut state, &pool, end)?;
+    let start = end
+        .checked_sub(window_seconds)
+        .ok_or_else(|| StdError::generic_err("Window exceeds the observation history"))?;
+
+    // Walk back from `end` to the last observation at or before `start`.
+    let store = observations_read(&deps.storage);
+    let recorded = state.observation_count.min(OBSERVATION_CAPACITY);
+    let mut later = Observation {
+        timestamp: end,
+        price_cumulative: state.price_cumulative.clone(),
+    };
+    let mut start_cumulative = None;
+    for i in 1..=recorded {
+        let slot = (state.observation_count - i) % OBSERVATION_CAPACITY;
+        let observation = store.load(&slot.to_be_bytes())?;
+        if observation.timestamp <= start {
+            start_cumulative = Some(interpolate(&observation, &later, start));
+            break;
+        }
+        later = observation;
+    }
+    let start_cumulative = start_cumulative
+        .ok_or_else(|| StdError::generic_err("Window exceeds the observation history"))?;
+
+    let price_average = state
+        .price_cumulative
+        .iter()
+        .zip(start_cumulative.iter())
+        .map(|(end, start)| Uint128(end.u128().wrapping_sub(start.u128()) / window_seconds as u128))
+        .collect();
+
+    Ok(TwapResponse {
+        price_average,
+        window_end: end,
+    })
+}
+
+/// Cumulative prices at `timestamp`, between `earlier` and `later`. The price is constant
+/// between observations, so only the division by their span rounds, down to the unit of the
+/// accumulators.
+fn interpolate(earlier: &Observation, later: &Observation, timestamp: u64) -> Vec<Uint128> {
+    if later.timestamp == earlier.timestamp {
+        return earlier.price_cumulative.clone();
+    }
+    let span = (later.timestamp - earlier.timestamp) as u128;
+    let elapsed = (timestamp - earlier.timestamp) as u128;
+
+    let mut cumulative = earlier.price_cumulative.clone();
+    for (value, end) in cumulative.iter_mut().zip(later.price_cumulative.iter()) {
+        let price = end.u128().wrapping_sub(value.u128()) / span;
+        *value = Uint128(value.u128().wrapping_add(price.wrapping_mul(elapsed)));
+    }
+    cumulative
 }
 
 pub fn handle<S: Storage, A: Api, Q: Querier>(
-    _deps: &Extern<S, A, Q>,
-    _env: Env,
-    _msg: HandleMsg,
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    msg: HandleMsg,
 ) -> StdResult<HandleResponse> {
-    Ok(HandleResponse::default())
+    let block_size = padding_block_size_read(&deps.storage).load()?;
+    let block = env.block.clone();
+    enforce_status(status_read(&deps.storage).load()?, &msg)?;
+    let before = reserves_read(&deps.storage).load()?;
+    let sqrt_price_before = concentrated_read(&deps.storage)
+        .may_load()?
+        .map(|state| state.sqrt_price);
+    let response = match msg {
+        HandleMsg::Receive {
+            from, amount, msg, ..
+        } => try_receive(deps, env, from, amount, msg),
+        HandleMsg::AddLiquidity {
+            assets,
+            receiver,
+            min_share,
+            ..
+        } => try_add_liquidity(deps, env, assets, receiver, min_share),
+        HandleMsg::RemoveLiquidity { share, asset, .. } => {
+            try_remove_liquidity(deps, env, share, asset)
+        }
+        HandleMsg::FlashSwap {
+            amounts,
+            receiver,
+            msg,
+            ..
+        } => try_flash_swap(deps, env, amounts, receiver, msg),
+        HandleMsg::FinishFlashSwap {} => try_finish_flash_swap(deps, env),
+        HandleMsg::RampAmplification {
+            future_amp,
+            future_time,
+        } => try_ramp_amplification(deps, env, future_amp, future_time),
+        HandleMsg::SetReserveVisibility { visibility } => {
+            try_set_reserve_visibility(deps, env, visibility)
+        }
+        HandleMsg::SetStatus { status } => try_set_status(deps, env, status),
+        HandleMsg::SetSwapLimits { limits } => try_set_swap_limits(deps, env, limits),
+        HandleMsg::OpenPosition {
+            lower_tick,
+            upper_tick,
+            amounts,
+            receiver,
+            ..
+        } => try_open_position(deps, env, lower_tick, upper_tick, amounts, receiver),
+        HandleMsg::WithdrawPosition {
+            position_id,
+            liquidity,
+            ..
+        } => try_withdraw_position(deps, env, position_id, liquidity),
+        HandleMsg::CollectFees { position_id, .. } => try_collect_fees(deps, env, position_id),
+        HandleMsg::FillOrder { order_id, .. } => try_fill_order(deps, env, order_id),
+        HandleMsg::CancelOrder { order_id, .. } => try_cancel_order(deps, env, order_id),
+        HandleMsg::CreateViewingKey { entropy, .. } => try_create_viewing_key(deps, env, entropy),
+        HandleMsg::SetViewingKey { key, .. } => try_set_viewing_key(deps, env, key),
+        HandleMsg::RevokePermit { permit_name, .. } => try_revoke_permit(deps, env, permit_name),
+        HandleMsg::Settle { .. } => try_settle(deps, env),
+        HandleMsg::MigrateLiquidity {
+            owner,
+            new_pair,
+            min_share,
+        } => try_migrate_liquidity(deps, env, owner, new_pair, min_share),
+    };
+    if response.is_ok() {
+        check_invariants(&deps.storage, &before, sqrt_price_before, block.time)?;
+        publish_reserves(&mut deps.storage, &block)?;
+    }
+    pad_handle_result(response, block_size as usize)
 }
 
-pub fn try_add_liquidity<S: Storage, A: Api, Q: Querier>(
+/// Fails if `status` doesn't let `msg` through. `StopTrading` still lets liquidity providers
+/// and order owners take their funds out, and refunds queued batch swaps on `Settle`. `StopAll`
+/// only lets users manage their viewing keys and permits, and the factory change the pair's
+/// settings and status.
+fn enforce_status(status: ContractStatus, msg: &HandleMsg) -> StdResult<()> {
+    let allowed = match msg {
+        HandleMsg::CreateViewingKey { .. }
+        | HandleMsg::SetViewingKey { .. }
+        | HandleMsg::RevokePermit { .. }
+        | HandleMsg::RampAmplification { .. }
+        | HandleMsg::SetReserveVisibility { .. }
+        | HandleMsg::SetStatus { .. }
+        | HandleMsg::SetSwapLimits { .. } => true,
+        HandleMsg::RemoveLiquidity { .. }
+        | HandleMsg::WithdrawPosition { .. }
+        | HandleMsg::CollectFees { .. }
+        | HandleMsg::CancelOrder { .. }
+        | HandleMsg::Settle { .. }
+        | HandleMsg::MigrateLiquidity { .. } => status != ContractStatus::StopAll,
+        _ => status == ContractStatus::Normal,
+    };
+
+    match status {
+        _ if allowed => Ok(()),
+        ContractStatus::StopTrading => Err(StdError::generic_err(
+            "Trading on this pair is stopped, only withdrawals are allowed",
+        )),
+        _ => Err(StdError::generic_err("This pair is stopped")),
+    }
+}
+
+pub fn try_receive<S: Storage, A: Api, Q: Querier>(
     deps: &mut Extern<S, A, Q>,
-    _env: Env,
-    _assets: [AssetMeta; 2],
-    _receiver: Option<HumanAddr>,
+    env: Env,
+    from: HumanAddr,
+    amount: Uint128,
+    msg: Option<Binary>,
 ) -> StdResult<HandleResponse> {
-    let _config = config_read(&deps.storage).load()?;
+    let msg: ReceiveMsg = from_binary(&msg.ok_or_else(|| StdError::generic_err("Missing msg"))?)?;
+    let pair = config_read(&deps.storage).load()?;
+    credit(&mut deps.storage, offer_index(&pair, &env)?, amount)?;
 
-    Ok(HandleResponse::default())
+    match msg {
+        ReceiveMsg::Swap {
+            ask_asset,
+            expected_return,
+            to,
+            callback,
+        } => {
+            let swap = SwapRequest {
+                sender: deps.api.canonical_address(&from)?,
+                recipient: deps.api.canonical_address(to.as_ref().unwrap_or(&from))?,
+                offer_index: offer_index(&pair, &env)? as u8,
+                offer_amount: amount,
+                expected_return,
+                callback,
+            };
+            try_swap(deps, env, pair, ask_asset, swap)
+        }
+        ReceiveMsg::RepayFlashSwap {} => try_repay_flash_swap(deps, env, amount),
+        ReceiveMsg::PlaceOrder {
+            ask_asset,
+            ask_amount,
+        } => try_place_order(deps, env, from, amount, ask_asset, ask_amount),
+    }
 }
 
-#[cfg(test)]
-mod tests {
-    use common::pair::{AssetMeta, Pair, PairInitMsg};
-    use cosmwasm_std::{
-        from_binary,
-        testing::{mock_dependencies, mock_env, MOCK_CONTRACT_ADDR},
-        HumanAddr,
+pub fn try_swap<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    pair: Pair,
+    ask_asset: Option<AssetMeta>,
+    swap: SwapRequest,
+) -> StdResult<HandleResponse> {
+    let offer_index = swap.offer_index as usize;
+    let ask_index = ask_index(&pair, offer_index, ask_asset)?;
+
+    if let SwapMode::BatchAuction { epoch_blocks } = swap_mode_read(&deps.storage).load()? {
+        // Nothing would call back before the batch settles, long after the sender expected it.
+        if swap.callback.is_some() {
+            return Err(StdError::generic_err(
+                "Batch auction pairs don't take swap callbacks",
+            ));
+        }
+        // The batch's net swap is checked against the limits again when it settles.
+        let limits = swap_limits_read(&deps.storage).load()?;
+        enforce_trade_size(&deps.storage, &limits, offer_index, swap.offer_amount)?;
+        return queue_swap(deps, env, &pair, epoch_blocks, swap);
+    }
+
+    let SwapRequest {
+        sender,
+        recipient,
+        offer_amount,
+        expected_return,
+        callback,
+        ..
+    } = swap;
+    let return_amount = execute_swap(
+        &mut deps.storage,
+        offer_index,
+        ask_index,
+        offer_amount,
+        env.block.time,
+    )?;
+    if let Some(expected_return) = expected_return {
+        if return_amount < expected_return {
+            return Err(StdError::generic_err(format!(
+                "Swap returns {}, less than the expected {}",
+                return_amount, expected_return
+            )));
+        }
+    }
+
+    let history = swap_history(&pair, offer_index, ask_index, offer_amount, return_amount)?;
+    record_history(&mut deps.storage, &sender, &env.block, history)?;
+    let recipient = deps.api.human_address(&recipient)?;
+    let transfer = swap_output(&pair.assets[ask_index], recipient, return_amount, callback)?;
+    debit(&mut deps.storage, ask_index, return_amount)?;
+
+    // The swap moved the price towards the orders going the other way.
+    let (fills, filled) = fill_crossed_orders(deps, &pair, ask_index, offer_index, &env.block)?;
+    let mut messages = vec![transfer];
+    messages.extend(fills);
+
+    let mut attributes = vec![
+        log("action", "swap"),
+        log("offer_asset", &pair.assets[offer_index]),
+        log("ask_asset", &pair.assets[ask_index]),
+        log("offer_amount", offer_amount),
+        log("return_amount", return_amount),
+    ];
+    if !filled.is_empty() {
+        attributes.push(log("filled_orders", join(&filled)));
+    }
+
+    Ok(HandleResponse {
+        messages,
+        log: attributes,
+        data: None,
+    })
+}
+
+/// Adds `swap` to the batch of the current epoch, first settling the batch of an earlier one.
+fn queue_swap<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    pair: &Pair,
+    epoch_blocks: u64,
+    swap: SwapRequest,
+) -> StdResult<HandleResponse> {
+    if swap.offer_amount.is_zero() {
+        return Err(StdError::generic_err("Swap amount too small"));
+    }
+    let reserve = reserves_read(&deps.storage).load()?.amounts[swap.offer_index as usize];
+    let min_amount = mul_div_up(reserve.u128(), MIN_BATCH_SWAP_BPS as u128, MAX_BPS as u128)?;
+    if swap.offer_amount.u128() < min_amount {
+        return Err(StdError::generic_err(format!(
+            "Batched swaps must offer at least {}",
+            min_amount
+        )));
+    }
+
+    let epoch = env.block.height / epoch_blocks;
+    let mut messages = vec![];
+    let mut current = match batch_read(&deps.storage).may_load()? {
+        Some(pending) if pending.epoch == epoch => pending,
+        Some(pending) => {
+            messages = settle_batch(deps, pair, pending, &env.block)?.0;
+            Batch {
+                epoch,
+                swaps: vec![],
+            }
+        }
+        None => Batch {
+            epoch,
+            swaps: vec![],
+        },
     };
+    if current.swaps.len() >= MAX_BATCH_SWAPS {
+        return Err(StdError::generic_err(format!(
+            "The batch is full, at {} swaps",
+            MAX_BATCH_SWAPS
+        )));
+    }
+    current.swaps.push(swap);
+    batch(&mut deps.storage).save(&current)?;
 
-    use crate::msg::QueryMsg;
+    Ok(HandleResponse {
+        messages,
+        log: vec![log("action", "queue_swap"), log("epoch", epoch)],
+        data: None,
+    })
+}
 
-    use super::{init, query};
+pub fn try_settle<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+) -> StdResult<HandleResponse> {
+    let epoch_blocks = batch_epoch_blocks(&deps.storage)?;
+    let pending = batch_read(&deps.storage)
+        .may_load()?
+        .ok_or_else(|| StdError::generic_err("No swaps to settle"))?;
+    // With trading stopped the batch is refunded instead, without waiting for its epoch to end.
+    let trading_stopped = status_read(&deps.storage).load()? == ContractStatus::StopTrading;
+    if !trading_stopped && pending.epoch >= env.block.height / epoch_blocks {
+        return Err(StdError::generic_err(format!(
+            "The batch can't be settled before height {}",
+            (pending.epoch + 1) * epoch_blocks
+        )));
+    }
 
-    #[test]
-    fn proper_initialization() {
-        let mut deps = mock_dependencies(20, &[]);
-        let env = mock_env("creator", &[]);
+    let pair = config_read(&deps.storage).load()?;
+    let epoch = pending.epoch;
+    let swap_count = pending.swaps.len();
+    let (messages, refunded) = if trading_stopped {
+        batch(&mut deps.storage).remove();
+        (refund_swaps(deps, &pair, pending.swaps)?, swap_count)
+    } else {
+        settle_batch(deps, &pair, pending, &env.block)?
+    };
 
-        let assets_meta = [
-            AssetMeta::Token {
-                contract_addr: HumanAddr::from(MOCK_CONTRACT_ADDR),
-            },
-            AssetMeta::Token {
-                contract_addr: HumanAddr::from(MOCK_CONTRACT_ADDR),
-            },
-        ];
-        let msg = PairInitMsg {
-            assets_meta: assets_meta.clone(),
+    Ok(HandleResponse {
+        messages,
+        log: vec![
+            log("action", "settle"),
+            log("epoch", epoch),
+            log("swaps", swap_count),
+            log("refunded", refunded),
+        ],
+        data: None,
+    })
+}
+
+fn batch_epoch_blocks<S: Storage>(storage: &S) -> StdResult<u64> {
+    match swap_mode_read(storage).load()? {
+        SwapMode::BatchAuction { epoch_blocks } => Ok(epoch_blocks),
+        SwapMode::Instant => Err(StdError::generic_err("Swaps of this pair aren't batched")),
+    }
+}
+
+/// Settles `batch` at a single clearing price and removes it. Swaps the price returns nothing
+/// or less than they expect are refunded to their sender, and the rest cleared again. Returns
+/// the transfers and the number of swaps refunded.
+fn settle_batch<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    pair: &Pair,
+    batch_to_settle: Batch,
+    block: &BlockInfo,
+) -> StdResult<(Vec<CosmosMsg>, usize)> {
+    let now = block.time;
+    let pool = reserves_read(&deps.storage).load()?;
+    let curve_state = curve_read(&deps.storage).load()?;
+
+    let mut swaps = batch_to_settle.swaps;
+    let mut refunds = vec![];
+    let (mut clearing, mut payouts) = loop {
+        let mut totals = [0u128; 2];
+        for swap in swaps.iter() {
+            let total = &mut totals[swap.offer_index as usize];
+            *total = total
+                .checked_add(swap.offer_amount.u128())
+                .ok_or_else(|| StdError::generic_err("Addition overflow"))?;
+        }
+        let clearing = clear_batch(&deps.storage, &curve_state, &pool, totals, now)?;
+
+        let swap_count = swaps.len();
+        let mut kept = vec![];
+        let mut payouts = vec![];
+        for swap in swaps {
+            let side = swap.offer_index as usize;
+            let payout = mul_div(swap.offer_amount.u128(), clearing.paid[side], totals[side])?;
+            if payout == 0
+                || matches!(swap.expected_return, Some(expected) if payout < expected.u128())
+            {
+                refunds.push(swap);
+            } else {
+                kept.push(swap);
+                payouts.push(payout);
+            }
+        }
+        swaps = kept;
+        if swaps.len() == swap_count {
+            break (clearing, payouts);
+        }
+    };
+    // A net swap the swap limits reject refunds the whole batch.
+    if let Some((offer_index, amount)) = clearing.net_swap {
+        let ask_index = 1 - offer_index;
+        if enforce_swap_limits(&deps.storage, offer_index, ask_index, Uint128(amount), now).is_err()
+        {
+            refunds.append(&mut swaps);
+            payouts.clear();
+            clearing = Clearing {
+                net_swap: None,
+                paid: [0, 0],
+            };
+        }
+    }
+
+    if let Some((offer_index, amount)) = clearing.net_swap {
+        execute_swap(
+            &mut deps.storage,
+            offer_index,
+            1 - offer_index,
+            Uint128(amount),
+            now,
+        )?;
+    }
+    // What rounding the payouts down leaves over goes to the pool.
+    let mut dust = [0u128; 2];
+    for side in 0..2 {
+        dust[1 - side] = clearing.paid[side];
+    }
+    for (swap, payout) in swaps.iter().zip(payouts.iter()) {
+        dust[1 - swap.offer_index as usize] -= payout;
+    }
+    if dust.iter().any(|amount| *amount > 0) {
+        let mut pool = reserves_read(&deps.storage).load()?;
+        for (reserve, amount) in pool.amounts.iter_mut().zip(dust.iter()) {
+            *reserve += Uint128(*amount);
+        }
+        save_reserves(&mut deps.storage, &pool, now)?;
+    }
+    batch(&mut deps.storage).remove();
+
+    let mut messages = vec![];
+    for (swap, payout) in swaps.into_iter().zip(payouts) {
+        let (offer_index, ask_index) = (swap.offer_index as usize, 1 - swap.offer_index as usize);
+        let history = swap_history(
+            pair,
+            offer_index,
+            ask_index,
+            swap.offer_amount,
+            Uint128(payout),
+        )?;
+        record_history(&mut deps.storage, &swap.sender, block, history)?;
+        let recipient = deps.api.human_address(&swap.recipient)?;
+        debit(&mut deps.storage, ask_index, Uint128(payout))?;
+        messages.push(swap_output(
+            &pair.assets[ask_index],
+            recipient,
+            Uint128(payout),
+            swap.callback,
+        )?);
+    }
+    let refunded = refunds.len();
+    messages.extend(refund_swaps(deps, pair, refunds)?);
+
+    Ok((messages, refunded))
+}
+
+/// Returns the offers of `swaps` to their senders.
+fn refund_swaps<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    pair: &Pair,
+    swaps: Vec<SwapRequest>,
+) -> StdResult<Vec<CosmosMsg>> {
+    let mut messages = vec![];
+    for swap in swaps {
+        let sender = deps.api.human_address(&swap.sender)?;
+        debit(
+            &mut deps.storage,
+            swap.offer_index as usize,
+            swap.offer_amount,
+        )?;
+        messages.push(transfer(
+            &pair.assets[swap.offer_index as usize],
+            sender,
+            swap.offer_amount,
+        )?);
+    }
+    Ok(messages)
+}
+
+/// How a batch of swaps between two assets clears.
+struct Clearing {
+    /// The asset traded with the pool, and how much of it, for the part of the batch the swaps
+    /// going the other way don't match.
+    net_swap: Option<(usize, u128)>,
+    /// Paid out to the swaps offering each asset, in the other asset.
+    paid: [u128; 2],
+}
+
+/// Clears `totals` offered of each asset at a single price. The side offering more is matched
+/// with the other side and trades the rest with the pool, the amount traded being where the
+/// pool's average price matches the price of the rest of the side buying all the other side
+/// offers. Within the fee, neither side trades with the pool.
+fn clear_batch<S: Storage>(
+    storage: &S,
+    curve_state: &CurveState,
+    pool: &Reserves,
+    totals: [u128; 2],
+    now: u64,
+) -> StdResult<Clearing> {
+    for side in 0..2 {
+        let (offered, other) = (totals[side], totals[1 - side]);
+        if offered == 0 {
+            continue;
+        }
+        let pool_return = |amount: u128| {
+            swap_return(
+                storage,
+                curve_state,
+                pool,
+                side,
+                1 - side,
+                Uint128(amount),
+                now,
+            )
+            .map_or(0, |amount| amount.u128())
         };
 
-        let _res = init(&mut deps, env, msg).unwrap();
-        let msg = QueryMsg::Pair {};
+        let amount = if other == 0 {
+            offered
+        } else {
+            let clears = |amount: u128| -> StdResult<bool> {
+                Ok(mul_div(offered - amount, pool_return(amount), amount)? >= other)
+            };
+            let (mut low, mut high) = (0, offered);
+            while high - low > 1 {
+                let mid = low + (high - low) / 2;
+                if clears(mid)? {
+                    low = mid;
+                } else {
+                    high = mid;
+                }
+            }
+            low
+        };
+        if amount > 0 {
+            let mut paid = [0u128; 2];
+            paid[side] = other + pool_return(amount);
+            paid[1 - side] = offered - amount;
+            return Ok(Clearing {
+                net_swap: Some((side, amount)),
+                paid,
+            });
+        }
+    }
 
-        let res = query(&deps, msg).unwrap();
-        let _value: Pair = from_binary(&res).unwrap();
+    Ok(Clearing {
+        net_swap: None,
+        paid: [totals[1], totals[0]],
+    })
+}
+
+/// Swaps `offer_amount` of the asset at `offer_index` into the pool and saves the new
+/// reserves, returning the amount of the asset at `ask_index` to pay out. Every trade with the
+/// pool goes through here, so it is where the swap limits are enforced.
+fn execute_swap<S: Storage>(
+    storage: &mut S,
+    offer_index: usize,
+    ask_index: usize,
+    offer_amount: Uint128,
+    now: u64,
+) -> StdResult<Uint128> {
+    enforce_swap_limits(storage, offer_index, ask_index, offer_amount, now)?;
+    let mut pool = reserves_read(storage).load()?;
+    let curve_state = curve_read(storage).load()?;
+
+    // A concentrated liquidity swap also moves the price and crosses ticks, which are saved
+    // along with the reserves.
+    let mut concentrated_swap = None;
+    let return_amount = match curve_state {
+        CurveState::Concentrated { .. } => {
+            let mut state = concentrated_read(storage).load()?;
+            let (return_amount, crossed) =
+                concentrated::swap(storage, &mut state, offer_index == 0, offer_amount.u128())?;
+            concentrated_swap = Some((state, crossed));
+            Uint128(return_amount)
+        }
+        _ => swap_return(
+            storage,
+            &curve_state,
+            &pool,
+            offer_index,
+            ask_index,
+            offer_amount,
+            now,
+        )?,
+    };
+    if return_amount.is_zero() {
+        return Err(StdError::generic_err("Swap amount too small"));
+    }
+
+    let mut swapped = vec![Uint128::zero(); pool.amounts.len()];
+    swapped[offer_index] = offer_amount;
+    accrue_fees(storage, &swapped, pool.total_share)?;
+
+    pool.amounts[offer_index] += offer_amount;
+    pool.amounts[ask_index] = (pool.amounts[ask_index] - return_amount)?;
+    save_reserves(storage, &pool, now)?;
+    if let Some((state, crossed)) = concentrated_swap {
+        for (tick, data) in crossed {
+            ticks(storage).save(&tick.to_be_bytes(), &data)?;
+        }
+        concentrated(storage).save(&state)?;
+    }
+
+    Ok(return_amount)
+}
+
+pub fn try_place_order<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    owner: HumanAddr,
+    offer_amount: Uint128,
+    ask_asset: Option<AssetMeta>,
+    ask_amount: Uint128,
+) -> StdResult<HandleResponse> {
+    if swap_mode_read(&deps.storage).load()? != SwapMode::Instant {
+        return Err(StdError::generic_err(
+            "Batch auction pairs don't support limit orders",
+        ));
+    }
+
+    let pair = config_read(&deps.storage).load()?;
+    let offer_index = offer_index(&pair, &env)?;
+    let ask_index = ask_index(&pair, offer_index, ask_asset)?;
+    if offer_amount.is_zero() || ask_amount.is_zero() {
+        return Err(StdError::generic_err("Order amounts must not be zero"));
+    }
+    let reserve = reserves_read(&deps.storage).load()?.amounts[offer_index];
+    let min_amount = mul_div_up(reserve.u128(), MIN_ORDER_BPS as u128, MAX_BPS as u128)?;
+    if offer_amount.u128() < min_amount {
+        return Err(StdError::generic_err(format!(
+            "Orders must offer at least {}",
+            min_amount
+        )));
+    }
+
+    let mut book = order_book_read(&deps.storage).load()?;
+    if book.open.len() >= MAX_OPEN_ORDERS {
+        return Err(StdError::generic_err(format!(
+            "The order book is full, at {} open orders",
+            MAX_OPEN_ORDERS
+        )));
+    }
+    let order_id = book.order_count;
+    book.order_count += 1;
+    let mut order = LimitOrder {
+        owner: deps.api.canonical_address(&owner)?,
+        offer_index: offer_index as u8,
+        ask_index: ask_index as u8,
+        offer_amount,
+        ask_amount,
+        offer_remaining: offer_amount,
+        received: Uint128::zero(),
+    };
+
+    // Whatever the pool fills right away doesn't wait in the book.
+    let messages: Vec<CosmosMsg> = fill_order(deps, &pair, &mut order, &env.block)?
+        .into_iter()
+        .collect();
+    if !order.offer_remaining.is_zero() {
+        let price = Uint128(mul_div_up(
+            ask_amount.u128(),
+            PRICE_PRECISION,
+            offer_amount.u128(),
+        )?);
+        let index = book
+            .open
+            .iter()
+            .position(|open| open.price > price)
+            .unwrap_or(book.open.len());
+        book.open.insert(
+            index,
+            OpenOrder {
+                id: order_id,
+                offer_index: order.offer_index,
+                ask_index: order.ask_index,
+                price,
+            },
+        );
+
+        let mut order_ids = owner_orders_read(&deps.storage)
+            .may_load(order.owner.as_slice())?
+            .unwrap_or_default();
+        order_ids.push(order_id);
+        owner_orders(&mut deps.storage).save(order.owner.as_slice(), &order_ids)?;
+    }
+    orders(&mut deps.storage).save(&order_id.to_be_bytes(), &order)?;
+    order_book(&mut deps.storage).save(&book)?;
+
+    Ok(HandleResponse {
+        messages,
+        log: vec![
+            log("action", "place_order"),
+            log("order_id", order_id),
+            log("owner", owner),
+            log("offer_amount", offer_amount),
+            log("ask_amount", ask_amount),
+            log("received", order.received),
+        ],
+        data: None,
+    })
+}
+
+pub fn try_fill_order<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    order_id: u64,
+) -> StdResult<HandleResponse> {
+    let pair = config_read(&deps.storage).load()?;
+    let mut order = load_open_order(&deps.storage, order_id)?;
+
+    let transfer = fill_order(deps, &pair, &mut order, &env.block)?
+        .ok_or_else(|| StdError::generic_err("The pool's price doesn't fill this order"))?;
+    orders(&mut deps.storage).save(&order_id.to_be_bytes(), &order)?;
+    if order.offer_remaining.is_zero() {
+        close_order(&mut deps.storage, order_id, &order.owner)?;
+    }
+
+    // Anyone can fill an order, so the response doesn't tell how much of it is left.
+    Ok(HandleResponse {
+        messages: vec![transfer],
+        log: vec![log("action", "fill_order"), log("order_id", order_id)],
+        data: None,
+    })
+}
+
+pub fn try_cancel_order<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    order_id: u64,
+) -> StdResult<HandleResponse> {
+    let pair = config_read(&deps.storage).load()?;
+    let mut order = load_open_order(&deps.storage, order_id)?;
+    if order.owner != deps.api.canonical_address(&env.message.sender)? {
+        return Err(StdError::unauthorized());
+    }
+
+    let refund = order.offer_remaining;
+    order.offer_remaining = Uint128::zero();
+    orders(&mut deps.storage).save(&order_id.to_be_bytes(), &order)?;
+    close_order(&mut deps.storage, order_id, &order.owner)?;
+    debit(&mut deps.storage, order.offer_index as usize, refund)?;
+
+    Ok(HandleResponse {
+        messages: vec![transfer(
+            &pair.assets[order.offer_index as usize],
+            env.message.sender,
+            refund,
+        )?],
+        log: vec![
+            log("action", "cancel_order"),
+            log("order_id", order_id),
+            log("refund", refund),
+        ],
+        data: None,
+    })
+}
+
+/// Swaps as much of `order` into the pool as fills at its price and within the swap limits,
+/// returning the transfer of the proceeds to its owner. A partial fill is found by bisection,
+/// so it may fall a little short of the most that would fill.
+fn fill_order<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    pair: &Pair,
+    order: &mut LimitOrder,
+    block: &BlockInfo,
+) -> StdResult<Option<CosmosMsg>> {
+    let now = block.time;
+    let (offer_index, ask_index) = (order.offer_index as usize, order.ask_index as usize);
+    let (offer_amount, ask_amount) = (order.offer_amount.u128(), order.ask_amount.u128());
+    let pool = reserves_read(&deps.storage).load()?;
+    let curve_state = curve_read(&deps.storage).load()?;
+    let fills = |amount: u128| -> StdResult<bool> {
+        let min_return = mul_div_up(ask_amount, amount, offer_amount)?;
+        let return_amount = swap_return(
+            &deps.storage,
+            &curve_state,
+            &pool,
+            offer_index,
+            ask_index,
+            Uint128(amount),
+            now,
+        );
+        Ok(
+            matches!(return_amount, Ok(amount) if amount.u128() >= min_return)
+                && enforce_swap_limits(&deps.storage, offer_index, ask_index, Uint128(amount), now)
+                    .is_ok(),
+        )
+    };
+
+    let remaining = order.offer_remaining.u128();
+    let amount = if fills(remaining)? {
+        remaining
+    } else {
+        let (mut low, mut high) = (0, remaining);
+        for _ in 0..FILL_SEARCH_STEPS {
+            if high - low <= 1 {
+                break;
+            }
+            let mid = low + (high - low) / 2;
+            if fills(mid)? {
+                low = mid;
+            } else {
+                high = mid;
+            }
+        }
+        low
+    };
+    if amount == 0 {
+        return Ok(None);
+    }
+
+    let return_amount = execute_swap(
+        &mut deps.storage,
+        offer_index,
+        ask_index,
+        Uint128(amount),
+        now,
+    )?;
+    order.offer_remaining = (order.offer_remaining - Uint128(amount))?;
+    order.received += return_amount;
+    let history = swap_history(pair, offer_index, ask_index, Uint128(amount), return_amount)?;
+    record_history(&mut deps.storage, &order.owner, block, history)?;
+
+    debit(&mut deps.storage, ask_index, return_amount)?;
+    let owner = deps.api.human_address(&order.owner)?;
+    Ok(Some(transfer(
+        &pair.assets[ask_index],
+        owner,
+        return_amount,
+    )?))
+}
+
+/// Fills the open orders offering the asset at `offer_index` for the one at `ask_index`, best
+/// priced first, until one doesn't fill completely. Returns the transfers to their owners and
+/// the ids of the orders filled.
+fn fill_crossed_orders<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    pair: &Pair,
+    offer_index: usize,
+    ask_index: usize,
+    block: &BlockInfo,
+) -> StdResult<(Vec<CosmosMsg>, Vec<u64>)> {
+    let candidates: Vec<u64> = order_book_read(&deps.storage)
+        .load()?
+        .open
+        .iter()
+        .filter(|open| {
+            open.offer_index as usize == offer_index && open.ask_index as usize == ask_index
+        })
+        .take(MAX_FILLS_PER_SWAP)
+        .map(|open| open.id)
+        .collect();
+
+    let mut messages = vec![];
+    let mut filled = vec![];
+    for order_id in candidates {
+        let mut order = orders_read(&deps.storage).load(&order_id.to_be_bytes())?;
+        match fill_order(deps, pair, &mut order, block)? {
+            Some(transfer) => messages.push(transfer),
+            None => break,
+        }
+        filled.push(order_id);
+        orders(&mut deps.storage).save(&order_id.to_be_bytes(), &order)?;
+        if !order.offer_remaining.is_zero() {
+            break;
+        }
+        close_order(&mut deps.storage, order_id, &order.owner)?;
+    }
+
+    Ok((messages, filled))
+}
+
+fn load_open_order<S: Storage>(storage: &S, order_id: u64) -> StdResult<LimitOrder> {
+    let order = orders_read(storage)
+        .may_load(&order_id.to_be_bytes())?
+        .ok_or_else(|| StdError::generic_err(format!("No order {}", order_id)))?;
+    if order.offer_remaining.is_zero() {
+        return Err(StdError::generic_err(format!(
+            "Order {} is closed",
+            order_id
+        )));
+    }
+    Ok(order)
+}
+
+/// Takes a filled or cancelled order off the book and its owner's open orders.
+fn close_order<S: Storage>(storage: &mut S, order_id: u64, owner: &CanonicalAddr) -> StdResult<()> {
+    let mut book = order_book_read(storage).load()?;
+    book.open.retain(|open| open.id != order_id);
+    order_book(storage).save(&book)?;
+
+    let mut order_ids = owner_orders_read(storage)
+        .may_load(owner.as_slice())?
+        .unwrap_or_default();
+    order_ids.retain(|id| *id != order_id);
+    if order_ids.is_empty() {
+        owner_orders(storage).remove(owner.as_slice());
+        Ok(())
+    } else {
+        owner_orders(storage).save(owner.as_slice(), &order_ids)
+    }
+}
+
+pub fn try_flash_swap<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    amounts: Vec<Uint128>,
+    receiver: ContractLink,
+    msg: Binary,
+) -> StdResult<HandleResponse> {
+    if flash_swap_read(&deps.storage).may_load()?.is_some() {
+        return Err(StdError::generic_err("A flash swap is in progress"));
+    }
+
+    if let CurveState::Concentrated { .. } = curve_read(&deps.storage).load()? {
+        return Err(StdError::generic_err(
+            "Concentrated liquidity pairs don't support flash swaps",
+        ));
+    }
+    if swap_mode_read(&deps.storage).load()? != SwapMode::Instant {
+        return Err(StdError::generic_err(
+            "Batch auction pairs don't support flash swaps",
+        ));
+    }
+
+    let pair = config_read(&deps.storage).load()?;
+    let pool = reserves_read(&deps.storage).load()?;
+    if amounts.len() != pair.assets.len() {
+        return Err(StdError::generic_err("Expected one amount per asset"));
+    }
+    if amounts.iter().all(|amount| amount.is_zero()) {
+        return Err(StdError::generic_err("Flash swap amount must not be zero"));
+    }
+    if amounts
+        .iter()
+        .zip(pool.amounts.iter())
+        .any(|(amount, reserve)| amount >= reserve)
+    {
+        return Err(StdError::generic_err("Insufficient liquidity"));
+    }
+
+    flash_swap(&mut deps.storage).save(&FlashSwap {
+        amounts_out: amounts.clone(),
+        repaid: vec![Uint128::zero(); amounts.len()],
+    })?;
+    for (i, amount) in amounts.iter().enumerate() {
+        debit(&mut deps.storage, i, *amount)?;
+    }
+
+    let mut messages = vec![];
+    for (meta, amount) in pair.assets.iter().zip(amounts.iter()) {
+        if amount.is_zero() {
+            continue;
+        }
+        let (contract_addr, token_code_hash) = token_info(meta);
+        messages.push(snip20::transfer_msg(
+            receiver.address.clone(),
+            *amount,
+            None,
+            None,
+            BLOCK_SIZE,
+            token_code_hash,
+            contract_addr,
+        )?);
+    }
+    messages.push(CosmosMsg::Wasm(WasmMsg::Execute {
+        contract_addr: receiver.address,
+        callback_code_hash: receiver.code_hash,
+        msg: to_binary(&FlashSwapReceiverMsg::FlashSwapCallback {
+            sender: env.message.sender,
+            amounts: amounts.clone(),
+            msg,
+        })?,
+        send: vec![],
+    }));
+    messages.push(CosmosMsg::Wasm(WasmMsg::Execute {
+        contract_addr: env.contract.address,
+        callback_code_hash: env.contract_code_hash,
+        msg: to_binary(&HandleMsg::FinishFlashSwap {})?,
+        send: vec![],
+    }));
+
+    Ok(HandleResponse {
+        messages,
+        log: vec![log("action", "flash_swap"), log("amounts", join(&amounts))],
+        data: None,
+    })
+}
+
+pub fn try_repay_flash_swap<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    amount: Uint128,
+) -> StdResult<HandleResponse> {
+    let pair = config_read(&deps.storage).load()?;
+    let mut state = flash_swap_read(&deps.storage)
+        .may_load()?
+        .ok_or_else(|| StdError::generic_err("No flash swap in progress"))?;
+
+    let index = pair
+        .assets
+        .iter()
+        .position(|asset| token_info(asset).0 == env.message.sender)
+        .ok_or_else(StdError::unauthorized)?;
+    state.repaid[index] += amount;
+    flash_swap(&mut deps.storage).save(&state)?;
+
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![log("action", "repay_flash_swap")],
+        data: None,
+    })
+}
+
+pub fn try_finish_flash_swap<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+) -> StdResult<HandleResponse> {
+    if env.message.sender != env.contract.address {
+        return Err(StdError::unauthorized());
+    }
+
+    let state = flash_swap_read(&deps.storage)
+        .may_load()?
+        .ok_or_else(|| StdError::generic_err("No flash swap in progress"))?;
+    flash_swap(&mut deps.storage).remove();
+
+    let mut pool = reserves_read(&deps.storage).load()?;
+    let reserves_before = pool.amounts.clone();
+    for i in 0..pool.amounts.len() {
+        pool.amounts[i] = (pool.amounts[i] + state.repaid[i] - state.amounts_out[i])?;
+    }
+    let repaid = match curve_read(&deps.storage).load()? {
+        CurveState::ConstantProduct => {
+            flash_swap_repaid(&reserves_before, &pool.amounts, &state.repaid)?
+        }
+        CurveState::StableSwap { decimals, amp } => stable::flash_swap_repaid(
+            amp.at(env.block.time),
+            &reserves_before,
+            &pool.amounts,
+            &state.repaid,
+            &stable::scales(&decimals),
+        )?,
+        CurveState::Weighted { weights } => {
+            weighted::flash_swap_repaid(&reserves_before, &pool.amounts, &state.repaid, &weights)?
+        }
+        CurveState::Concentrated { .. } => false,
+    };
+    if !repaid {
+        return Err(StdError::generic_err(
+            "Flash swap was not repaid, including the fee",
+        ));
+    }
+    accrue_fees(&mut deps.storage, &state.repaid, pool.total_share)?;
+    save_reserves(&mut deps.storage, &pool, env.block.time)?;
+
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "finish_flash_swap"),
+            log("repaid", join(&state.repaid)),
+        ],
+        data: None,
+    })
+}
+
+pub fn try_ramp_amplification<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    future_amp: u64,
+    future_time: u64,
+) -> StdResult<HandleResponse> {
+    if factory_read(&deps.storage).load()?.address != env.message.sender {
+        return Err(StdError::unauthorized());
+    }
+
+    let (decimals, amp) = match curve_read(&deps.storage).load()? {
+        CurveState::StableSwap { decimals, amp } => (decimals, amp),
+        _ => return Err(StdError::generic_err("Not a StableSwap pair")),
+    };
+
+    validate_amp(future_amp)?;
+    let now = env.block.time;
+    if future_time < now + stable::MIN_RAMP_TIME {
+        return Err(StdError::generic_err(format!(
+            "Ramps must last at least {} seconds",
+            stable::MIN_RAMP_TIME
+        )));
+    }
+    let current_amp = amp.at(now);
+    if future_amp > current_amp * stable::MAX_AMP_CHANGE
+        || future_amp * stable::MAX_AMP_CHANGE < current_amp
+    {
+        return Err(StdError::generic_err(format!(
+            "Amplification can change at most {}x per ramp",
+            stable::MAX_AMP_CHANGE
+        )));
+    }
+
+    curve(&mut deps.storage).save(&CurveState::StableSwap {
+        decimals,
+        amp: Amplification {
+            initial: current_amp,
+            future: future_amp,
+            initial_time: now,
+            future_time,
+        },
+    })?;
+
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "ramp_amplification"),
+            log("future_amp", future_amp),
+            log("future_time", future_time),
+        ],
+        data: None,
+    })
+}
+
+pub fn try_add_liquidity<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    assets: Vec<Asset>,
+    receiver: Option<HumanAddr>,
+    min_share: Option<Uint128>,
+) -> StdResult<HandleResponse> {
+    let curve_state = curve_read(&deps.storage).load()?;
+    if let CurveState::Concentrated { .. } = curve_state {
+        return Err(StdError::generic_err(
+            "Concentrated liquidity is provided with OpenPosition",
+        ));
+    }
+
+    let pair = config_read(&deps.storage).load()?;
+    let mut pool = reserves_read(&deps.storage).load()?;
+
+    // Deposits in the order of `pair.assets`, whatever order they were given in.
+    let (deposits, share) = match (&curve_state, assets.as_slice()) {
+        (CurveState::Weighted { weights }, [single]) if !pool.total_share.is_zero() => {
+            let index = asset_index(&pair, &single.meta)?;
+            let share = weighted::share_for_single_deposit(
+                pool.amounts[index],
+                pool.total_share,
+                single.amount,
+                weights[index],
+            )?;
+            let mut deposits = vec![Uint128::zero(); pair.assets.len()];
+            deposits[index] = single.amount;
+            // The fee goes to the shares that were there before.
+            let swapped = weighted::swapped_part(single.amount, weights[index])?;
+            let mut fee_amounts = vec![Uint128::zero(); pair.assets.len()];
+            fee_amounts[index] = swapped;
+            accrue_fees(&mut deps.storage, &fee_amounts, pool.total_share)?;
+            (deposits, share)
+        }
+        _ => {
+            let mut deposits = vec![];
+            for meta in pair.assets.iter() {
+                let deposit = assets
+                    .iter()
+                    .find(|asset| asset.meta == *meta)
+                    .map(|asset| asset.amount)
+                    .ok_or_else(|| StdError::generic_err(format!("Missing deposit of {}", meta)))?;
+                if deposit.is_zero() {
+                    return Err(StdError::generic_err("Deposits must not be zero"));
+                }
+                deposits.push(deposit);
+            }
+            let share = compute_share(&deposits, &pool.amounts, pool.total_share)?;
+            (deposits, share)
+        }
+    };
+    if share.is_zero() {
+        return Err(StdError::generic_err("Deposit too small"));
+    }
+    if let Some(min_share) = min_share {
+        if share < min_share {
+            return Err(StdError::generic_err(format!(
+                "Deposit mints {} shares, less than the minimum {}",
+                share, min_share
+            )));
+        }
+    }
+
+    let receiver = receiver.unwrap_or_else(|| env.message.sender.clone());
+    let receiver_raw = deps.api.canonical_address(&receiver)?;
+    add_share(&mut deps.storage, &receiver_raw, share)?;
+
+    for (amount, deposit) in pool.amounts.iter_mut().zip(deposits.iter()) {
+        *amount += *deposit;
+    }
+    pool.total_share += share;
+    save_reserves(&mut deps.storage, &pool, env.block.time)?;
+    for (i, deposit) in deposits.iter().enumerate() {
+        credit(&mut deps.storage, i, *deposit)?;
+    }
+    let history = HistoryAction::AddLiquidity {
+        amounts: deposits.clone(),
+        share,
+        position_id: None,
+    };
+    record_history(&mut deps.storage, &receiver_raw, &env.block, history)?;
+
+    let mut messages = vec![];
+    for (i, meta) in pair.assets.iter().enumerate() {
+        if deposits[i].is_zero() {
+            continue;
+        }
+        let (contract_addr, token_code_hash) = token_info(meta);
+        messages.push(snip20::transfer_from_msg(
+            env.message.sender.clone(),
+            env.contract.address.clone(),
+            deposits[i],
+            None,
+            None,
+            BLOCK_SIZE,
+            token_code_hash,
+            contract_addr,
+        )?);
+    }
+
+    Ok(HandleResponse {
+        messages,
+        log: vec![
+            log("action", "add_liquidity"),
+            log("receiver", receiver),
+            log("share", share),
+        ],
+        data: None,
+    })
+}
+
+pub fn try_open_position<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    lower_tick: i32,
+    upper_tick: i32,
+    amounts: Vec<Uint128>,
+    receiver: Option<HumanAddr>,
+) -> StdResult<HandleResponse> {
+    let tick_spacing = match curve_read(&deps.storage).load()? {
+        CurveState::Concentrated { tick_spacing } => tick_spacing as i32,
+        _ => return Err(StdError::generic_err("Not a concentrated liquidity pair")),
+    };
+    if lower_tick >= upper_tick || lower_tick % tick_spacing != 0 || upper_tick % tick_spacing != 0
+    {
+        return Err(StdError::generic_err(format!(
+            "Ticks must be increasing multiples of {}",
+            tick_spacing
+        )));
+    }
+    if amounts.len() != 2 {
+        return Err(StdError::generic_err("Expected one amount per asset"));
+    }
+
+    let pair = config_read(&deps.storage).load()?;
+    let mut state = concentrated_read(&deps.storage).load()?;
+    let liquidity = concentrated::liquidity_for_amounts(&state, lower_tick, upper_tick, &amounts)?;
+    if liquidity == 0 {
+        return Err(StdError::generic_err("Deposit too small"));
+    }
+
+    let receiver = receiver.unwrap_or_else(|| env.message.sender.clone());
+    let mut position = RangePosition {
+        owner: deps.api.canonical_address(&receiver)?,
+        lower_tick,
+        upper_tick,
+        liquidity: Uint128::zero(),
+        fee_growth_inside_last: vec![Uint128::zero(); 2],
+        fees_owed: vec![Uint128::zero(); 2],
+    };
+    let deposits = concentrated::update_position(
+        &mut deps.storage,
+        &mut state,
+        &mut position,
+        liquidity,
+        true,
+    )?;
+
+    let position_id = state.position_count;
+    state.position_count += 1;
+    positions(&mut deps.storage).save(&position_id.to_be_bytes(), &position)?;
+    concentrated(&mut deps.storage).save(&state)?;
+    let history = HistoryAction::AddLiquidity {
+        amounts: deposits.clone(),
+        share: Uint128(liquidity),
+        position_id: Some(position_id),
+    };
+    record_history(&mut deps.storage, &position.owner, &env.block, history)?;
+
+    let mut pool = reserves_read(&deps.storage).load()?;
+    for (amount, deposit) in pool.amounts.iter_mut().zip(deposits.iter()) {
+        *amount += *deposit;
+    }
+    save_reserves(&mut deps.storage, &pool, env.block.time)?;
+    for (i, deposit) in deposits.iter().enumerate() {
+        credit(&mut deps.storage, i, *deposit)?;
+    }
+
+    let mut messages = vec![];
+    for (meta, deposit) in pair.assets.iter().zip(deposits.iter()) {
+        if deposit.is_zero() {
+            continue;
+        }
+        let (contract_addr, token_code_hash) = token_info(meta);
+        messages.push(snip20::transfer_from_msg(
+            env.message.sender.clone(),
+            env.contract.address.clone(),
+            *deposit,
+            None,
+            None,
+            BLOCK_SIZE,
+            token_code_hash,
+            contract_addr,
+        )?);
+    }
+
+    Ok(HandleResponse {
+        messages,
+        log: vec![
+            log("action", "open_position"),
+            log("position_id", position_id),
+            log("receiver", receiver),
+            log("liquidity", liquidity),
+            log("amounts", join(&deposits)),
+        ],
+        data: None,
+    })
+}
+
+pub fn try_withdraw_position<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    position_id: u64,
+    liquidity: Uint128,
+) -> StdResult<HandleResponse> {
+    let (mut state, mut position) = load_position(deps, &env, position_id)?;
+    if liquidity.is_zero() || liquidity > position.liquidity {
+        return Err(StdError::generic_err(format!(
+            "Insufficient liquidity: {} available",
+            position.liquidity
+        )));
+    }
+
+    let mut amounts = concentrated::update_position(
+        &mut deps.storage,
+        &mut state,
+        &mut position,
+        liquidity.u128(),
+        false,
+    )?;
+    for (amount, fee) in amounts.iter_mut().zip(position.fees_owed.iter()) {
+        *amount += *fee;
+    }
+    position.fees_owed = vec![Uint128::zero(); 2];
+
+    let key = position_id.to_be_bytes();
+    if position.liquidity.is_zero() {
+        positions(&mut deps.storage).remove(&key);
+    } else {
+        positions(&mut deps.storage).save(&key, &position)?;
+    }
+    concentrated(&mut deps.storage).save(&state)?;
+    let history = HistoryAction::RemoveLiquidity {
+        amounts: amounts.clone(),
+        share: liquidity,
+        position_id: Some(position_id),
+    };
+    record_history(&mut deps.storage, &position.owner, &env.block, history)?;
+    let messages = pay_out(&mut deps.storage, &env, &amounts)?;
+
+    Ok(HandleResponse {
+        messages,
+        log: vec![
+            log("action", "withdraw_position"),
+            log("position_id", position_id),
+            log("liquidity", liquidity),
+            log("amounts", join(&amounts)),
+        ],
+        data: None,
+    })
+}
+
+pub fn try_collect_fees<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    position_id: u64,
+) -> StdResult<HandleResponse> {
+    let (mut state, mut position) = load_position(deps, &env, position_id)?;
+
+    // Updating by no liquidity only credits the fees earned so far.
+    concentrated::update_position(&mut deps.storage, &mut state, &mut position, 0, true)?;
+    let fees = position.fees_owed.clone();
+    position.fees_owed = vec![Uint128::zero(); 2];
+
+    positions(&mut deps.storage).save(&position_id.to_be_bytes(), &position)?;
+    concentrated(&mut deps.storage).save(&state)?;
+    let history = HistoryAction::CollectFees {
+        position_id,
+        amounts: fees.clone(),
+    };
+    record_history(&mut deps.storage, &position.owner, &env.block, history)?;
+    let messages = pay_out(&mut deps.storage, &env, &fees)?;
+
+    Ok(HandleResponse {
+        messages,
+        log: vec![
+            log("action", "collect_fees"),
+            log("position_id", position_id),
+            log("fees", join(&fees)),
+        ],
+        data: None,
+    })
+}
+
+/// Loads the pool and the sender's position `position_id`.
+fn load_position<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    env: &Env,
+    position_id: u64,
+) -> StdResult<(ConcentratedPool, RangePosition)> {
+    let state = concentrated_read(&deps.storage).load()?;
+    let position = positions_read(&deps.storage)
+        .may_load(&position_id.to_be_bytes())?
+        .ok_or_else(|| StdError::generic_err(format!("No position {}", position_id)))?;
+    if position.owner != deps.api.canonical_address(&env.message.sender)? {
+        return Err(StdError::unauthorized());
+    }
+
+    Ok((state, position))
+}
+
+/// Takes `amounts` out of the reserves and sends them to the sender.
+fn pay_out<S: Storage>(
+    storage: &mut S,
+    env: &Env,
+    amounts: &[Uint128],
+) -> StdResult<Vec<CosmosMsg>> {
+    let pair = config_read(storage).load()?;
+    let mut pool = reserves_read(storage).load()?;
+    for (reserve, amount) in pool.amounts.iter_mut().zip(amounts.iter()) {
+        *reserve = (*reserve - *amount)?;
+    }
+    save_reserves(storage, &pool, env.block.time)?;
+    for (i, amount) in amounts.iter().enumerate() {
+        debit(storage, i, *amount)?;
+    }
+
+    let mut messages = vec![];
+    for (meta, amount) in pair.assets.iter().zip(amounts.iter()) {
+        if amount.is_zero() {
+            continue;
+        }
+        let (contract_addr, token_code_hash) = token_info(meta);
+        messages.push(snip20::transfer_msg(
+            env.message.sender.clone(),
+            *amount,
+            None,
+            None,
+            BLOCK_SIZE,
+            token_code_hash,
+            contract_addr,
+        )?);
+    }
+    Ok(messages)
+}
+
+pub fn try_remove_liquidity<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    share: Uint128,
+    asset: Option<AssetMeta>,
+) -> StdResult<HandleResponse> {
+    let pair = config_read(&deps.storage).load()?;
+    let owner_raw = deps.api.canonical_address(&env.message.sender)?;
+    let single = match asset {
+        Some(asset) => Some(asset_index(&pair, &asset)?),
+        None => None,
+    };
+
+    let amounts = withdraw(&mut deps.storage, &owner_raw, share, single, &env.block)?;
+
+    let mut messages = vec![];
+    for (i, meta) in pair.assets.iter().enumerate() {
+        if amounts[i].is_zero() {
+            continue;
+        }
+        let (contract_addr, token_code_hash) = token_info(meta);
+        messages.push(snip20::transfer_msg(
+            env.message.sender.clone(),
+            amounts[i],
+            None,
+            None,
+            BLOCK_SIZE,
+            token_code_hash,
+            contract_addr,
+        )?);
+    }
+
+    Ok(HandleResponse {
+        messages,
+        log: vec![
+            log("action", "remove_liquidity"),
+            log("share", share),
+            log("amounts", join(&amounts)),
+        ],
+        data: None,
+    })
+}
+
+pub fn try_create_viewing_key<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    entropy: String,
+) -> StdResult<HandleResponse> {
+    let seed = prng_seed_read(&deps.storage).load()?;
+    let (key, next_seed) = viewing_key::generate(&seed, &env, &entropy);
+    prng_seed(&mut deps.storage).save(&next_seed)?;
+    let owner = deps.api.canonical_address(&env.message.sender)?;
+    viewing_keys(&mut deps.storage).save(owner.as_slice(), &viewing_key::hash(&key))?;
+
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![log("action", "create_viewing_key")],
+        data: Some(to_binary(&HandleAnswer::CreateViewingKey { key })?),
+    })
+}
+
+pub fn try_set_viewing_key<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    key: String,
+) -> StdResult<HandleResponse> {
+    let owner = deps.api.canonical_address(&env.message.sender)?;
+    viewing_keys(&mut deps.storage).save(owner.as_slice(), &viewing_key::hash(&key))?;
+
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![log("action", "set_viewing_key")],
+        data: None,
+    })
+}
+
+pub fn try_revoke_permit<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    permit_name: String,
+) -> StdResult<HandleResponse> {
+    RevokedPermits::revoke_permit(
+        &mut deps.storage,
+        PREFIX_REVOKED_PERMITS,
+        &env.message.sender,
+        &permit_name,
+    );
+
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![log("action", "revoke_permit")],
+        data: None,
+    })
+}
+
+pub fn try_set_reserve_visibility<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    visibility: ReserveVisibility,
+) -> StdResult<HandleResponse> {
+    if factory_read(&deps.storage).load()?.address != env.message.sender {
+        return Err(StdError::unauthorized());
+    }
+
+    match visibility {
+        ReserveVisibility::Exact => {}
+        _ if matches!(
+            curve_read(&deps.storage).load()?,
+            CurveState::Concentrated { .. }
+        ) =>
+        {
+            return Err(StdError::generic_err(
+                "Concentrated liquidity pairs reveal their liquidity through ConcentratedPool",
+            ))
+        }
+        ReserveVisibility::Rounded { significant_digits } => {
+            if significant_digits == 0 || significant_digits > MAX_SIGNIFICANT_DIGITS {
+                return Err(StdError::generic_err(format!(
+                    "Reserves can be rounded to between 1 and {} significant digits",
+                    MAX_SIGNIFICANT_DIGITS
+                )));
+            }
+        }
+        ReserveVisibility::Delayed { blocks } => {
+            if blocks == 0 || blocks > MAX_RESERVE_DELAY_BLOCKS {
+                return Err(StdError::generic_err(format!(
+                    "Reserves can be delayed by between 1 and {} blocks",
+                    MAX_RESERVE_DELAY_BLOCKS
+                )));
+            }
+        }
+    }
+
+    checkpoint_prices(&mut deps.storage, env.block.time)?;
+    match visibility {
+        // A pair that already delays its reserves keeps showing the delayed ones.
+        ReserveVisibility::Delayed { .. } => {
+            if delayed_reserves_read(&deps.storage).may_load()?.is_none() {
+                let visible = reserves_read(&deps.storage).load()?;
+                delayed_reserves(&mut deps.storage).save(&DelayedReserves {
+                    visible,
+                    pending: vec![],
+                })?;
+            }
+        }
+        _ => delayed_reserves(&mut deps.storage).remove(),
+    }
+    reserve_visibility(&mut deps.storage).save(&visibility)?;
+
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![log("action", "set_reserve_visibility")],
+        data: None,
+    })
+}
+
+pub fn try_set_swap_limits<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    limits: SwapLimits,
+) -> StdResult<HandleResponse> {
+    if factory_read(&deps.storage).load()?.address != env.message.sender {
+        return Err(StdError::unauthorized());
+    }
+
+    let caps = std::iter::once(limits.max_price_impact_bps).chain(limits.max_trade_size_bps);
+    for cap in caps {
+        if cap == 0 || cap > MAX_BPS {
+            return Err(StdError::generic_err(format!(
+                "Swap limits must be between 1 and {} basis points",
+                MAX_BPS
+            )));
+        }
+    }
+    swap_limits(&mut deps.storage).save(&limits)?;
+
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![log("action", "set_swap_limits")],
+        data: None,
+    })
+}
+
+pub fn try_set_status<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    new_status: ContractStatus,
+) -> StdResult<HandleResponse> {
+    if factory_read(&deps.storage).load()?.address != env.message.sender {
+        return Err(StdError::unauthorized());
+    }
+    status(&mut deps.storage).save(&new_status)?;
+
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![
+            log("action", "set_status"),
+            log("status", format!("{:?}", new_status)),
+        ],
+        data: None,
+    })
+}
+
+/// Withdraws all of `owner`'s liquidity and provides it to `new_pair` in their name, by
+/// granting `new_pair` an allowance on each token and calling its `AddLiquidity`.
+pub fn try_migrate_liquidity<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    owner: HumanAddr,
+    new_pair: ContractLink,
+    min_share: Option<Uint128>,
+) -> StdResult<HandleResponse> {
+    if factory_read(&deps.storage).load()?.address != env.message.sender {
+        return Err(StdError::unauthorized());
+    }
+
+    let pair = config_read(&deps.storage).load()?;
+    let owner_raw = deps.api.canonical_address(&owner)?;
+    let share = shares_read(&deps.storage)
+        .may_load(owner_raw.as_slice())?
+        .unwrap_or_else(Uint128::zero);
+    if share.is_zero() {
+        return Err(StdError::generic_err("No liquidity to migrate"));
+    }
+
+    let amounts = withdraw(&mut deps.storage, &owner_raw, share, None, &env.block)?;
+
+    let mut messages: Vec<CosmosMsg> = vec![];
+    for (i, meta) in pair.assets.iter().enumerate() {
+        let (contract_addr, token_code_hash) = token_info(meta);
+        messages.push(snip20::increase_allowance_msg(
+            new_pair.address.clone(),
+            amounts[i],
+            None,
+            None,
+            BLOCK_SIZE,
+            token_code_hash,
+            contract_addr,
+        )?);
+    }
+    messages.push(
+        WasmMsg::Execute {
+            contract_addr: new_pair.address.clone(),
+            callback_code_hash: new_pair.code_hash,
+            msg: to_binary(&HandleMsg::AddLiquidity {
+                assets: pair
+                    .assets
+                    .iter()
+                    .zip(amounts.iter())
+                    .map(|(meta, amount)| Asset {
+                        meta: meta.clone(),
+                        amount: *amount,
+                    })
+                    .collect(),
+                receiver: Some(owner.clone()),
+                min_share,
+                padding: None,
+            })?,
+            send: vec![],
+        }
+        .into(),
+    );
+
+    Ok(HandleResponse {
+        messages,
+        log: vec![
+            log("action", "migrate_liquidity"),
+            log("owner", owner),
+            log("new_pair", new_pair.address),
+            log("share", share),
+        ],
+        data: None,
+    })
+}
+
+/// Burns `share` of `owner`'s liquidity and returns the reserve amounts it redeemed for, which
+/// the caller pays out. A weighted pool redeems it for the asset at `single` alone if given.
+fn withdraw<S: Storage>(
+    storage: &mut S,
+    owner: &CanonicalAddr,
+    share: Uint128,
+    single: Option<usize>,
+    block: &BlockInfo,
+) -> StdResult<Vec<Uint128>> {
+    let mut pool = reserves_read(storage).load()?;
+    let balance = shares_read(storage)
+        .may_load(owner.as_slice())?
+        .unwrap_or_else(Uint128::zero);
+    if share.is_zero() || share > balance {
+        return Err(StdError::generic_err(format!(
+            "Insufficient liquidity: {} available",
+            balance
+        )));
+    }
+
+    let (amounts, swapped) = match (single, curve_read(storage).load()?) {
+        (None, _) => (
+            compute_withdrawal(share, &pool.amounts, pool.total_share)?,
+            None,
+        ),
+        (Some(index), CurveState::Weighted { weights }) => {
+            let mut amounts = vec![Uint128::zero(); pool.amounts.len()];
+            amounts[index] = weighted::single_withdrawal(
+                pool.amounts[index],
+                pool.total_share,
+                share,
+                weights[index],
+            )?;
+            let mut swapped = amounts.clone();
+            swapped[index] = weighted::swapped_part(amounts[index], weights[index])?;
+            (amounts, Some(swapped))
+        }
+        (Some(_), _) => {
+            return Err(StdError::generic_err(
+                "Only weighted pairs redeem shares for a single asset",
+            ))
+        }
+    };
+
+    let mut fees = lp_fees_at(storage, owner, balance)?;
+    if share == balance {
+        fees.fees_accrued = vec![Uint128::zero(); pool.amounts.len()];
+    }
+    lp_fees(storage).save(owner.as_slice(), &fees)?;
+    shares(storage).save(owner.as_slice(), &(balance - share)?)?;
+    for (reserve, amount) in pool.amounts.iter_mut().zip(amounts.iter()) {
+        *reserve = (*reserve - *amount)?;
+    }
+    pool.total_share = (pool.total_share - share)?;
+    save_reserves(storage, &pool, block.time)?;
+    // The fee goes to the shares that are left.
+    if let Some(swapped) = swapped {
+        accrue_fees(storage, &swapped, pool.total_share)?;
+    }
+    let supply = share_supply_read(storage).load()?;
+    share_supply(storage).save(&(supply - share)?)?;
+    for (i, amount) in amounts.iter().enumerate() {
+        debit(storage, i, *amount)?;
+    }
+    let history = HistoryAction::RemoveLiquidity {
+        amounts: amounts.clone(),
+        share,
+        position_id: None,
+    };
+    record_history(storage, owner, block, history)?;
+
+    Ok(amounts)
+}
+
+/// The reserves as the pair's `ReserveVisibility` shows them publicly.
+fn public_reserves<S: Storage>(storage: &S) -> StdResult<Reserves> {
+    match reserve_visibility_read(storage).load()? {
+        ReserveVisibility::Exact => reserves_read(storage).load(),
+        ReserveVisibility::Rounded { significant_digits } => {
+            let mut pool = reserves_read(storage).load()?;
+            for amount in pool.amounts.iter_mut() {
+                *amount = round_down(*amount, significant_digits);
+            }
+            pool.total_share = round_down(pool.total_share, significant_digits);
+            Ok(pool)
+        }
+        ReserveVisibility::Delayed { .. } => Ok(delayed_reserves_read(storage).load()?.visible),
+    }
+}
+
+/// `amount` with all but its `digits` leading digits zeroed.
+fn round_down(amount: Uint128, digits: u8) -> Uint128 {
+    let limit = 10u128.pow(digits as u32);
+    let mut scale = 1;
+    while amount.u128() / scale >= limit {
+        scale *= 10;
+    }
+    Uint128(amount.u128() / scale * scale)
+}
+
+/// Records the reserves of `block` for a `ReserveVisibility::Delayed` pair, and shows the
+/// newest ones that have become old enough.
+fn publish_reserves<S: Storage>(storage: &mut S, block: &BlockInfo) -> StdResult<()> {
+    let blocks = match reserve_visibility_read(storage).load()? {
+        ReserveVisibility::Delayed { blocks } => blocks,
+        _ => return Ok(()),
+    };
+
+    let mut delayed = delayed_reserves_read(storage).load()?;
+    let reserves = reserves_read(storage).load()?;
+    match delayed.pending.last_mut() {
+        Some(last) if last.height == block.height => last.reserves = reserves,
+        _ => delayed.pending.push(ReserveSnapshot {
+            height: block.height,
+            reserves,
+        }),
+    }
+
+    if let Some(cutoff) = block.height.checked_sub(blocks) {
+        let old_enough = delayed
+            .pending
+            .iter()
+            .take_while(|snapshot| snapshot.height <= cutoff)
+            .count();
+        if let Some(newest) = delayed.pending.drain(..old_enough).next_back() {
+            checkpoint_prices(storage, block.time)?;
+            delayed.visible = newest.reserves;
+        }
+    }
+    delayed_reserves(storage).save(&delayed)
+}
+
+/// Index of the asset whose token sent the pair a `Receive`.
+fn offer_index(pair: &Pair, env: &Env) -> StdResult<usize> {
+    pair.assets
+        .iter()
+        .position(|asset| token_info(asset).0 == env.message.sender)
+        .ok_or_else(StdError::unauthorized)
+}
+
+/// Index of the asset a swap of the asset at `offer_index` returns. Two-asset pairs don't need
+/// `ask_asset`.
+fn asset_index(pair: &Pair, meta: &AssetMeta) -> StdResult<usize> {
+    pair.assets
+        .iter()
+        .position(|asset| asset == meta)
+        .ok_or_else(|| StdError::generic_err(format!("{} is not in this pair", meta)))
+}
+
+fn ask_index(pair: &Pair, offer_index: usize, ask_asset: Option<AssetMeta>) -> StdResult<usize> {
+    let ask_asset = match ask_asset {
+        Some(ask_asset) => ask_asset,
+        None if pair.assets.len() == 2 => return Ok(1 - offer_index),
+        None => {
+            return Err(StdError::generic_err(
+                "ask_asset is required for pools of more than two assets",
+            ))
+        }
+    };
+
+    match pair.assets.iter().position(|asset| *asset == ask_asset) {
+        Some(index) if index != offer_index => Ok(index),
+        Some(_) => Err(StdError::generic_err("Cannot swap an asset for itself")),
+        None => Err(StdError::generic_err(format!(
+            "{} is not in this pair",
+            ask_asset
+        ))),
+    }
+}
+
+/// Amount of the asset at `ask_index` returned for `offer_amount` of the one at `offer_index`.
+fn swap_return<S: Storage>(
+    storage: &S,
+    curve_state: &CurveState,
+    pool: &Reserves,
+    offer_index: usize,
+    ask_index: usize,
+    offer_amount: Uint128,
+    now: u64,
+) -> StdResult<Uint128> {
+    match curve_state {
+        CurveState::ConstantProduct => compute_swap(
+            pool.amounts[offer_index],
+            pool.amounts[ask_index],
+            offer_amount,
+        ),
+        CurveState::StableSwap { decimals, amp } => stable::compute_swap(
+            amp.at(now),
+            &pool.amounts,
+            offer_index,
+            ask_index,
+            offer_amount,
+            &stable::scales(decimals),
+        ),
+        CurveState::Weighted { weights } => weighted::compute_swap(
+            pool.amounts[offer_index],
+            pool.amounts[ask_index],
+            offer_amount,
+            [weights[offer_index], weights[ask_index]],
+        ),
+        CurveState::Concentrated { .. } => {
+            let mut state = concentrated_read(storage).load()?;
+            let (return_amount, _) =
+                concentrated::swap(storage, &mut state, offer_index == 0, offer_amount.u128())?;
+            Ok(Uint128(return_amount))
+        }
+    }
+}
+
+/// Fails if swapping `offer_amount` of the asset at `offer_index` for the one at `ask_index` is
+/// more of its reserve, or would move its price by more, than the pair's swap limits allow.
+fn enforce_swap_limits<S: Storage>(
+    storage: &S,
+    offer_index: usize,
+    ask_index: usize,
+    offer_amount: Uint128,
+    now: u64,
+) -> StdResult<()> {
+    let limits = swap_limits_read(storage).load()?;
+    enforce_trade_size(storage, &limits, offer_index, offer_amount)?;
+    if limits.max_price_impact_bps == MAX_BPS {
+        return Ok(());
+    }
+
+    let pool = reserves_read(storage).load()?;
+    let curve_state = curve_read(storage).load()?;
+    let price_before = scaled_price(spot_price(
+        storage,
+        &curve_state,
+        &pool,
+        offer_index,
+        ask_index,
+        now,
+    )?)?;
+    let price_after = match curve_state {
+        CurveState::Concentrated { .. } => {
+            let mut state = concentrated_read(storage).load()?;
+            concentrated::swap(storage, &mut state, offer_index == 0, offer_amount.u128())?;
+            concentrated_price(state.sqrt_price, offer_index)?
+        }
+        _ => {
+            let return_amount = swap_return(
+                storage,
+                &curve_state,
+                &pool,
+                offer_index,
+                ask_index,
+                offer_amount,
+                now,
+            )?;
+            let mut after = pool;
+            after.amounts[offer_index] += offer_amount;
+            after.amounts[ask_index] = (after.amounts[ask_index] - return_amount)?;
+            spot_price(storage, &curve_state, &after, offer_index, ask_index, now)?
+        }
+    };
+    enforce_price_impact(&limits, price_before, scaled_price(price_after)?)
+}
+
+/// Fails if `offer_amount` of the asset at `offer_index` is more of its reserve than `limits`
+/// allow.
+fn enforce_trade_size<S: Storage>(
+    storage: &S,
+    limits: &SwapLimits,
+    offer_index: usize,
+    offer_amount: Uint128,
+) -> StdResult<()> {
+    let max_bps = match limits.max_trade_size_bps {
+        Some(max_bps) => max_bps,
+        None => return Ok(()),
+    };
+    let reserve = reserves_read(storage).load()?.amounts[offer_index];
+    let max_amount = mul_div(reserve.u128(), max_bps as u128, MAX_BPS as u128)?;
+    if offer_amount.u128() > max_amount {
+        return Err(StdError::generic_err(format!(
+            "Swap of {} is above the maximum trade size of {}",
+            offer_amount, max_amount
+        )));
+    }
+    Ok(())
+}
+
+/// Fails if a swap moved the offer asset's price from `price_before` to `price_after` by more
+/// than `limits` allow.
+fn enforce_price_impact(
+    limits: &SwapLimits,
+    price_before: u128,
+    price_after: u128,
+) -> StdResult<()> {
+    let impact = mul_div(
+        price_before.saturating_sub(price_after),
+        MAX_BPS as u128,
+        price_before,
+    )?;
+    if impact > limits.max_price_impact_bps as u128 {
+        return Err(StdError::generic_err(format!(
+            "Swap moves the price by {} bps, more than the {} bps allowed",
+            impact, limits.max_price_impact_bps
+        )));
+    }
+    Ok(())
+}
+
+fn validate_amp(amp: u64) -> StdResult<()> {
+    if !(stable::MIN_AMP..=stable::MAX_AMP).contains(&amp) {
+        return Err(StdError::generic_err(format!(
+            "Amplification must be between {} and {}",
+            stable::MIN_AMP,
+            stable::MAX_AMP
+        )));
+    }
+    Ok(())
+}
+
+/// Saves `pool`, first accumulating the prices of the reserves it replaces. Reserves are locked
+/// while a flash swap is in progress.
+fn save_reserves<S: Storage>(storage: &mut S, pool: &Reserves, now: u64) -> StdResult<()> {
+    if flash_swap_read(storage).may_load()?.is_some() {
+        return Err(StdError::generic_err("A flash swap is in progress"));
+    }
+
+    checkpoint_prices(storage, now)?;
+    reserves(storage).save(pool)
+}
+
+/// Accumulates the prices of the public reserves until `now`. Called before they change, so
+/// the accumulators never follow reserves the pair's `ReserveVisibility` hides.
+fn checkpoint_prices<S: Storage>(storage: &mut S, now: u64) -> StdResult<()> {
+    let pool = public_reserves(storage)?;
+    let mut state = prices_read(storage).load()?;
+    accumulate_prices(storage, &mut state, &pool, now)?;
+    if now > state.last_update {
+        state.last_update = now;
+        record_observation(storage, &mut state)?;
+    }
+
+    prices(storage).save(&state)
+}
+
+/// Adds the prices of `pool` from `state.last_update` until `now` to the accumulators, each
+/// asset priced in the next one.
+fn accumulate_prices<S: Storage>(
+    storage: &S,
+    state: &mut Prices,
+    pool: &Reserves,
+    now: u64,
+) -> StdResult<()> {
+    let curve_state = curve_read(storage).load()?;
+    let elapsed = now.saturating_sub(state.last_update);
+    let n = pool.amounts.len();
+    for i in 0..n {
+        let (base, quote) = spot_price(storage, &curve_state, pool, i, (i + 1) % n, now)?;
+        state.price_cumulative[i] =
+            accumulate_price(state.price_cumulative[i], base, quote, elapsed);
+    }
+    Ok(())
+}
+
+/// The spot price of the asset at `base` in the one at `quote`, as amounts of each that trade
+/// for one another. A weighted pool's is that of its reserves divided by their weights, a
+/// StableSwap pool's follows from its invariant, a concentrated liquidity pool keeps its own.
+fn spot_price<S: Storage>(
+    storage: &S,
+    curve_state: &CurveState,
+    pool: &Reserves,
+    base: usize,
+    quote: usize,
+    now: u64,
+) -> StdResult<(Uint128, Uint128)> {
+    match curve_state {
+        CurveState::StableSwap { decimals, amp } => stable::spot_price(
+            amp.at(now),
+            &pool.amounts,
+            base,
+            quote,
+            &stable::scales(decimals),
+        ),
+        CurveState::Weighted { weights } => Ok((
+            Uint128(mul(pool.amounts[base].u128(), weights[quote] as u128)?),
+            Uint128(mul(pool.amounts[quote].u128(), weights[base] as u128)?),
+        )),
+        CurveState::Concentrated { .. } => {
+            concentrated_price(concentrated_read(storage).load()?.sqrt_price, base)
+        }
+        CurveState::ConstantProduct => Ok((pool.amounts[base], pool.amounts[quote])),
+    }
+}
+
+/// `spot_price` of a concentrated liquidity pool at `sqrt_price`.
+fn concentrated_price(sqrt_price: Uint128, base: usize) -> StdResult<(Uint128, Uint128)> {
+    let sqrt_price = sqrt_price.u128();
+    let price = Uint128(mul_div(sqrt_price, sqrt_price, concentrated::Q64)?);
+    match base {
+        0 => Ok((Uint128(concentrated::Q64), price)),
+        _ => Ok((price, Uint128(concentrated::Q64))),
+    }
+}
+
+/// A `spot_price` as the amount of the quote asset per base asset, scaled by `PRICE_PRECISION`.
+fn scaled_price((base_amount, quote_amount): (Uint128, Uint128)) -> StdResult<u128> {
+    if base_amount.is_zero() {
+        return Err(StdError::generic_err("Pool has no liquidity"));
+    }
+    mul_div(quote_amount.u128(), PRICE_PRECISION, base_amount.u128())
+}
+
+/// Writes the current accumulators to the next ring buffer slot, overwriting the oldest.
+fn record_observation<S: Storage>(storage: &mut S, state: &mut Prices) -> StdResult<()> {
+    let slot = state.observation_count % OBSERVATION_CAPACITY;
+    observations(storage).save(
+        &slot.to_be_bytes(),
+        &Observation {
+            timestamp: state.last_update,
+            price_cumulative: state.price_cumulative.clone(),
+        },
+    )?;
+    state.observation_count = state.observation_count.wrapping_add(1);
+
+    Ok(())
+}
+
+/// Appends `action` to `owner`'s history.
+fn record_history<S: Storage>(
+    storage: &mut S,
+    owner: &CanonicalAddr,
+    block: &BlockInfo,
+    action: HistoryAction,
+) -> StdResult<()> {
+    let mut store = PrefixedStorage::multilevel(&[PREFIX_HISTORY, owner.as_slice()], storage);
+    let mut store = AppendStoreMut::attach_or_create(&mut store)?;
+    store.push(&HistoryEntry {
+        action,
+        block_height: block.height,
+        block_time: block.time,
+    })
+}
+
+fn swap_history(
+    pair: &Pair,
+    offer_index: usize,
+    ask_index: usize,
+    offer_amount: Uint128,
+    return_amount: Uint128,
+) -> StdResult<HistoryAction> {
+    Ok(HistoryAction::Swap {
+        offer_asset: pair.assets[offer_index].clone(),
+        offer_amount,
+        ask_asset: pair.assets[ask_index].clone(),
+        return_amount,
+        price: Uint128(mul_div(
+            return_amount.u128(),
+            PRICE_PRECISION,
+            offer_amount.u128(),
+        )?),
+    })
+}
+
+fn add_share<S: Storage>(storage: &mut S, owner: &CanonicalAddr, share: Uint128) -> StdResult<()> {
+    let balance = shares_read(storage)
+        .may_load(owner.as_slice())?
+        .unwrap_or_else(Uint128::zero);
+    let fees = lp_fees_at(storage, owner, balance)?;
+    lp_fees(storage).save(owner.as_slice(), &fees)?;
+    let supply = share_supply_read(storage).load()?;
+    share_supply(storage).save(&(supply + share))?;
+    shares(storage).save(owner.as_slice(), &(balance + share))
+}
+
+/// Adds `amount` of the asset at `index` the pair received to its tracked balance.
+fn credit<S: Storage>(storage: &mut S, index: usize, amount: Uint128) -> StdResult<()> {
+    let mut tracked = balances_read(storage).load()?;
+    tracked[index] += amount;
+    balances(storage).save(&tracked)
+}
+
+/// Takes `amount` of the asset at `index` the pair pays out from its tracked balance.
+fn debit<S: Storage>(storage: &mut S, index: usize, amount: Uint128) -> StdResult<()> {
+    let mut tracked = balances_read(storage).load()?;
+    tracked[index] = (tracked[index] - amount)
+        .map_err(|_| invariant_violation("paid out more than the pair holds"))?;
+    balances(storage).save(&tracked)
+}
+
+/// Checks what the handle that started from the `before` reserves, and a concentrated liquidity
+/// pool's `sqrt_price_before`, left behind, so a rounding bug in a curve aborts the transaction
+/// rather than leaking value out of the pool.
+fn check_invariants<S: Storage>(
+    storage: &S,
+    before: &Reserves,
+    sqrt_price_before: Option<Uint128>,
+    now: u64,
+) -> StdResult<()> {
+    let after = reserves_read(storage).load()?;
+    if after.total_share != share_supply_read(storage).load()? {
+        return Err(invariant_violation(
+            "the LP supply doesn't match the shares held",
+        ));
+    }
+    // Until a flash swap finishes, what it lent out is still counted in the reserves.
+    if flash_swap_read(storage).may_load()?.is_none() {
+        let tracked = balances_read(storage).load()?;
+        if after
+            .amounts
+            .iter()
+            .zip(tracked.iter())
+            .any(|(reserve, balance)| reserve > balance)
+        {
+            return Err(invariant_violation(
+                "the reserves exceed the pair's balances",
+            ));
+        }
+    }
+
+    // Concentrated liquidity pools have no shares at all, only their swaps are checked.
+    if let Some(sqrt_price) = sqrt_price_before {
+        return check_concentrated_swap(before, &after, sqrt_price);
+    }
+    // A first deposit or last withdrawal has nothing to compare with.
+    if after == *before || before.total_share.is_zero() || after.total_share.is_zero() {
+        return Ok(());
+    }
+    if after.total_share != before.total_share {
+        // A weighted pool's deposits and withdrawals of a single asset trade some of it for the
+        // others, so only the weighted product of the reserves a share is worth has to hold.
+        if let CurveState::Weighted { weights } = curve_read(storage).load()? {
+            if !weighted::share_value_held(
+                &before.amounts,
+                before.total_share,
+                &after.amounts,
+                after.total_share,
+                &weights,
+            )? {
+                return Err(invariant_violation("a share is worth less than before"));
+            }
+            return Ok(());
+        }
+        // Deposits and withdrawals round in the pool's favour, so no share is worth less of any
+        // asset than before.
+        for (before_amount, after_amount) in before.amounts.iter().zip(after.amounts.iter()) {
+            let least = mul_div_up(
+                before_amount.u128(),
+                after.total_share.u128(),
+                before.total_share.u128(),
+            )?;
+            if after_amount.u128() < least {
+                return Err(invariant_violation("a share is worth less than before"));
+            }
+        }
+        return Ok(());
+    }
+
+    // Swap fees stay in the pool, so trading only ever grows `k`.
+    let no_fee = vec![Uint128::zero(); after.amounts.len()];
+    let k_held = match curve_read(storage).load()? {
+        CurveState::ConstantProduct => flash_swap_repaid(&before.amounts, &after.amounts, &no_fee)?,
+        CurveState::StableSwap { decimals, amp } => stable::flash_swap_repaid(
+            amp.at(now),
+            &before.amounts,
+            &after.amounts,
+            &no_fee,
+            &stable::scales(&decimals),
+        )?,
+        CurveState::Weighted { weights } => {
+            weighted::flash_swap_repaid(&before.amounts, &after.amounts, &no_fee, &weights)?
+        }
+        CurveState::Concentrated { .. } => true,
+    };
+    if !k_held {
+        return Err(invariant_violation("k decreased"));
+    }
+    Ok(())
+}
+
+/// A concentrated liquidity swap trades along the curve from `sqrt_price` towards where it
+/// leaves the price, so what the pool took in net of the fee is worth at least what it paid out
+/// at the price it started from. Changes that don't take in one asset for the other are
+/// positions opening, closing or collecting fees.
+fn check_concentrated_swap(
+    before: &Reserves,
+    after: &Reserves,
+    sqrt_price: Uint128,
+) -> StdResult<()> {
+    let (offer_index, ask_index) =
+        if after.amounts[0] > before.amounts[0] && after.amounts[1] < before.amounts[1] {
+            (0, 1)
+        } else if after.amounts[1] > before.amounts[1] && after.amounts[0] < before.amounts[0] {
+            (1, 0)
+        } else {
+            return Ok(());
+        };
+
+    let offered = after.amounts[offer_index].u128() - before.amounts[offer_index].u128();
+    let paid = before.amounts[ask_index].u128() - after.amounts[ask_index].u128();
+    let net = offered - mul(offered, FEE_NUMERATOR)? / FEE_DENOMINATOR;
+    // Rounded up, in the swap's favour, so that only a real loss fails.
+    let (sqrt_price, q64) = (sqrt_price.u128(), concentrated::Q64);
+    let worth = if offer_index == 0 {
+        mul_div_up(mul_div_up(net, sqrt_price, q64)?, sqrt_price, q64)?
+    } else {
+        mul_div_up(mul_div_up(net, q64, sqrt_price)?, q64, sqrt_price)?
+    };
+    if worth < paid {
+        return Err(invariant_violation(
+            "the pool's reserves lost value net of fees",
+        ));
+    }
+    Ok(())
+}
+
+fn invariant_violation(invariant: &str) -> StdError {
+    StdError::generic_err(format!("Invariant violated: {}", invariant))
+}
+
+/// Adds the swap fee on `amounts`, in the same order as `Pair::assets`, to the fee growth of
+/// the pool's shares.
+fn accrue_fees<S: Storage>(
+    storage: &mut S,
+    amounts: &[Uint128],
+    total_share: Uint128,
+) -> StdResult<()> {
+    if total_share.is_zero() {
+        return Ok(());
+    }
+
+    let mut growth = fee_growth_read(storage).load()?;
+    for (growth, amount) in growth.iter_mut().zip(amounts) {
+        let fee = mul_div(amount.u128(), FEE_NUMERATOR, FEE_DENOMINATOR)?;
+        let per_share = mul_div(fee, concentrated::Q64, total_share.u128())?;
+        *growth = Uint128(growth.u128().wrapping_add(per_share));
+    }
+    fee_growth(storage).save(&growth)
+}
+
+/// The fees of `owner` brought up to date for their current `share`.
+fn lp_fees_at<S: Storage>(storage: &S, owner: &CanonicalAddr, share: Uint128) -> StdResult<LpFees> {
+    let growth = fee_growth_read(storage).load()?;
+    let mut fees = lp_fees_read(storage)
+        .may_load(owner.as_slice())?
+        .unwrap_or_else(|| LpFees {
+            fee_growth_last: growth.clone(),
+            fees_accrued: vec![Uint128::zero(); growth.len()],
+        });
+    for (i, growth) in growth.iter().enumerate() {
+        let delta = growth.u128().wrapping_sub(fees.fee_growth_last[i].u128());
+        fees.fees_accrued[i] += Uint128(mul_div(delta, share.u128(), concentrated::Q64)?);
+    }
+    fees.fee_growth_last = growth;
+    Ok(fees)
+}
+
+fn join<T: ToString>(amounts: &[T]) -> String {
+    amounts
+        .iter()
+        .map(|amount| amount.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Transfers a swap's output, or sends it with `callback` so that `recipient` gets a `Receive`.
+fn swap_output(
+    asset: &AssetMeta,
+    recipient: HumanAddr,
+    amount: Uint128,
+    callback: Option<Binary>,
+) -> StdResult<CosmosMsg> {
+    let callback = match callback {
+        Some(callback) => callback,
+        None => return transfer(asset, recipient, amount),
+    };
+    let (contract_addr, token_code_hash) = token_info(asset);
+    snip20::send_msg(
+        recipient,
+        amount,
+        Some(callback),
+        None,
+        None,
+        BLOCK_SIZE,
+        token_code_hash,
+        contract_addr,
+    )
+}
+
+fn transfer(asset: &AssetMeta, recipient: HumanAddr, amount: Uint128) -> StdResult<CosmosMsg> {
+    let (contract_addr, token_code_hash) = token_info(asset);
+    snip20::transfer_msg(
+        recipient,
+        amount,
+        None,
+        None,
+        BLOCK_SIZE,
+        token_code_hash,
+        contract_addr,
+    )
+}
+
+fn token_info(asset: &AssetMeta) -> (HumanAddr, String) {
+    match asset {
+        AssetMeta::Token {
+            contract_addr,
+            token_code_hash,
+        } => (contract_addr.clone(), token_code_hash.clone()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use common::pair::PairInitMsg;
+    use cosmwasm_std::{
+        from_binary, from_slice,
+        testing::{mock_dependencies, mock_env, MockApi, MockQuerier, MockStorage},
+        Empty, Extern, HumanAddr, QuerierResult, QueryRequest, WasmQuery,
+    };
+    use secret_toolkit::{
+        permit::{PermitParams, PermitSignature, PubKey, SignedPermit},
+        snip20::TokenInfo,
+    };
+    use serde::Serialize;
+    use sha2::{Digest, Sha256};
+
+    use crate::msg::QueryMsg;
+
+    use super::*;
+
+    fn token(name: &str) -> AssetMeta {
+        AssetMeta::Token {
+            contract_addr: HumanAddr::from(name),
+            token_code_hash: format!("{}_hash", name),
+        }
+    }
+
+    fn assets_meta() -> Vec<AssetMeta> {
+        vec![token("token_a"), token("token_b")]
+    }
+
+    fn init_pair() -> Extern<MockStorage, MockApi, MockQuerier> {
+        init_pair_with(MockApi::new(20))
+    }
+
+    fn init_pair_with<A: Api>(api: A) -> Extern<MockStorage, A, MockQuerier> {
+        let mut deps = Extern {
+            storage: MockStorage::default(),
+            api,
+            querier: MockQuerier::new(&[]),
+        };
+        let msg = PairInitMsg {
+            assets_meta: assets_meta(),
+            version: "1.0.0".to_string(),
+            factory: ContractLink {
+                address: HumanAddr::from("factory"),
+                code_hash: "factory_hash".to_string(),
+            },
+            curve: Curve::ConstantProduct,
+            swap_mode: SwapMode::Instant,
+            padding_block_size: None,
+        };
+        let _res = init(&mut deps, mock_env("factory", &[]), msg).unwrap();
+        deps
+    }
+
+    fn add_liquidity<A: Api>(
+        deps: &mut Extern<MockStorage, A, MockQuerier>,
+        sender: &str,
+        amounts: &[u128],
+    ) -> HandleResponse {
+        let assets = ["token_a", "token_b", "token_c"]
+            .iter()
+            .zip(amounts.iter())
+            .map(|(name, amount)| Asset {
+                meta: token(name),
+                amount: Uint128(*amount),
+            })
+            .collect();
+        let msg = HandleMsg::AddLiquidity {
+            assets,
+            receiver: None,
+            min_share: None,
+            padding: None,
+        };
+        handle(deps, mock_env(sender, &[]), msg).unwrap()
+    }
+
+    /// Whether `res` logged `key` with `value`, ignoring the padding.
+    fn logged<T: ToString>(res: &HandleResponse, key: &str, value: T) -> bool {
+        res.log
+            .iter()
+            .any(|log| log.key.trim_end() == key && log.value.trim_end() == value.to_string())
+    }
+
+    fn pool<Q: Querier>(deps: &Extern<MockStorage, MockApi, Q>) -> PoolResponse {
+        from_binary(&query(deps, QueryMsg::Pool {}).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn proper_initialization() {
+        let deps = init_pair();
+
+        let msg = QueryMsg::Pair {};
+
+        let res = query(&deps, msg).unwrap();
+        let value: Pair = from_binary(&res).unwrap();
+        assert_eq!(value.version, "1.0.0");
+        assert_eq!(res.len() % DEFAULT_PADDING_BLOCK_SIZE as usize, 0);
+    }
+
+    #[test]
+    fn add_swap_remove() {
+        let mut deps = init_pair();
+
+        let res = add_liquidity(&mut deps, "provider", &[1_000_000, 4_000_000]);
+        assert_eq!(res.messages.len(), 2);
+        let block_size = DEFAULT_PADDING_BLOCK_SIZE as usize;
+        assert!(res.log.iter().all(|log| log.value.len() % block_size == 0));
+        assert!(logged(&res, "share", 2_000_000));
+        assert_eq!(pool(&deps).total_share, Uint128(2_000_000));
+
+        let swap = ReceiveMsg::Swap {
+            ask_asset: None,
+            expected_return: Some(Uint128(3_984)),
+            to: None,
+            callback: None,
+        };
+        let msg = swap_msg("trader", 1_000, Some(swap));
+        let res = handle(&mut deps, mock_env("not_a_token", &[]), msg.clone());
+        assert_eq!(res.unwrap_err(), StdError::unauthorized());
+
+        let msg_simulation = QueryMsg::Simulation {
+            offer: Asset {
+                meta: assets_meta()[0].clone(),
+                amount: Uint128(1_000),
+            },
+            ask_asset: None,
+        };
+        let simulation: SimulationResponse =
+            from_binary(&query(&deps, msg_simulation).unwrap()).unwrap();
+        assert_eq!(simulation.return_amount, Uint128(3_984));
+
+        let _res = handle(&mut deps, mock_env("token_a", &[]), msg).unwrap();
+        let pool_after_swap = pool(&deps);
+        assert_eq!(pool_after_swap.assets[0].amount, Uint128(1_001_000));
+        assert_eq!(pool_after_swap.assets[1].amount, Uint128(3_996_016));
+
+        let res = handle(
+            &mut deps,
+            mock_env("provider", &[]),
+            HandleMsg::RemoveLiquidity {
+                share: Uint128(3_000_000),
+                asset: None,
+                padding: None,
+            },
+        );
+        assert!(res.is_err());
+
+        let _res = handle(
+            &mut deps,
+            mock_env("provider", &[]),
+            HandleMsg::RemoveLiquidity {
+                share: Uint128(2_000_000),
+                asset: None,
+                padding: None,
+            },
+        )
+        .unwrap();
+        assert_eq!(pool(&deps).total_share, Uint128::zero());
+    }
+
+    #[test]
+    fn migrate_liquidity() {
+        let mut deps = init_pair();
+        add_liquidity(&mut deps, "provider", &[1_000_000, 4_000_000]);
+
+        let msg = HandleMsg::AddLiquidity {
+            assets: assets_meta()
+                .into_iter()
+                .map(|meta| Asset {
+                    meta,
+                    amount: Uint128(1_000),
+                })
+                .collect(),
+            receiver: None,
+            min_share: Some(Uint128(1_001)),
+            padding: None,
+        };
+        let res = handle(&mut deps, mock_env("provider", &[]), msg);
+        assert_eq!(
+            res.unwrap_err(),
+            StdError::generic_err("Deposit mints 500 shares, less than the minimum 1001")
+        );
+
+        let msg = HandleMsg::MigrateLiquidity {
+            owner: HumanAddr::from("provider"),
+            new_pair: ContractLink {
+                address: HumanAddr::from("new_pair"),
+                code_hash: "new_pair_hash".to_string(),
+            },
+            min_share: Some(Uint128(1_000)),
+        };
+        let res = handle(&mut deps, mock_env("provider", &[]), msg.clone());
+        assert_eq!(res.unwrap_err(), StdError::unauthorized());
+
+        let res = handle(&mut deps, mock_env("factory", &[]), msg).unwrap();
+        assert_eq!(res.messages.len(), 3);
+        assert_eq!(pool(&deps).total_share, Uint128::zero());
+    }
+
+    fn place_order(
+        deps: &mut Extern<MockStorage, MockApi, MockQuerier>,
+        owner: &str,
+        offer_token: &str,
+        offer_amount: u128,
+        ask_amount: u128,
+    ) -> StdResult<HandleResponse> {
+        let msg = HandleMsg::Receive {
+            sender: HumanAddr::from(owner),
+            from: HumanAddr::from(owner),
+            amount: Uint128(offer_amount),
+            msg: Some(
+                to_binary(&ReceiveMsg::PlaceOrder {
+                    ask_asset: None,
+                    ask_amount: Uint128(ask_amount),
+                })
+                .unwrap(),
+            ),
+        };
+        handle(deps, mock_env(offer_token, &[]), msg)
+    }
+
+    /// `trader` selling `amount` of the token the message comes from, as `swap` says or else
+    /// for the other asset at any price.
+    fn swap_msg(trader: &str, amount: u128, swap: Option<ReceiveMsg>) -> HandleMsg {
+        let swap = swap.unwrap_or(ReceiveMsg::Swap {
+            ask_asset: None,
+            expected_return: None,
+            to: None,
+            callback: None,
+        });
+        HandleMsg::Receive {
+            sender: HumanAddr::from(trader),
+            from: HumanAddr::from(trader),
+            amount: Uint128(amount),
+            msg: Some(to_binary(&swap).unwrap()),
+        }
+    }
+
+    /// Order `order_id` of `owner`, whose viewing key is "key".
+    fn order<Q: Querier>(
+        deps: &Extern<MockStorage, MockApi, Q>,
+        owner: &str,
+        order_id: u64,
+    ) -> OrderResponse {
+        let msg = QueryMsg::Order {
+            order_id,
+            address: HumanAddr::from(owner),
+            key: "key".to_string(),
+        };
+        from_binary(&query(deps, msg).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn limit_orders() {
+        let mut deps = init_pair();
+        add_liquidity(&mut deps, "provider", &[1_000_000, 4_000_000]);
+        for owner in ["maker", "taker"].iter() {
+            let msg = HandleMsg::SetViewingKey {
+                key: "key".to_string(),
+                padding: None,
+            };
+            handle(&mut deps, mock_env(*owner, &[]), msg).unwrap();
+        }
+
+        let res = place_order(&mut deps, "maker", "token_b", 0, 100);
+        assert!(res.is_err());
+        let res = place_order(&mut deps, "maker", "token_b", 399, 100);
+        assert_eq!(
+            res.unwrap_err(),
+            StdError::generic_err("Orders must offer at least 400")
+        );
+
+        // 1_000 token_b for 260 token_a is above the pool's price, so it waits in the book.
+        let res = place_order(&mut deps, "maker", "token_b", 1_000, 260).unwrap();
+        assert!(res.messages.is_empty());
+        assert_eq!(order(&deps, "maker", 0).offer_remaining, Uint128(1_000));
+        let msg = QueryMsg::Order {
+            order_id: 0,
+            address: HumanAddr::from("taker"),
+            key: "key".to_string(),
+        };
+        assert!(query(&deps, msg).is_err());
+        let res = handle(
+            &mut deps,
+            mock_env("keeper", &[]),
+            HandleMsg::FillOrder {
+                order_id: 0,
+                padding: None,
+            },
+        );
+        assert_eq!(
+            res.unwrap_err(),
+            StdError::generic_err("The pool's price doesn't fill this order")
+        );
+
+        // A marketable order fills right away.
+        let res = place_order(&mut deps, "taker", "token_a", 1_000, 3_900).unwrap();
+        assert_eq!(res.messages.len(), 1);
+        assert_eq!(order(&deps, "taker", 1).received, Uint128(3_984));
+        assert_eq!(order(&deps, "taker", 1).offer_remaining, Uint128::zero());
+
+        // Selling token_a raises the price of token_b, filling the first order.
+        let msg = swap_msg("trader", 30_000, None);
+        let res = handle(&mut deps, mock_env("token_a", &[]), msg).unwrap();
+        assert_eq!(res.messages.len(), 2);
+        assert!(logged(&res, "filled_orders", "0"));
+        let filled = order(&deps, "maker", 0);
+        assert_eq!(filled.offer_remaining, Uint128::zero());
+        assert!(filled.received >= Uint128(260));
+        let book: OrderBookResponse =
+            from_binary(&query(&deps, QueryMsg::OrderBook {}).unwrap()).unwrap();
+        assert!(book.depth.is_empty());
+
+        let res = handle(
+            &mut deps,
+            mock_env("maker", &[]),
+            HandleMsg::CancelOrder {
+                order_id: 0,
+                padding: None,
+            },
+        );
+        assert_eq!(res.unwrap_err(), StdError::generic_err("Order 0 is closed"));
+
+        // Only part of a large order fills at its price, the rest can be cancelled.
+        place_order(&mut deps, "maker", "token_b", 1_000_000, 260_000).unwrap();
+        let partial = order(&deps, "maker", 2);
+        let book: OrderBookResponse =
+            from_binary(&query(&deps, QueryMsg::OrderBook {}).unwrap()).unwrap();
+        assert!(book.depth.is_empty());
+
+        // The public depth only shows buckets of several orders, rounded.
+        place_order(&mut deps, "taker", "token_b", 1_000, 260).unwrap();
+        let sum = partial.offer_remaining + order(&deps, "taker", 3).offer_remaining;
+        let book: OrderBookResponse =
+            from_binary(&query(&deps, QueryMsg::OrderBook {}).unwrap()).unwrap();
+        assert_eq!(
+            book.depth,
+            vec![DepthBucket {
+                offer_asset: token("token_b"),
+                ask_asset: token("token_a"),
+                price: Uint128(260_000_000_000_000_000),
+                offer_remaining: round_down(sum, DEPTH_AMOUNT_DIGITS),
+            }]
+        );
+        assert_ne!(book.depth[0].offer_remaining, sum);
+        let msg = QueryMsg::Orders {
+            address: HumanAddr::from("maker"),
+            key: "key".to_string(),
+        };
+        let res: OrdersResponse = from_binary(&query(&deps, msg).unwrap()).unwrap();
+        assert_eq!(res.orders, vec![partial.clone()]);
+        assert!(partial.offer_remaining > Uint128::zero());
+        assert!(partial.offer_remaining < Uint128(1_000_000));
+        assert!(
+            partial.received.u128() * 1_000_000
+                >= 260_000 * (1_000_000 - partial.offer_remaining.u128())
+        );
+
+        let res = handle(
+            &mut deps,
+            mock_env("taker", &[]),
+            HandleMsg::CancelOrder {
+                order_id: 2,
+                padding: None,
+            },
+        );
+        assert_eq!(res.unwrap_err(), StdError::unauthorized());
+        let res = handle(
+            &mut deps,
+            mock_env("maker", &[]),
+            HandleMsg::CancelOrder {
+                order_id: 2,
+                padding: None,
+            },
+        )
+        .unwrap();
+        assert!(logged(&res, "refund", partial.offer_remaining));
+        assert_eq!(order(&deps, "maker", 2).offer_remaining, Uint128::zero());
+    }
+
+    #[test]
+    fn viewing_keys() {
+        let mut deps = init_pair();
+        add_liquidity(&mut deps, "provider", &[1_000_000, 4_000_000]);
+
+        let res = handle(
+            &mut deps,
+            mock_env("provider", &[]),
+            HandleMsg::CreateViewingKey {
+                entropy: "entropy".to_string(),
+                padding: None,
+            },
+        )
+        .unwrap();
+        let key = match from_binary(&res.data.unwrap()).unwrap() {
+            HandleAnswer::CreateViewingKey { key } => key,
+        };
+        assert!(key.starts_with(viewing_key::VIEWING_KEY_PREFIX));
+
+        let position = |deps: &Extern<MockStorage, MockApi, MockQuerier>, key: &str| {
+            query(
+                deps,
+                QueryMsg::Position {
+                    address: HumanAddr::from("provider"),
+                    key: key.to_string(),
+                },
+            )
+            .map(|res| from_binary::<PositionResponse>(&res).unwrap())
+        };
+        let wrong_key =
+            StdError::generic_err("Wrong viewing key for this address or viewing key not set");
+        assert_eq!(position(&deps, "wrong").unwrap_err(), wrong_key);
+        let res = query(
+            &deps,
+            QueryMsg::Position {
+                address: HumanAddr::from("trader"),
+                key: key.clone(),
+            },
+        );
+        assert_eq!(res.unwrap_err(), wrong_key);
+
+        let res = position(&deps, &key).unwrap();
+        assert_eq!(res.share, Uint128(2_000_000));
+        assert_eq!(res.amounts, vec![Uint128(1_000_000), Uint128(4_000_000)]);
+        assert_eq!(res.fees, vec![Uint128::zero(); 2]);
+
+        // The 0.3% fee on a swap stays in the pool for the provider.
+        let msg = swap_msg("trader", 100_000, None);
+        handle(&mut deps, mock_env("token_a", &[]), msg).unwrap();
+        let res = position(&deps, &key).unwrap();
+        assert_eq!(res.amounts[0], Uint128(1_100_000));
+        assert_eq!(res.fees, vec![Uint128(299), Uint128::zero()]);
+
+        // Withdrawing part of the share keeps the fees, withdrawing the rest resets them.
+        let remove = |share: u128| HandleMsg::RemoveLiquidity {
+            share: Uint128(share),
+            asset: None,
+            padding: None,
+        };
+        handle(&mut deps, mock_env("provider", &[]), remove(1_000_000)).unwrap();
+        assert_eq!(position(&deps, &key).unwrap().fees[0], Uint128(299));
+        handle(&mut deps, mock_env("provider", &[]), remove(1_000_000)).unwrap();
+        let res = position(&deps, &key).unwrap();
+        assert_eq!(res.share, Uint128::zero());
+        assert_eq!(res.fees, vec![Uint128::zero(); 2]);
+
+        handle(
+            &mut deps,
+            mock_env("provider", &[]),
+            HandleMsg::SetViewingKey {
+                key: "my key".to_string(),
+                padding: None,
+            },
+        )
+        .unwrap();
+        assert_eq!(position(&deps, &key).unwrap_err(), wrong_key);
+        assert!(position(&deps, "my key").is_ok());
+    }
+
+    /// `MockApi`, except that canonical addresses it can't turn back into mock human addresses,
+    /// like the ones of permit signers, get a human address of their own.
+    #[derive(Copy, Clone)]
+    struct PermitApi(MockApi);
+
+    impl Api for PermitApi {
+        fn canonical_address(&self, human: &HumanAddr) -> StdResult<CanonicalAddr> {
+            match human.as_str().strip_prefix("signer_") {
+                Some(encoded) => Ok(CanonicalAddr(Binary::from_base64(encoded)?)),
+                None => self.0.canonical_address(human),
+            }
+        }
+
+        fn human_address(&self, canonical: &CanonicalAddr) -> StdResult<HumanAddr> {
+            self.0
+                .human_address(canonical)
+                .or_else(|_| Ok(HumanAddr(format!("signer_{}", canonical.0.to_base64()))))
+        }
+    }
+
+    fn signed_permit(
+        permit_name: &str,
+        allowed_token: &str,
+        permissions: Vec<Permission>,
+    ) -> Permit {
+        let params = PermitParams {
+            allowed_tokens: vec![HumanAddr::from(allowed_token)],
+            permit_name: permit_name.to_string(),
+            chain_id: "secret-4".to_string(),
+            permissions,
+        };
+        let signed_bytes = to_binary(&SignedPermit::from_params(&params)).unwrap();
+        let message =
+            secp256k1::Message::from_slice(&Sha256::digest(signed_bytes.as_slice())).unwrap();
+        let secp = secp256k1::Secp256k1::new();
+        let secret_key = secp256k1::SecretKey::from_slice(&[7; 32]).unwrap();
+
+        Permit {
+            params,
+            signature: PermitSignature {
+                pub_key: PubKey {
+                    r#type: "tendermint/PubKeySecp256k1".to_string(),
+                    value: Binary(
+                        secp256k1::PublicKey::from_secret_key(&secp, &secret_key)
+                            .serialize()
+                            .to_vec(),
+                    ),
+                },
+                signature: Binary(
+                    secp.sign(&message, &secret_key)
+                        .serialize_compact()
+                        .to_vec(),
+                ),
+            },
+        }
+    }
+
+    #[test]
+    fn query_permits() {
+        let mut deps = init_pair_with(PermitApi(MockApi::new(20)));
+        let permit = signed_permit("pools", "cosmos2contract", vec![Permission::Balance]);
+        let signer = deps
+            .api
+            .human_address(&permit::pubkey_to_account(&permit.signature.pub_key.value))
+            .unwrap();
+        add_liquidity(&mut deps, signer.as_str(), &[1_000_000, 4_000_000]);
+
+        let position = |deps: &Extern<MockStorage, PermitApi, MockQuerier>, permit: Permit| {
+            query(
+                deps,
+                QueryMsg::WithPermit {
+                    permit,
+                    query: QueryWithPermit::Position {},
+                },
+            )
+            .map(|res| from_binary::<PositionResponse>(&res).unwrap())
+        };
+        let res = position(&deps, permit.clone()).unwrap();
+        assert_eq!(res.share, Uint128(2_000_000));
+        assert_eq!(res.amounts, vec![Uint128(1_000_000), Uint128(4_000_000)]);
+
+        let other_pair = signed_permit("pools", "other_pair", vec![Permission::Balance]);
+        assert!(position(&deps, other_pair).is_err());
+        let history_only = signed_permit("pools", "cosmos2contract", vec![Permission::History]);
+        assert!(position(&deps, history_only.clone()).is_err());
+        let mut forged = permit.clone();
+        forged.params.permissions.push(Permission::Owner);
+        assert!(position(&deps, forged).is_err());
+
+        let msg = QueryMsg::WithPermit {
+            permit: history_only,
+            query: QueryWithPermit::History {
+                page: 0,
+                page_size: 10,
+            },
+        };
+        let res: HistoryResponse = from_binary(&query(&deps, msg).unwrap()).unwrap();
+        assert_eq!(res.total, 1);
+
+        handle(
+            &mut deps,
+            mock_env(signer.as_str(), &[]),
+            HandleMsg::RevokePermit {
+                permit_name: "pools".to_string(),
+                padding: None,
+            },
+        )
+        .unwrap();
+        let res = position(&deps, permit);
+        assert!(res.unwrap_err().to_string().contains("was revoked"));
+    }
+
+    #[test]
+    fn reserve_visibility() {
+        let mut deps = init_pair();
+        add_liquidity(&mut deps, "provider", &[1_000_000, 4_000_000]);
+        let msg = HandleMsg::SetViewingKey {
+            key: "key".to_string(),
+            padding: None,
+        };
+        handle(&mut deps, mock_env("provider", &[]), msg).unwrap();
+
+        let set_visibility = |visibility| HandleMsg::SetReserveVisibility { visibility };
+        let swap = swap_msg("trader", 1_000, None);
+        let exact_pool = |deps: &Extern<MockStorage, MockApi, MockQuerier>, address: &str| {
+            let msg = QueryMsg::ExactPool {
+                address: HumanAddr::from(address),
+                key: "key".to_string(),
+            };
+            query(deps, msg).map(|res| from_binary::<PoolResponse>(&res).unwrap())
+        };
+        let amounts = |pool: PoolResponse| -> Vec<u128> {
+            pool.assets
+                .iter()
+                .map(|asset| asset.amount.u128())
+                .collect()
+        };
+
+        let rounded = set_visibility(ReserveVisibility::Rounded {
+            significant_digits: 2,
+        });
+        let res = handle(&mut deps, mock_env("provider", &[]), rounded.clone());
+        assert_eq!(res.unwrap_err(), StdError::unauthorized());
+        handle(&mut deps, mock_env("factory", &[]), rounded).unwrap();
+        handle(&mut deps, mock_env("token_a", &[]), swap.clone()).unwrap();
+        assert_eq!(amounts(pool(&deps)), vec![1_000_000, 3_900_000]);
+        assert_eq!(
+            amounts(exact_pool(&deps, "provider").unwrap()),
+            vec![1_001_000, 3_996_016]
+        );
+        // Simulations quote against the rounded reserves too.
+        let msg = QueryMsg::Simulation {
+            offer: Asset {
+                meta: token("token_a"),
+                amount: Uint128(1_000),
+            },
+            ask_asset: None,
+        };
+        let simulation: SimulationResponse = from_binary(&query(&deps, msg).unwrap()).unwrap();
+        assert_eq!(simulation.return_amount, Uint128(3_884));
+        let msg = HandleMsg::SetViewingKey {
+            key: "key".to_string(),
+            padding: None,
+        };
+        handle(&mut deps, mock_env("trader", &[]), msg).unwrap();
+        assert!(exact_pool(&deps, "trader").is_err());
+        // Nor do prices, however short the window.
+        let msg = QueryMsg::Twap {
+            window_seconds: 1,
+            now: Some(mock_env("trader", &[]).block.time + 10),
+        };
+        let twap: TwapResponse = from_binary(&query(&deps, msg).unwrap()).unwrap();
+        assert_eq!(
+            twap.price_average,
+            [
+                Uint128(39 * PRICE_PRECISION / 10),
+                Uint128(10 * PRICE_PRECISION / 39)
+            ]
+        );
+
+        // Swaps show up once they're 5 blocks older than the pair's latest transaction.
+        let delayed = set_visibility(ReserveVisibility::Delayed { blocks: 5 });
+        let mut env = mock_env("factory", &[]);
+        handle(&mut deps, env.clone(), delayed).unwrap();
+        env.message.sender = HumanAddr::from("token_a");
+        env.block.height += 1;
+        handle(&mut deps, env.clone(), swap.clone()).unwrap();
+        let after_swap = amounts(exact_pool(&deps, "provider").unwrap());
+        assert_eq!(amounts(pool(&deps)), vec![1_001_000, 3_996_016]);
+        env.block.height += 4;
+        handle(&mut deps, env.clone(), swap.clone()).unwrap();
+        assert_eq!(amounts(pool(&deps)), vec![1_001_000, 3_996_016]);
+        env.block.height += 1;
+        handle(&mut deps, env, swap).unwrap();
+        assert_eq!(amounts(pool(&deps)), after_swap);
+
+        let res = handle(
+            &mut deps,
+            mock_env("factory", &[]),
+            set_visibility(ReserveVisibility::Delayed { blocks: 0 }),
+        );
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn contract_status() {
+        let mut deps = init_pair();
+        add_liquidity(&mut deps, "provider", &[1_000_000, 4_000_000]);
+        place_order(&mut deps, "maker", "token_b", 1_000, 260).unwrap();
+
+        let set_status = |status| HandleMsg::SetStatus { status };
+        let swap = swap_msg("trader", 1_000, None);
+        let remove = HandleMsg::RemoveLiquidity {
+            share: Uint128(1_000),
+            asset: None,
+            padding: None,
+        };
+        let status = |deps: &Extern<MockStorage, MockApi, MockQuerier>| -> ContractStatus {
+            from_binary(&query(deps, QueryMsg::Status {}).unwrap()).unwrap()
+        };
+        assert_eq!(status(&deps), ContractStatus::Normal);
+
+        let stop_trading = set_status(ContractStatus::StopTrading);
+        let res = handle(&mut deps, mock_env("provider", &[]), stop_trading.clone());
+        assert_eq!(res.unwrap_err(), StdError::unauthorized());
+        handle(&mut deps, mock_env("factory", &[]), stop_trading).unwrap();
+        assert_eq!(status(&deps), ContractStatus::StopTrading);
+
+        // Swaps, deposits and new orders are frozen, but liquidity and orders can be withdrawn.
+        let res = handle(&mut deps, mock_env("token_a", &[]), swap.clone());
+        assert_eq!(
+            res.unwrap_err(),
+            StdError::generic_err("Trading on this pair is stopped, only withdrawals are allowed")
+        );
+        assert!(place_order(&mut deps, "maker", "token_b", 1_000, 260).is_err());
+        let msg = HandleMsg::AddLiquidity {
+            assets: vec![],
+            receiver: None,
+            min_share: None,
+            padding: None,
+        };
+        assert!(handle(&mut deps, mock_env("provider", &[]), msg).is_err());
+        handle(&mut deps, mock_env("provider", &[]), remove.clone()).unwrap();
+        let msg = HandleMsg::CancelOrder {
+            order_id: 0,
+            padding: None,
+        };
+        handle(&mut deps, mock_env("maker", &[]), msg).unwrap();
+
+        let stop_all = set_status(ContractStatus::StopAll);
+        handle(&mut deps, mock_env("factory", &[]), stop_all).unwrap();
+        let res = handle(&mut deps, mock_env("provider", &[]), remove.clone());
+        assert_eq!(
+            res.unwrap_err(),
+            StdError::generic_err("This pair is stopped")
+        );
+        let msg = HandleMsg::SetViewingKey {
+            key: "key".to_string(),
+            padding: None,
+        };
+        handle(&mut deps, mock_env("provider", &[]), msg).unwrap();
+
+        let normal = set_status(ContractStatus::Normal);
+        handle(&mut deps, mock_env("factory", &[]), normal).unwrap();
+        handle(&mut deps, mock_env("token_a", &[]), swap).unwrap();
+        handle(&mut deps, mock_env("provider", &[]), remove).unwrap();
+    }
+
+    #[test]
+    fn swap_limits() {
+        let mut deps = init_pair();
+        add_liquidity(&mut deps, "provider", &[1_000_000, 4_000_000]);
+        let swap = swap_msg("trader", 1_000, None);
+        let set_limits = |max_price_impact_bps, max_trade_size_bps| HandleMsg::SetSwapLimits {
+            limits: SwapLimits {
+                max_price_impact_bps,
+                max_trade_size_bps,
+            },
+        };
+
+        let res = handle(&mut deps, mock_env("provider", &[]), set_limits(18, None));
+        assert_eq!(res.unwrap_err(), StdError::unauthorized());
+        let res = handle(&mut deps, mock_env("factory", &[]), set_limits(0, None));
+        assert!(res.is_err());
+        let res = handle(
+            &mut deps,
+            mock_env("factory", &[]),
+            set_limits(18, Some(10_001)),
+        );
+        assert!(res.is_err());
+
+        // 1_000 token_a moves its price from 4 to about 3.992 token_b, by 19 bps.
+        handle(&mut deps, mock_env("factory", &[]), set_limits(20, Some(5))).unwrap();
+        let limits: SwapLimits =
+            from_binary(&query(&deps, QueryMsg::SwapLimits {}).unwrap()).unwrap();
+        assert_eq!(limits.max_trade_size_bps, Some(5));
+        let res = handle(&mut deps, mock_env("token_a", &[]), swap.clone());
+        assert_eq!(
+            res.unwrap_err(),
+            StdError::generic_err("Swap of 1000 is above the maximum trade size of 500")
+        );
+        handle(
+            &mut deps,
+            mock_env("factory", &[]),
+            set_limits(20, Some(10)),
+        )
+        .unwrap();
+        let res = handle(&mut deps, mock_env("token_a", &[]), swap.clone()).unwrap();
+        assert!(logged(&res, "return_amount", 3_984));
+
+        handle(&mut deps, mock_env("factory", &[]), set_limits(18, None)).unwrap();
+        let res = handle(&mut deps, mock_env("token_a", &[]), swap);
+        assert_eq!(
+            res.unwrap_err(),
+            StdError::generic_err("Swap moves the price by 19 bps, more than the 18 bps allowed")
+        );
+
+        // A limit order only fills what the limits allow right away, and rests with the rest.
+        handle(
+            &mut deps,
+            mock_env("factory", &[]),
+            set_limits(MAX_BPS, Some(10)),
+        )
+        .unwrap();
+        let res = place_order(&mut deps, "trader", "token_a", 2_000, 1).unwrap();
+        assert_eq!(res.messages.len(), 1);
+        let order = orders_read(&deps.storage)
+            .load(&0u64.to_be_bytes())
+            .unwrap();
+        assert_eq!(order.offer_remaining, Uint128(999));
+        assert_eq!(order_book_read(&deps.storage).load().unwrap().open.len(), 1);
+    }
+
+    #[test]
+    fn invariants() {
+        let mut deps = init_pair();
+        add_liquidity(&mut deps, "provider", &[1_000_000, 4_000_000]);
+        let swap = swap_msg("trader", 1_000, None);
+        handle(&mut deps, mock_env("token_a", &[]), swap.clone()).unwrap();
+        let pool = reserves_read(&deps.storage).load().unwrap();
+        assert_eq!(
+            balances_read(&deps.storage).load().unwrap(),
+            vec![Uint128(1_001_000), Uint128(3_996_016)]
+        );
+        check_invariants(&deps.storage, &pool, None, 0).unwrap();
+
+        // Reserves worth more than they are now, for the same shares or for fewer.
+        let mut richer = pool.clone();
+        richer.amounts[1] += Uint128(1);
+        assert_eq!(
+            check_invariants(&deps.storage, &richer, None, 0).unwrap_err(),
+            StdError::generic_err("Invariant violated: k decreased")
+        );
+        let mut fewer_shares = pool.clone();
+        fewer_shares.total_share = (fewer_shares.total_share - Uint128(1)).unwrap();
+        assert_eq!(
+            check_invariants(&deps.storage, &fewer_shares, None, 0).unwrap_err(),
+            StdError::generic_err("Invariant violated: a share is worth less than before")
+        );
+
+        let mut inflated = pool.clone();
+        inflated.amounts[0] += Uint128(1);
+        reserves(&mut deps.storage).save(&inflated).unwrap();
+        let res = handle(&mut deps, mock_env("token_a", &[]), swap);
+        assert_eq!(
+            res.unwrap_err(),
+            StdError::generic_err("Invariant violated: the reserves exceed the pair's balances")
+        );
+        reserves(&mut deps.storage).save(&pool).unwrap();
+
+        share_supply(&mut deps.storage)
+            .save(&(pool.total_share + Uint128(1)))
+            .unwrap();
+        let msg = HandleMsg::RemoveLiquidity {
+            share: Uint128(1_000),
+            asset: None,
+            padding: None,
+        };
+        let res = handle(&mut deps, mock_env("provider", &[]), msg);
+        assert_eq!(
+            res.unwrap_err(),
+            StdError::generic_err(
+                "Invariant violated: the LP supply doesn't match the shares held"
+            )
+        );
+
+        let held = balances_read(&deps.storage).load().unwrap()[0];
+        assert_eq!(
+            debit(&mut deps.storage, 0, held + Uint128(1)).unwrap_err(),
+            StdError::generic_err("Invariant violated: paid out more than the pair holds")
+        );
+    }
+
+    #[test]
+    fn history() {
+        let mut deps = init_pair();
+        add_liquidity(&mut deps, "provider", &[1_000_000, 4_000_000]);
+        for key in ["provider key", "trader key"].iter() {
+            let sender = key.trim_end_matches(" key");
+            let msg = HandleMsg::SetViewingKey {
+                key: key.to_string(),
+                padding: None,
+            };
+            handle(&mut deps, mock_env(sender, &[]), msg).unwrap();
+        }
+
+        let to_recipient = ReceiveMsg::Swap {
+            ask_asset: None,
+            expected_return: None,
+            to: Some(HumanAddr::from("recipient")),
+            callback: None,
+        };
+        let swap = swap_msg("trader", 1_000, Some(to_recipient));
+        handle(&mut deps, mock_env("token_a", &[]), swap.clone()).unwrap();
+        handle(&mut deps, mock_env("token_a", &[]), swap).unwrap();
+        let msg = HandleMsg::RemoveLiquidity {
+            share: Uint128(1_000_000),
+            asset: None,
+            padding: None,
+        };
+        handle(&mut deps, mock_env("provider", &[]), msg).unwrap();
+
+        let history = |address: &str, key: &str, page: u32, page_size: u32| {
+            let msg = QueryMsg::History {
+                address: HumanAddr::from(address),
+                key: key.to_string(),
+                page,
+                page_size,
+            };
+            query(&deps, msg).map(|res| from_binary::<HistoryResponse>(&res).unwrap())
+        };
+
+        // Newest first, recorded for the sender rather than the recipient.
+        let res = history("trader", "trader key", 1, 1).unwrap();
+        assert_eq!(res.total, 2);
+        assert_eq!(
+            res.entries[0].action,
+            HistoryAction::Swap {
+                offer_asset: token("token_a"),
+                offer_amount: Uint128(1_000),
+                ask_asset: token("token_b"),
+                return_amount: Uint128(3_984),
+                price: Uint128(3_984_000_000_000_000_000),
+            }
+        );
+        assert_eq!(
+            history("trader", "trader key", 2, 1).unwrap().entries,
+            vec![]
+        );
+        assert_eq!(
+            history("trader", "trader key", u32::MAX, MAX_PAGE_SIZE)
+                .unwrap()
+                .entries,
+            vec![]
+        );
+
+        let res = history("provider", "provider key", 0, 10).unwrap();
+        assert_eq!(res.total, 2);
+        assert_eq!(
+            res.entries[0].action,
+            HistoryAction::RemoveLiquidity {
+                amounts: vec![Uint128(501_000), Uint128(1_996_020)],
+                share: Uint128(1_000_000),
+                position_id: None,
+            }
+        );
+        assert_eq!(
+            res.entries[1].action,
+            HistoryAction::AddLiquidity {
+                amounts: vec![Uint128(1_000_000), Uint128(4_000_000)],
+                share: Uint128(2_000_000),
+                position_id: None,
+            }
+        );
+
+        assert!(history("trader", "provider key", 0, 10).is_err());
+        assert!(history("provider", "provider key", 0, 0).is_err());
+        assert!(history("provider", "provider key", 0, MAX_PAGE_SIZE + 1).is_err());
+    }
+
+    #[test]
+    fn batch_auction() {
+        let mut deps = mock_dependencies(20, &[]);
+        let msg = PairInitMsg {
+            assets_meta: assets_meta(),
+            version: "1.0.0".to_string(),
+            factory: ContractLink {
+                address: HumanAddr::from("factory"),
+                code_hash: "factory_hash".to_string(),
+            },
+            curve: Curve::ConstantProduct,
+            swap_mode: SwapMode::BatchAuction { epoch_blocks: 10 },
+            padding_block_size: None,
+        };
+        init(&mut deps, mock_env("factory", &[]), msg).unwrap();
+        add_liquidity(&mut deps, "provider", &[1_000_000, 4_000_000]);
+
+        let swap = |trader: &str, amount: u128, expected_return: Option<u128>| {
+            let swap = ReceiveMsg::Swap {
+                ask_asset: None,
+                expected_return: expected_return.map(Uint128),
+                to: None,
+                callback: None,
+            };
+            swap_msg(trader, amount, Some(swap))
+        };
+        let at_height = |sender: &str, height: u64| {
+            let mut env = mock_env(sender, &[]);
+            env.block.height = height;
+            env
+        };
+
+        // Swaps only queue, in either direction.
+        let res = handle(
+            &mut deps,
+            at_height("token_a", 100),
+            swap("seller_a", 10_000, None),
+        );
+        assert!(res.unwrap().messages.is_empty());
+        let res = handle(&mut deps, at_height("token_a", 100), swap("dust", 99, None));
+        assert_eq!(
+            res.unwrap_err(),
+            StdError::generic_err("Batched swaps must offer at least 100")
+        );
+        let with_callback = ReceiveMsg::Swap {
+            ask_asset: None,
+            expected_return: None,
+            to: None,
+            callback: Some(Binary::from(b"hop".to_vec())),
+        };
+        let msg = swap_msg("router", 10_000, Some(with_callback));
+        assert_eq!(
+            handle(&mut deps, at_height("token_a", 100), msg).unwrap_err(),
+            StdError::generic_err("Batch auction pairs don't take swap callbacks")
+        );
+        let msg = swap("seller_b", 20_000, None);
+        handle(&mut deps, at_height("token_b", 105), msg).unwrap();
+        let msg = swap("greedy", 4_000, Some(16_000));
+        handle(&mut deps, at_height("token_b", 109), msg).unwrap();
+        assert_eq!(pool(&deps).assets[0].amount, Uint128(1_000_000));
+        let batch: BatchResponse = from_binary(&query(&deps, QueryMsg::Batch {}).unwrap()).unwrap();
+        assert_eq!(batch.swap_count, 3);
+        assert_eq!(batch.settle_height, Some(110));
+
+        let res = handle(
+            &mut deps,
+            at_height("keeper", 109),
+            HandleMsg::Settle { padding: None },
+        );
+        assert_eq!(
+            res.unwrap_err(),
+            StdError::generic_err("The batch can't be settled before height 110")
+        );
+
+        // The token_b sellers are matched with part of the token_a, the rest trades with the
+        // pool, and everyone gets the same price. The swap expecting better is refunded.
+        let res = handle(
+            &mut deps,
+            at_height("keeper", 110),
+            HandleMsg::Settle { padding: None },
+        )
+        .unwrap();
+        let paid = |token_name: &str, recipient: &str, amount: u128| {
+            transfer(
+                &token(token_name),
+                HumanAddr::from(recipient),
+                Uint128(amount),
+            )
+            .unwrap()
+        };
+        assert_eq!(
+            res.messages,
+            vec![
+                paid("token_b", "seller_a", 39_683),
+                paid("token_a", "seller_b", 5_040),
+                paid("token_b", "greedy", 4_000),
+            ]
+        );
+        assert!(logged(&res, "refunded", 1));
+        let pool_after = pool(&deps);
+        assert_eq!(pool_after.assets[0].amount, Uint128(1_004_960));
+        assert_eq!(pool_after.assets[1].amount, Uint128(3_980_317));
+
+        let res = handle(
+            &mut deps,
+            at_height("keeper", 120),
+            HandleMsg::Settle { padding: None },
+        );
+        assert_eq!(
+            res.unwrap_err(),
+            StdError::generic_err("No swaps to settle")
+        );
+
+        // A swap in a later epoch settles the earlier batch first.
+        handle(
+            &mut deps,
+            at_height("token_a", 120),
+            swap("seller_a", 1_000, None),
+        )
+        .unwrap();
+        let res = handle(
+            &mut deps,
+            at_height("token_a", 130),
+            swap("seller_a", 1_000, None),
+        );
+        assert_eq!(res.unwrap().messages.len(), 1);
+
+        let res = handle(
+            &mut deps,
+            mock_env("provider", &[]),
+            HandleMsg::FlashSwap {
+                amounts: vec![Uint128(1_000), Uint128::zero()],
+                receiver: ContractLink {
+                    address: HumanAddr::from("borrower"),
+                    code_hash: "borrower_hash".to_string(),
+                },
+                msg: Binary::default(),
+                padding: None,
+            },
+        );
+        assert!(res.is_err());
+
+        // Stopping trading lets the queued swaps be refunded right away.
+        let msg = HandleMsg::SetStatus {
+            status: ContractStatus::StopTrading,
+        };
+        handle(&mut deps, mock_env("factory", &[]), msg).unwrap();
+        let res = handle(
+            &mut deps,
+            at_height("keeper", 131),
+            HandleMsg::Settle { padding: None },
+        )
+        .unwrap();
+        assert_eq!(res.messages, vec![paid("token_a", "seller_a", 1_000)]);
+        assert!(logged(&res, "refunded", 1));
+        assert!(batch_read(&deps.storage).may_load().unwrap().is_none());
+    }
+
+    #[test]
+    fn price_accumulators() {
+        let mut deps = init_pair();
+        let start = mock_env("factory", &[]).block.time;
+
+        add_liquidity(&mut deps, "provider", &[1_000_000, 4_000_000]);
+        let prices: PricesResponse =
+            from_binary(&query(&deps, QueryMsg::Prices {}).unwrap()).unwrap();
+        assert_eq!(prices.price_cumulative, [Uint128::zero(), Uint128::zero()]);
+        assert_eq!(prices.last_update, start);
+
+        let mut env = mock_env("provider", &[]);
+        env.block.time = start + 100;
+        let _res = handle(
+            &mut deps,
+            env,
+            HandleMsg::RemoveLiquidity {
+                share: Uint128(1_000_000),
+                asset: None,
+                padding: None,
+            },
+        )
+        .unwrap();
+
+        let prices: PricesResponse =
+            from_binary(&query(&deps, QueryMsg::Prices {}).unwrap()).unwrap();
+        assert_eq!(
+            prices.price_cumulative,
+            [
+                Uint128(400_000_000_000_000_000_000),
+                Uint128(25_000_000_000_000_000_000)
+            ]
+        );
+        assert_eq!(prices.last_update, start + 100);
+        assert_eq!(prices.reserves, [Uint128(500_000), Uint128(2_000_000)]);
+    }
+
+    #[test]
+    fn twap() {
+        let mut deps = init_pair();
+        let start = mock_env("factory", &[]).block.time;
+        add_liquidity(&mut deps, "provider", &[1_000_000, 4_000_000]);
+
+        let mut env = mock_env("token_a", &[]);
+        env.block.time = start + 100;
+        let msg = swap_msg("trader", 1_000, None);
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        let mut env = mock_env("provider", &[]);
+        env.block.time = start + 300;
+        let msg = HandleMsg::RemoveLiquidity {
+            share: Uint128(1_000_000),
+            asset: None,
+            padding: None,
+        };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        let twap = |deps: &Extern<MockStorage, MockApi, MockQuerier>, window_seconds| {
+            query(
+                deps,
+                QueryMsg::Twap {
+                    window_seconds,
+                    now: None,
+                },
+            )
+            .and_then(|res| from_binary::<TwapResponse>(&res))
+        };
+
+        // Starts exactly on the swap's observation.
+        let res = twap(&deps, 200).unwrap();
+        assert_eq!(res.window_end, start + 300);
+        assert_eq!(
+            res.price_average,
+            [
+                Uint128(3_992_023_976_023_976_023),
+                Uint128(250_499_497_499_509_511)
+            ]
+        );
+
+        // Starts halfway between the deposit and the swap.
+        let res = twap(&deps, 250).unwrap();
+        assert_eq!(
+            res.price_average,
+            [
+                Uint128(3_993_619_180_819_180_818),
+                Uint128(250_399_597_999_607_608)
+            ]
+        );
+
+        assert!(twap(&deps, 0).is_err());
+        assert!(twap(&deps, 301).is_err());
+
+        // Ending the window later leaves out the price before the swap, as the same reserves
+        // are taken to have held since the withdrawal.
+        let twap_at = |window_seconds, now| {
+            query(
+                &deps,
+                QueryMsg::Twap {
+                    window_seconds,
+                    now: Some(now),
+                },
+            )
+            .and_then(|res| from_binary::<TwapResponse>(&res))
+        };
+        let res = twap_at(250, start + 350).unwrap();
+        assert_eq!(res.window_end, start + 350);
+        assert_eq!(
+            res.price_average,
+            [
+                Uint128(3_992_023_976_023_976_023),
+                Uint128(250_499_497_499_509_511)
+            ]
+        );
+        assert_eq!(
+            twap_at(250, start + 300).unwrap(),
+            twap(&deps, 250).unwrap()
+        );
+        assert!(twap_at(100, start + 299).is_err());
+    }
+
+    #[test]
+    fn flash_swap() {
+        let mut deps = init_pair();
+        add_liquidity(&mut deps, "provider", &[1_000_000, 4_000_000]);
+        let receiver = ContractLink {
+            address: HumanAddr::from("arbitrage_bot"),
+            code_hash: "bot_hash".to_string(),
+        };
+        let flash_swap = |amount| HandleMsg::FlashSwap {
+            amounts: vec![Uint128::zero(), Uint128(amount)],
+            receiver: receiver.clone(),
+            msg: Binary::from(b"arbitrage".to_vec()),
+            padding: None,
+        };
+        let repay = |amount| HandleMsg::Receive {
+            sender: HumanAddr::from("arbitrage_bot"),
+            from: HumanAddr::from("arbitrage_bot"),
+            amount: Uint128(amount),
+            msg: Some(to_binary(&ReceiveMsg::RepayFlashSwap {}).unwrap()),
+        };
+        let finish = |deps: &mut Extern<MockStorage, MockApi, MockQuerier>| {
+            let env = mock_env("cosmos2contract", &[]);
+            handle(deps, env, HandleMsg::FinishFlashSwap {})
+        };
+
+        // The same trade as swapping 1000 token_a is repaid, one more token_b isn't.
+        let res = handle(&mut deps, mock_env("arbitrageur", &[]), flash_swap(3_985)).unwrap();
+        assert_eq!(res.messages.len(), 3);
+        let _res = handle(&mut deps, mock_env("token_a", &[]), repay(1_000)).unwrap();
+        assert!(finish(&mut deps).is_err());
+
+        // A failed finish reverts the whole transaction, including the lock.
+        let mut deps = init_pair();
+        add_liquidity(&mut deps, "provider", &[1_000_000, 4_000_000]);
+        let _res = handle(&mut deps, mock_env("arbitrageur", &[]), flash_swap(3_984)).unwrap();
+
+        let res = handle(&mut deps, mock_env("arbitrageur", &[]), flash_swap(1));
+        assert!(res.is_err());
+        let res = handle(
+            &mut deps,
+            mock_env("provider", &[]),
+            HandleMsg::RemoveLiquidity {
+                share: Uint128(1_000),
+                asset: None,
+                padding: None,
+            },
+        );
+        assert!(res.is_err());
+        let res = handle(
+            &mut deps,
+            mock_env("arbitrage_bot", &[]),
+            HandleMsg::FinishFlashSwap {},
+        );
+        assert_eq!(res.unwrap_err(), StdError::unauthorized());
+
+        let _res = handle(&mut deps, mock_env("token_a", &[]), repay(1_000)).unwrap();
+        let _res = finish(&mut deps).unwrap();
+        let pool = pool(&deps);
+        assert_eq!(pool.assets[0].amount, Uint128(1_001_000));
+        assert_eq!(pool.assets[1].amount, Uint128(3_996_016));
+
+        let res = handle(&mut deps, mock_env("token_a", &[]), repay(1_000));
+        assert!(res.is_err());
+    }
+
+    /// Answers SNIP-20 `TokenInfo` queries, with 6 decimals for token_a and 8 for token_b.
+    struct TokenQuerier;
+
+    impl Querier for TokenQuerier {
+        fn raw_query(&self, bin_request: &[u8]) -> QuerierResult {
+            let request: QueryRequest<Empty> = from_slice(bin_request).unwrap();
+            let decimals = match request {
+                QueryRequest::Wasm(WasmQuery::Smart { contract_addr, .. }) => {
+                    if contract_addr == HumanAddr::from("token_a") {
+                        6
+                    } else {
+                        8
+                    }
+                }
+                _ => panic!("unexpected query"),
+            };
+            #[derive(Serialize)]
+            struct TokenInfoResponse {
+                token_info: TokenInfo,
+            }
+            Ok(to_binary(&TokenInfoResponse {
+                token_info: TokenInfo {
+                    name: "token".to_string(),
+                    symbol: "TKN".to_string(),
+                    decimals,
+                    total_supply: None,
+                },
+            }))
+        }
+    }
+
+    #[test]
+    fn stable_swap() {
+        let mut deps = Extern {
+            storage: MockStorage::default(),
+            api: MockApi::new(20),
+            querier: TokenQuerier,
+        };
+        let start = mock_env("factory", &[]).block.time;
+        let msg = PairInitMsg {
+            assets_meta: assets_meta(),
+            version: "1.0.0".to_string(),
+            factory: ContractLink {
+                address: HumanAddr::from("factory"),
+                code_hash: "factory_hash".to_string(),
+            },
+            curve: Curve::StableSwap { amp: 100 },
+            swap_mode: SwapMode::Instant,
+            padding_block_size: None,
+        };
+        let _res = init(&mut deps, mock_env("factory", &[]), msg).unwrap();
+
+        // 1000 of each token.
+        let (meta_a, meta_b) = (token("token_a"), token("token_b"));
+        let msg = HandleMsg::AddLiquidity {
+            assets: vec![
+                Asset {
+                    meta: meta_a.clone(),
+                    amount: Uint128(1_000_000_000),
+                },
+                Asset {
+                    meta: meta_b,
+                    amount: Uint128(100_000_000_000),
+                },
+            ],
+            receiver: None,
+            min_share: None,
+            padding: None,
+        };
+        let _res = handle(&mut deps, mock_env("provider", &[]), msg).unwrap();
+
+        // Close to 1:1 less the fee, where constant product would return about 0.996.
+        let msg = QueryMsg::Simulation {
+            offer: Asset {
+                meta: meta_a,
+                amount: Uint128(1_000_000),
+            },
+            ask_asset: None,
+        };
+        let simulation: SimulationResponse = from_binary(&query(&deps, msg).unwrap()).unwrap();
+        assert!(simulation.return_amount > Uint128(99_690_000));
+        assert!(simulation.return_amount < Uint128(99_700_000));
+
+        // The spot price follows the invariant rather than the ratio of the reserves, which is
+        // about 33 once half as much token_a again is sold into the pool.
+        let price = |deps: &Extern<MockStorage, MockApi, TokenQuerier>| {
+            let pool = reserves_read(&deps.storage).load().unwrap();
+            let curve_state = curve_read(&deps.storage).load().unwrap();
+            scaled_price(spot_price(&deps.storage, &curve_state, &pool, 0, 1, start).unwrap())
+                .unwrap()
+        };
+        assert_eq!(price(&deps), 100 * PRICE_PRECISION);
+        let msg = swap_msg("trader", 500_000_000, None);
+        handle(&mut deps, mock_env("token_a", &[]), msg).unwrap();
+        assert!(price(&deps) > 98 * PRICE_PRECISION);
+        assert!(price(&deps) < 99 * PRICE_PRECISION);
+
+        let ramp = |future_amp, future_time| HandleMsg::RampAmplification {
+            future_amp,
+            future_time,
+        };
+        let res = handle(
+            &mut deps,
+            mock_env("provider", &[]),
+            ramp(200, start + 86_400),
+        );
+        assert_eq!(res.unwrap_err(), StdError::unauthorized());
+        let res = handle(
+            &mut deps,
+            mock_env("factory", &[]),
+            ramp(200, start + 3_600),
+        );
+        assert!(res.is_err());
+        let res = handle(
+            &mut deps,
+            mock_env("factory", &[]),
+            ramp(1_001, start + 86_400),
+        );
+        assert!(res.is_err());
+
+        let _res = handle(
+            &mut deps,
+            mock_env("factory", &[]),
+            ramp(200, start + 86_400),
+        )
+        .unwrap();
+        match curve_read(&deps.storage).load().unwrap() {
+            CurveState::StableSwap { decimals, amp } => {
+                assert_eq!(decimals, [6, 8]);
+                assert_eq!(amp.at(start + 43_200), 150);
+                assert_eq!(amp.at(start + 100_000), 200);
+            }
+            _ => panic!("expected a StableSwap pair"),
+        }
+    }
+
+    #[test]
+    fn weighted_pool() {
+        let init_weighted = |weights| {
+            let mut deps = mock_dependencies(20, &[]);
+            let msg = PairInitMsg {
+                assets_meta: assets_meta(),
+                version: "1.0.0".to_string(),
+                factory: ContractLink {
+                    address: HumanAddr::from("factory"),
+                    code_hash: "factory_hash".to_string(),
+                },
+                curve: Curve::Weighted { weights },
+                swap_mode: SwapMode::Instant,
+                padding_block_size: None,
+            };
+            init(&mut deps, mock_env("factory", &[]), msg).map(|_| deps)
+        };
+        assert!(init_weighted(vec![90, 20]).is_err());
+        assert!(init_weighted(vec![99, 1]).is_err());
+        assert!(init_weighted(vec![u64::MAX, 101]).is_err());
+
+        // 80/20 at a price of 1.
+        let mut deps = init_weighted(vec![80, 20]).unwrap();
+        add_liquidity(&mut deps, "provider", &[4_000_000, 1_000_000]);
+
+        let simulate = |deps: &Extern<MockStorage, MockApi, MockQuerier>, index: usize| {
+            let msg = QueryMsg::Simulation {
+                offer: Asset {
+                    meta: assets_meta()[index].clone(),
+                    amount: Uint128(1_000),
+                },
+                ask_asset: None,
+            };
+            from_binary::<SimulationResponse>(&query(deps, msg).unwrap())
+                .unwrap()
+                .return_amount
+        };
+        assert_eq!(simulate(&deps, 0), Uint128(996));
+        assert_eq!(simulate(&deps, 1), Uint128(996));
+
+        let msg = QueryMsg::Simulation {
+            offer: Asset {
+                meta: assets_meta()[1].clone(),
+                amount: Uint128(600_000),
+            },
+            ask_asset: None,
+        };
+        assert!(query(&deps, msg).is_err());
+
+        let mut env = mock_env("provider", &[]);
+        env.block.time += 10;
+        let msg = HandleMsg::RemoveLiquidity {
+            share: Uint128(1_000),
+            asset: None,
+            padding: None,
+        };
+        let _res = handle(&mut deps, env, msg).unwrap();
+        let prices: PricesResponse =
+            from_binary(&query(&deps, QueryMsg::Prices {}).unwrap()).unwrap();
+        assert_eq!(
+            prices.price_cumulative,
+            [
+                Uint128(10_000_000_000_000_000_000),
+                Uint128(10_000_000_000_000_000_000)
+            ]
+        );
+
+        // 1% more token_a on its own is worth 0.8% more of the pool, less the fee on the 20%
+        // of it traded for token_b.
+        let msg = HandleMsg::AddLiquidity {
+            assets: vec![Asset {
+                meta: assets_meta()[0].clone(),
+                amount: Uint128(39_990),
+            }],
+            receiver: None,
+            min_share: None,
+            padding: None,
+        };
+        let res = handle(&mut deps, mock_env("joiner", &[]), msg).unwrap();
+        assert_eq!(res.messages.len(), 1);
+        assert!(logged(&res, "share", 15_970));
+
+        // Redeeming it for token_b alone pays the fee on the 80% traded for token_a.
+        let msg = HandleMsg::RemoveLiquidity {
+            share: Uint128(15_970),
+            asset: Some(assets_meta()[1].clone()),
+            padding: None,
+        };
+        let res = handle(&mut deps, mock_env("joiner", &[]), msg).unwrap();
+        assert_eq!(res.messages.len(), 1);
+        assert!(logged(&res, "amounts", "0, 38892"));
+
+        let msg = HandleMsg::RemoveLiquidity {
+            share: Uint128(1_000_000),
+            asset: Some(assets_meta()[1].clone()),
+            padding: None,
+        };
+        assert!(handle(&mut deps, mock_env("provider", &[]), msg).is_err());
+    }
+
+    #[test]
+    fn three_asset_pool() {
+        let mut deps = Extern {
+            storage: MockStorage::default(),
+            api: MockApi::new(20),
+            querier: TokenQuerier,
+        };
+        let init_with = |deps: &mut Extern<MockStorage, MockApi, TokenQuerier>, curve| {
+            let msg = PairInitMsg {
+                assets_meta: vec![token("token_a"), token("token_b"), token("token_c")],
+                version: "1.0.0".to_string(),
+                factory: ContractLink {
+                    address: HumanAddr::from("factory"),
+                    code_hash: "factory_hash".to_string(),
+                },
+                curve,
+                swap_mode: SwapMode::Instant,
+                padding_block_size: None,
+            };
+            init(deps, mock_env("factory", &[]), msg)
+        };
+        assert!(init_with(&mut deps, Curve::ConstantProduct).is_err());
+        assert!(init_with(
+            &mut deps,
+            Curve::Weighted {
+                weights: vec![50, 50]
+            }
+        )
+        .is_err());
+        let _res = init_with(&mut deps, Curve::StableSwap { amp: 100 }).unwrap();
+
+        // 1000 of each token, token_a having 6 decimals and the others 8.
+        let amounts = [1_000_000_000, 100_000_000_000, 100_000_000_000];
+        let msg = HandleMsg::AddLiquidity {
+            assets: ["token_a", "token_b", "token_c"]
+                .iter()
+                .zip(amounts.iter())
+                .map(|(name, amount)| Asset {
+                    meta: token(name),
+                    amount: Uint128(*amount),
+                })
+                .collect(),
+            receiver: None,
+            min_share: None,
+            padding: None,
+        };
+        let res = handle(&mut deps, mock_env("provider", &[]), msg).unwrap();
+        assert_eq!(res.messages.len(), 3);
+
+        let swap = |ask_asset| {
+            let swap = ReceiveMsg::Swap {
+                ask_asset,
+                expected_return: None,
+                to: None,
+                callback: None,
+            };
+            swap_msg("trader", 100_000_000, Some(swap))
+        };
+        let res = handle(&mut deps, mock_env("token_b", &[]), swap(None));
+        assert!(res.is_err());
+        let res = handle(
+            &mut deps,
+            mock_env("token_b", &[]),
+            swap(Some(token("token_b"))),
+        );
+        assert!(res.is_err());
+
+        let simulation = QueryMsg::Simulation {
+            offer: Asset {
+                meta: token("token_b"),
+                amount: Uint128(100_000_000),
+            },
+            ask_asset: Some(token("token_c")),
+        };
+        let simulation: SimulationResponse =
+            from_binary(&query(&deps, simulation).unwrap()).unwrap();
+        assert!(simulation.return_amount > Uint128(99_690_000));
+        assert!(simulation.return_amount < Uint128(99_700_000));
+
+        let _res = handle(
+            &mut deps,
+            mock_env("token_b", &[]),
+            swap(Some(token("token_c"))),
+        )
+        .unwrap();
+        let pool = pool(&deps);
+        assert_eq!(pool.assets[0].amount, Uint128(1_000_000_000));
+        assert_eq!(pool.assets[1].amount, Uint128(100_100_000_000));
+        assert_eq!(
+            pool.assets[2].amount,
+            (Uint128(100_000_000_000) - simulation.return_amount).unwrap()
+        );
+    }
+
+    #[test]
+    fn concentrated_liquidity() {
+        let mut deps = mock_dependencies(20, &[]);
+        let msg = PairInitMsg {
+            assets_meta: assets_meta(),
+            version: "1.0.0".to_string(),
+            factory: ContractLink {
+                address: HumanAddr::from("factory"),
+                code_hash: "factory_hash".to_string(),
+            },
+            curve: Curve::Concentrated {
+                tick_spacing: 10,
+                initial_tick: 0,
+            },
+            swap_mode: SwapMode::Instant,
+            padding_block_size: None,
+        };
+        let _res = init(&mut deps, mock_env("factory", &[]), msg).unwrap();
+        let msg = HandleMsg::AddLiquidity {
+            assets: vec![],
+            receiver: None,
+            min_share: None,
+            padding: None,
+        };
+        assert!(handle(&mut deps, mock_env("provider", &[]), msg).is_err());
+
+        let open = |lower_tick, upper_tick, amounts: [u128; 2]| HandleMsg::OpenPosition {
+            lower_tick,
+            upper_tick,
+            amounts: vec![Uint128(amounts[0]), Uint128(amounts[1])],
+            receiver: None,
+            padding: None,
+        };
+        let res = handle(
+            &mut deps,
+            mock_env("provider", &[]),
+            open(-1_000, 1_005, [1_000_000, 1_000_000]),
+        );
+        assert!(res.is_err());
+
+        // Around a price of 1, and above it, which takes only token_a.
+        let res = handle(
+            &mut deps,
+            mock_env("provider", &[]),
+            open(-1_000, 1_000, [1_000_000, 1_000_000]),
+        )
+        .unwrap();
+        assert_eq!(res.messages.len(), 2);
+        let res = handle(
+            &mut deps,
+            mock_env("other_provider", &[]),
+            open(1_000, 2_000, [500_000, 500_000]),
+        )
+        .unwrap();
+        assert_eq!(res.messages.len(), 1);
+
+        // Positions are private to their owners, whose viewing key is "key".
+        for owner in ["provider", "other_provider"].iter() {
+            let msg = HandleMsg::SetViewingKey {
+                key: "key".to_string(),
+                padding: None,
+            };
+            handle(&mut deps, mock_env(*owner, &[]), msg).unwrap();
+        }
+        let range_position =
+            |deps: &Extern<MockStorage, MockApi, MockQuerier>, owner: &str, position_id| {
+                let msg = QueryMsg::RangePosition {
+                    position_id,
+                    address: HumanAddr::from(owner),
+                    key: "key".to_string(),
+                };
+                query(deps, msg).and_then(|res| from_binary::<RangePositionResponse>(&res))
+            };
+        let position = |deps: &Extern<MockStorage, MockApi, MockQuerier>, position_id| {
+            let owner = ["provider", "other_provider"][position_id as usize];
+            range_position(deps, owner, position_id)
+        };
+        assert_eq!(
+            range_position(&deps, "other_provider", 0).unwrap_err(),
+            StdError::generic_err("No position 0 of other_provider")
+        );
+        let around = position(&deps, 0).unwrap();
+        assert_eq!(around.amounts, vec![Uint128(999_998), Uint128(999_998)]);
+        let above = position(&deps, 1).unwrap();
+        assert_eq!(above.amounts, vec![Uint128(499_998), Uint128::zero()]);
+
+        let swap = |amount| swap_msg("trader", amount, None);
+        let simulation = QueryMsg::Simulation {
+            offer: Asset {
+                meta: token("token_a"),
+                amount: Uint128(100_000),
+            },
+            ask_asset: None,
+        };
+        // A full range pool of the same deposits would return 90_661.
+        let simulation: SimulationResponse =
+            from_binary(&query(&deps, simulation).unwrap()).unwrap();
+        assert_eq!(simulation.return_amount, Uint128(99_217));
+        let before = reserves_read(&deps.storage).load().unwrap();
+        let sqrt_price = concentrated_read(&deps.storage).load().unwrap().sqrt_price;
+        let _res = handle(&mut deps, mock_env("token_a", &[]), swap(100_000)).unwrap();
+        assert_eq!(pool(&deps).assets[1].amount, Uint128(900_782));
+
+        // At the old price of 1, paying out more than the 99_700 token_a left after the fee
+        // loses value.
+        check_invariants(&deps.storage, &before, Some(sqrt_price), 0).unwrap();
+        let mut paid_more = before.clone();
+        paid_more.amounts[1] += Uint128(99_700 - 99_217);
+        check_invariants(&deps.storage, &paid_more, Some(sqrt_price), 0).unwrap();
+        paid_more.amounts[1] += Uint128(1);
+        assert_eq!(
+            check_invariants(&deps.storage, &paid_more, Some(sqrt_price), 0).unwrap_err(),
+            StdError::generic_err("Invariant violated: the pool's reserves lost value net of fees")
+        );
+
+        // Fees go to the position the price traded in.
+        assert_eq!(
+            position(&deps, 0).unwrap().fees,
+            vec![Uint128(299), Uint128::zero()]
+        );
+        assert_eq!(position(&deps, 1).unwrap().fees, vec![Uint128::zero(); 2]);
+
+        let res = handle(
+            &mut deps,
+            mock_env("other_provider", &[]),
+            HandleMsg::CollectFees {
+                position_id: 0,
+                padding: None,
+            },
+        );
+        assert_eq!(res.unwrap_err(), StdError::unauthorized());
+        let res = handle(
+            &mut deps,
+            mock_env("provider", &[]),
+            HandleMsg::CollectFees {
+                position_id: 0,
+                padding: None,
+            },
+        )
+        .unwrap();
+        assert_eq!(res.messages.len(), 1);
+        assert_eq!(position(&deps, 0).unwrap().fees, vec![Uint128::zero(); 2]);
+
+        let res = handle(
+            &mut deps,
+            mock_env("provider", &[]),
+            HandleMsg::WithdrawPosition {
+                position_id: 0,
+                liquidity: around.liquidity,
+                padding: None,
+            },
+        )
+        .unwrap();
+        assert_eq!(res.messages.len(), 2);
+        assert!(position(&deps, 0).is_err());
+
+        // Only the range above is left, swapping token_b crosses into it.
+        let _res = handle(&mut deps, mock_env("token_b", &[]), swap(100_000)).unwrap();
+        let state: ConcentratedPool =
+            from_binary(&query(&deps, QueryMsg::ConcentratedPool {}).unwrap()).unwrap();
+        assert_eq!(state.tick, 1_175);
+        assert_eq!(state.liquidity, above.liquidity);
+        let above = position(&deps, 1).unwrap();
+        assert_eq!(above.amounts, vec![Uint128(410_572), Uint128(99_699)]);
+        assert_eq!(above.fees, vec![Uint128::zero(), Uint128(299)]);
+        let pool = pool(&deps);
+        assert_eq!(pool.assets[0].amount, Uint128(410_576));
+        assert_eq!(pool.assets[1].amount, Uint128(100_001));
     }
 }
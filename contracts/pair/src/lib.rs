@@ -1,6 +1,11 @@
+pub mod concentrated;
 pub mod contract;
+pub mod math;
 pub mod msg;
+pub mod stable;
 pub mod state;
+pub mod viewing_key;
+pub mod weighted;
 
 #[cfg(target_arch = "wasm32")]
 mod wasm {
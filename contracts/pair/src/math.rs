@@ -0,0 +1,191 @@
+use cosmwasm_std::{StdError, StdResult, Uint128};
+
+/// Swap fee taken from the offer amount, 0.3%.
+pub const FEE_NUMERATOR: u128 = 3;
+pub const FEE_DENOMINATOR: u128 = 1000;
+
+/// Fixed point scale of the cumulative prices.
+pub const PRICE_PRECISION: u128 = 1_000_000_000_000_000_000;
+
+/// Amount of the ask asset returned for `offer_amount`, keeping `x * y` constant after fees.
+pub fn compute_swap(
+    offer_pool: Uint128,
+    ask_pool: Uint128,
+    offer_amount: Uint128,
+) -> StdResult<Uint128> {
+    let offer_after_fee = mul(offer_amount.u128(), FEE_DENOMINATOR - FEE_NUMERATOR)?;
+    let numerator = mul(ask_pool.u128(), offer_after_fee)?;
+    let denominator = mul(offer_pool.u128(), FEE_DENOMINATOR)? + offer_after_fee;
+    if denominator == 0 {
+        return Err(StdError::generic_err("Pool has no liquidity"));
+    }
+
+    Ok(Uint128(numerator / denominator))
+}
+
+/// Adds the price of `base` in `quote` held for `elapsed` seconds to `cumulative`.
+///
+/// Like Uniswap V2 this wraps on overflow, so consumers must take the difference of two
+/// observations with `wrapping_sub`.
+pub fn accumulate_price(
+    cumulative: Uint128,
+    base: Uint128,
+    quote: Uint128,
+    elapsed: u64,
+) -> Uint128 {
+    if base.is_zero() || elapsed == 0 {
+        return cumulative;
+    }
+
+    let (base, quote) = (base.u128(), quote.u128());
+    let remainder = quote % base;
+    let fraction = match remainder.checked_mul(PRICE_PRECISION) {
+        Some(scaled) => scaled / base,
+        None => remainder / (base / PRICE_PRECISION),
+    };
+    let price = (quote / base)
+        .wrapping_mul(PRICE_PRECISION)
+        .wrapping_add(fraction);
+
+    Uint128(
+        cumulative
+            .u128()
+            .wrapping_add(price.wrapping_mul(elapsed as u128)),
+    )
+}
+
+/// Whether `balances` after a flash swap keep `x * y` at least at `reserves`, after the swap fee
+/// on the amounts `repaid`.
+pub fn flash_swap_repaid(
+    reserves: &[Uint128],
+    balances: &[Uint128],
+    repaid: &[Uint128],
+) -> StdResult<bool> {
+    if [reserves.len(), balances.len(), repaid.len()] != [2; 3] {
+        return Err(StdError::generic_err(
+            "Constant product pools have exactly two assets",
+        ));
+    }
+
+    let mut k_after = 1u128;
+    for (balance, repaid) in balances.iter().zip(repaid.iter()) {
+        let adjusted = mul(balance.u128(), FEE_DENOMINATOR)? - mul(repaid.u128(), FEE_NUMERATOR)?;
+        k_after = mul(k_after, adjusted)?;
+    }
+    let k_before = mul(
+        mul(reserves[0].u128(), reserves[1].u128())?,
+        FEE_DENOMINATOR * FEE_DENOMINATOR,
+    )?;
+    Ok(k_after >= k_before)
+}
+
+/// Shares minted for `deposits`, given the current `reserves` and `total_share`.
+///
+/// The first deposit into a two-asset pool mints the geometric mean of the deposits, into a
+/// larger pool their sum. Either only sets the scale of the shares.
+pub fn compute_share(
+    deposits: &[Uint128],
+    reserves: &[Uint128],
+    total_share: Uint128,
+) -> StdResult<Uint128> {
+    if total_share.is_zero() {
+        if let [a, b] = deposits {
+            return Ok(Uint128(isqrt(mul(a.u128(), b.u128())?)));
+        }
+        return deposits.iter().try_fold(Uint128::zero(), |sum, deposit| {
+            sum.u128()
+                .checked_add(deposit.u128())
+                .map(Uint128)
+                .ok_or_else(|| StdError::generic_err("Addition overflow"))
+        });
+    }
+
+    let mut share = u128::MAX;
+    for (deposit, reserve) in deposits.iter().zip(reserves.iter()) {
+        share = share.min(mul(deposit.u128(), total_share.u128())? / reserve.u128());
+    }
+    Ok(Uint128(share))
+}
+
+/// Amounts of each reserve `share` redeems for.
+pub fn compute_withdrawal(
+    share: Uint128,
+    reserves: &[Uint128],
+    total_share: Uint128,
+) -> StdResult<Vec<Uint128>> {
+    reserves
+        .iter()
+        .map(|reserve| {
+            Ok(Uint128(
+                mul(reserve.u128(), share.u128())? / total_share.u128(),
+            ))
+        })
+        .collect()
+}
+
+/// `a * b / c` with a 256 bit intermediate product.
+pub fn mul_div(a: u128, b: u128, c: u128) -> StdResult<u128> {
+    Ok(mul_div_rem(a, b, c)?.0)
+}
+
+/// Like `mul_div`, rounded up.
+pub fn mul_div_up(a: u128, b: u128, c: u128) -> StdResult<u128> {
+    let (quotient, remainder) = mul_div_rem(a, b, c)?;
+    if remainder == 0 {
+        return Ok(quotient);
+    }
+    quotient
+        .checked_add(1)
+        .ok_or_else(|| StdError::generic_err("Multiplication overflow"))
+}
+
+fn mul_div_rem(a: u128, b: u128, c: u128) -> StdResult<(u128, u128)> {
+    if c == 0 {
+        return Err(StdError::generic_err("Division by zero"));
+    }
+
+    const MASK: u128 = u64::MAX as u128;
+    let (a_hi, a_lo) = (a >> 64, a & MASK);
+    let (b_hi, b_lo) = (b >> 64, b & MASK);
+    let lo_lo = a_lo * b_lo;
+    let lo_hi = a_lo * b_hi;
+    let hi_lo = a_hi * b_lo;
+    let middle = (lo_lo >> 64) + (lo_hi & MASK) + (hi_lo & MASK);
+    let low = (lo_lo & MASK) | (middle << 64);
+    let high = a_hi * b_hi + (lo_hi >> 64) + (hi_lo >> 64) + (middle >> 64);
+    if high >= c {
+        return Err(StdError::generic_err("Multiplication overflow"));
+    }
+
+    // Long division of `high:low` by `c`, one bit at a time.
+    let mut remainder = high;
+    let mut quotient = 0u128;
+    for bit in (0..128).rev() {
+        let carry = remainder >> 127;
+        remainder = (remainder << 1) | ((low >> bit) & 1);
+        quotient <<= 1;
+        if carry == 1 || remainder >= c {
+            remainder = remainder.wrapping_sub(c);
+            quotient |= 1;
+        }
+    }
+    Ok((quotient, remainder))
+}
+
+pub fn mul(a: u128, b: u128) -> StdResult<u128> {
+    a.checked_mul(b)
+        .ok_or_else(|| StdError::generic_err("Multiplication overflow"))
+}
+
+pub fn isqrt(value: u128) -> u128 {
+    if value < 2 {
+        return value;
+    }
+    let mut x = value;
+    let mut y = x / 2 + 1;
+    while y < x {
+        x = y;
+        y = (x + value / x) / 2;
+    }
+    x
+}
@@ -1,18 +1,116 @@
+use common::pair::{Asset, AssetMeta, ContractLink, ContractStatus, ReserveVisibility, SwapLimits};
 use cosmwasm_std::{Binary, HumanAddr, Uint128};
 use schemars::JsonSchema;
+use secret_toolkit::permit::Permit;
 use serde::{Deserialize, Serialize};
 
+use crate::state::HistoryEntry;
+
+pub use common::pair::PairReceiveMsg as ReceiveMsg;
+
+/// `padding` is ignored, it only hides the length of the rest of the message. Messages sent by
+/// other contracts don't take it.
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum HandleMsg {
-    Recieve {
+    Receive {
         sender: HumanAddr,
         from: HumanAddr,
         amount: Uint128,
         msg: Option<Binary>,
     },
-    AddLiquidity {},
-    Swap {},
+    /// Pulls every asset of the pool from the sender with `TransferFrom`, so the pair needs an
+    /// allowance on each token. A weighted pair with liquidity also takes a single asset, of at
+    /// most half its reserve, and charges the swap fee on the part it trades for the others.
+    /// Fails if the deposit mints fewer than `min_share` liquidity tokens.
+    AddLiquidity {
+        assets: Vec<Asset>,
+        receiver: Option<HumanAddr>,
+        min_share: Option<Uint128>,
+        padding: Option<String>,
+    },
+    RemoveLiquidity {
+        share: Uint128,
+        /// Weighted pairs only. Redeems at most half of the shares for this asset alone, with
+        /// the swap fee on the part traded for the others.
+        asset: Option<AssetMeta>,
+        padding: Option<String>,
+    },
+    /// Sends `amounts` to `receiver`, executes `common::pair::FlashSwapReceiverMsg` on it, then
+    /// fails the whole transaction unless the repayments restore the pool's invariant, fee
+    /// included.
+    FlashSwap {
+        /// In the same order as `Pair::assets`.
+        amounts: Vec<Uint128>,
+        receiver: ContractLink,
+        msg: Binary,
+        padding: Option<String>,
+    },
+    /// Pair only, the last message of a flash swap.
+    FinishFlashSwap {},
+    /// Concentrated liquidity pairs only. Provides liquidity between two ticks, pulling at most
+    /// `amounts` with `TransferFrom` like `AddLiquidity`. The new position's id is logged.
+    OpenPosition {
+        lower_tick: i32,
+        upper_tick: i32,
+        /// In the same order as `Pair::assets`.
+        amounts: Vec<Uint128>,
+        receiver: Option<HumanAddr>,
+        padding: Option<String>,
+    },
+    /// Removes `liquidity` from a position and sends it along with the position's fees.
+    WithdrawPosition {
+        position_id: u64,
+        liquidity: Uint128,
+        padding: Option<String>,
+    },
+    CollectFees {
+        position_id: u64,
+        padding: Option<String>,
+    },
+    /// Fills as much of a limit order as the pool's price allows. Swaps fill crossed orders
+    /// too, but only a few at a time, so anyone can call this to fill the rest.
+    FillOrder {
+        order_id: u64,
+        padding: Option<String>,
+    },
+    /// Owner only, refunds what is left of a limit order.
+    CancelOrder {
+        order_id: u64,
+        padding: Option<String>,
+    },
+    /// Generates a viewing key for the sender from `entropy`, returned in
+    /// `HandleAnswer::CreateViewingKey`.
+    CreateViewingKey {
+        entropy: String,
+        padding: Option<String>,
+    },
+    SetViewingKey {
+        key: String,
+        padding: Option<String>,
+    },
+    /// Stops the sender's query permits named `permit_name` from being accepted.
+    RevokePermit {
+        permit_name: String,
+        padding: Option<String>,
+    },
+    /// Batch auction pairs only. Settles the queued swaps once their epoch has ended, which
+    /// anyone can do. The next swap settles them too.
+    Settle { padding: Option<String> },
+    /// Factory only, see `common::pair::PairHandleMsg`.
+    MigrateLiquidity {
+        owner: HumanAddr,
+        new_pair: ContractLink,
+        min_share: Option<Uint128>,
+    },
+    /// Factory only, see `common::pair::PairHandleMsg`.
+    RampAmplification { future_amp: u64, future_time: u64 },
+    /// Factory only, see `common::pair::PairHandleMsg`.
+    SetReserveVisibility { visibility: ReserveVisibility },
+    /// Factory only, see `common::pair::PairHandleMsg`.
+    SetStatus { status: ContractStatus },
+    /// Factory only, see `common::pair::PairHandleMsg`.
+    SetSwapLimits { limits: SwapLimits },
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -20,4 +118,204 @@ pub enum HandleMsg {
 pub enum QueryMsg {
     Pair {},
     Pool {},
+    /// Returns a `state::CurveState`.
+    Curve {},
+    /// Returns a `common::pair::SwapMode`.
+    SwapMode {},
+    /// Returns a `common::pair::SimulationResponse`.
+    Simulation {
+        offer: Asset,
+        /// Required for pools of more than two assets.
+        ask_asset: Option<AssetMeta>,
+    },
+    /// The accumulators follow the same rounded or delayed reserves as `reserves`, so they
+    /// reveal no more than `Pool` does.
+    Prices {},
+    /// Returns a `state::ConcentratedPool`.
+    ConcentratedPool {},
+    /// One of `address`'s concentrated liquidity positions, given its viewing key.
+    RangePosition {
+        position_id: u64,
+        address: HumanAddr,
+        key: String,
+    },
+    /// The open limit orders summed up by direction and price, rounded, and only where several
+    /// orders share a price. All that is public about them.
+    OrderBook {},
+    /// One of `address`'s limit orders, given its viewing key.
+    Order {
+        order_id: u64,
+        address: HumanAddr,
+        key: String,
+    },
+    /// The open limit orders of `address`, given its viewing key.
+    Orders {
+        address: HumanAddr,
+        key: String,
+    },
+    /// Time-weighted average prices over the `window_seconds` before `now`.
+    ///
+    /// Queries don't see the block time, so without `now` the window ends at the last reserve
+    /// change rather than the current block, however long ago that was. Callers that want the
+    /// price up to the current block pass its time as `now`.
+    Twap {
+        window_seconds: u64,
+        now: Option<u64>,
+    },
+    /// Batch auction pairs only. Only tells how many swaps are queued, not what they are.
+    Batch {},
+    /// The pair's `common::pair::ContractStatus`.
+    Status {},
+    /// The pair's `common::pair::SwapLimits`.
+    SwapLimits {},
+    /// The liquidity of `address`, given its viewing key.
+    Position {
+        address: HumanAddr,
+        key: String,
+    },
+    /// The swaps, deposits and withdrawals of `address`, newest first, given its viewing key.
+    History {
+        address: HumanAddr,
+        key: String,
+        page: u32,
+        /// At most `contract::MAX_PAGE_SIZE`.
+        page_size: u32,
+    },
+    /// Like `Pool`, but exact whatever the pair's `common::pair::ReserveVisibility`. Only for
+    /// liquidity providers, given their viewing key.
+    ExactPool {
+        address: HumanAddr,
+        key: String,
+    },
+    /// A private query of the signer of a SNIP-24 query permit, which must allow this pair.
+    WithPermit {
+        permit: Permit,
+        query: QueryWithPermit,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum QueryWithPermit {
+    /// Like `QueryMsg::Position`, needs the `balance` permission.
+    Position {},
+    /// Like `QueryMsg::ExactPool`, needs the `balance` permission.
+    ExactPool {},
+    /// Like `QueryMsg::Orders`, needs the `balance` permission.
+    Orders {},
+    /// Like `QueryMsg::RangePosition`, needs the `balance` permission.
+    RangePosition { position_id: u64 },
+    /// Like `QueryMsg::History`, needs the `history` permission.
+    History { page: u32, page_size: u32 },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum HandleAnswer {
+    CreateViewingKey { key: String },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct PoolResponse {
+    pub assets: Vec<Asset>,
+    pub total_share: Uint128,
+}
+
+/// Cumulative prices as of `last_update`. Prices are scaled by `math::PRICE_PRECISION`, and
+/// `reserves` have held since `last_update`, so the accumulators can be extrapolated to now.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct PricesResponse {
+    /// Price of each asset in the next one, wrapping around to the first, in the same order as
+    /// `Pair::assets`.
+    pub price_cumulative: Vec<Uint128>,
+    pub last_update: u64,
+    pub reserves: Vec<Uint128>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct RangePositionResponse {
+    pub lower_tick: i32,
+    pub upper_tick: i32,
+    pub liquidity: Uint128,
+    /// What the liquidity is worth at the current price.
+    pub amounts: Vec<Uint128>,
+    /// Fees earned and not collected yet.
+    pub fees: Vec<Uint128>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct OrderResponse {
+    pub order_id: u64,
+    pub offer_asset: AssetMeta,
+    pub ask_asset: AssetMeta,
+    pub offer_amount: Uint128,
+    pub ask_amount: Uint128,
+    pub offer_remaining: Uint128,
+    pub received: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct OrdersResponse {
+    pub orders: Vec<OrderResponse>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct OrderBookResponse {
+    pub depth: Vec<DepthBucket>,
+}
+
+/// The open orders offering `offer_asset` for `ask_asset` at prices that share their leading
+/// `contract::DEPTH_PRICE_DIGITS` digits. Only shown for at least `contract::MIN_DEPTH_ORDERS`
+/// orders.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct DepthBucket {
+    pub offer_asset: AssetMeta,
+    pub ask_asset: AssetMeta,
+    /// Lowest ask amount per offered unit in the bucket, scaled by `math::PRICE_PRECISION`.
+    pub price: Uint128,
+    /// Rounded down to its leading `contract::DEPTH_AMOUNT_DIGITS` digits.
+    pub offer_remaining: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct TwapResponse {
+    /// Average price of each asset in the next one, scaled by `math::PRICE_PRECISION`.
+    pub price_average: Vec<Uint128>,
+    /// Block time the window ends at, the `now` queried or else the last reserve change.
+    pub window_end: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct PositionResponse {
+    pub share: Uint128,
+    /// What `share` redeems for, in the same order as `Pair::assets`.
+    pub amounts: Vec<Uint128>,
+    /// Swap fees earned since the first deposit, which are part of `amounts` rather than paid
+    /// out. Reset once the whole share is withdrawn.
+    pub fees: Vec<Uint128>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct BatchResponse {
+    pub swap_count: u64,
+    /// First block height at which the queued swaps can be settled.
+    pub settle_height: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct HistoryResponse {
+    pub entries: Vec<HistoryEntry>,
+    /// Of all pages.
+    pub total: u64,
 }
@@ -0,0 +1,189 @@
+use cosmwasm_std::{StdError, StdResult, Uint128};
+
+use crate::math::{mul, mul_div, FEE_DENOMINATOR, FEE_NUMERATOR};
+
+/// Bounds of the amplification coefficient.
+pub const MIN_AMP: u64 = 1;
+pub const MAX_AMP: u64 = 1_000_000;
+/// An amplification ramp must last at least a day and change the coefficient at most tenfold.
+pub const MIN_RAMP_TIME: u64 = 86_400;
+pub const MAX_AMP_CHANGE: u64 = 10;
+
+const MAX_ITERATIONS: usize = 255;
+
+/// Factors that bring every asset to the precision of the one with the most decimals.
+pub fn scales(decimals: &[u8]) -> Vec<u128> {
+    let max = decimals.iter().copied().max().unwrap_or(0);
+    decimals
+        .iter()
+        .map(|decimals| 10u128.pow((max - decimals) as u32))
+        .collect()
+}
+
+/// The StableSwap invariant `D` of normalized `balances`, by Newton iteration.
+pub fn compute_d(amp: u64, balances: &[u128]) -> StdResult<u128> {
+    let n_coins = balances.len() as u128;
+    let sum = balances.iter().try_fold(0u128, |sum, balance| {
+        sum.checked_add(*balance)
+            .ok_or_else(|| StdError::generic_err("Addition overflow"))
+    })?;
+    if sum == 0 {
+        return Ok(0);
+    }
+    if balances.contains(&0) {
+        return Err(StdError::generic_err("Pool has no liquidity"));
+    }
+
+    let ann = amp as u128 * n_coins;
+    let mut d = sum;
+    for _ in 0..MAX_ITERATIONS {
+        let mut d_product = d;
+        for balance in balances.iter() {
+            d_product = mul_div(d_product, d, mul(*balance, n_coins)?)?;
+        }
+
+        let previous = d;
+        let numerator = mul(ann, sum)? + mul(d_product, n_coins)?;
+        let denominator = mul(ann - 1, d)? + mul(d_product, n_coins + 1)?;
+        d = mul_div(numerator, d, denominator)?;
+        if d.abs_diff(previous) <= 1 {
+            return Ok(d);
+        }
+    }
+
+    Err(StdError::generic_err(
+        "StableSwap invariant did not converge",
+    ))
+}
+
+/// The normalized balance of asset `j` that keeps invariant `d` when asset `i` is changed to
+/// `x`, all other `balances` staying the same.
+pub fn compute_y(
+    amp: u64,
+    i: usize,
+    j: usize,
+    x: u128,
+    balances: &[u128],
+    d: u128,
+) -> StdResult<u128> {
+    let n_coins = balances.len() as u128;
+    let ann = amp as u128 * n_coins;
+    let mut c = d;
+    let mut sum = 0u128;
+    for (k, balance) in balances.iter().enumerate() {
+        if k == j {
+            continue;
+        }
+        let balance = if k == i { x } else { *balance };
+        if balance == 0 {
+            return Err(StdError::generic_err("Pool has no liquidity"));
+        }
+        sum += balance;
+        c = mul_div(c, d, mul(balance, n_coins)?)?;
+    }
+    c = mul_div(c, d, mul(ann, n_coins)?)?;
+    let b = sum + d / ann;
+
+    let mut y = d;
+    for _ in 0..MAX_ITERATIONS {
+        let previous = y;
+        let denominator = (mul(y, 2)? + b)
+            .checked_sub(d)
+            .ok_or_else(|| StdError::generic_err("StableSwap invariant did not converge"))?;
+        y = mul_div(y, y, denominator)? + c / denominator;
+        if y.abs_diff(previous) <= 1 {
+            return Ok(y);
+        }
+    }
+
+    Err(StdError::generic_err(
+        "StableSwap invariant did not converge",
+    ))
+}
+
+/// Amount of the asset at `ask` returned for `offer_amount` of the one at `offer`, keeping `D`
+/// constant after fees.
+pub fn compute_swap(
+    amp: u64,
+    pools: &[Uint128],
+    offer: usize,
+    ask: usize,
+    offer_amount: Uint128,
+    scales: &[u128],
+) -> StdResult<Uint128> {
+    let balances = normalize(pools, scales)?;
+    let d = compute_d(amp, &balances)?;
+
+    let offer_after_fee =
+        mul(offer_amount.u128(), FEE_DENOMINATOR - FEE_NUMERATOR)? / FEE_DENOMINATOR;
+    let x = balances[offer] + mul(offer_after_fee, scales[offer])?;
+    let new_y = compute_y(amp, offer, ask, x, &balances, d)?;
+
+    // Rounds against the trader.
+    Ok(Uint128(
+        balances[ask].saturating_sub(new_y).saturating_sub(1) / scales[ask],
+    ))
+}
+
+/// The price of the asset at `base` in the one at `quote` at the margin, the ratio of the
+/// invariant's partial derivatives, as amounts of each that trade for one another.
+pub fn spot_price(
+    amp: u64,
+    pools: &[Uint128],
+    base: usize,
+    quote: usize,
+    scales: &[u128],
+) -> StdResult<(Uint128, Uint128)> {
+    let balances = normalize(pools, scales)?;
+    if balances.contains(&0) {
+        return Ok((pools[base], pools[quote]));
+    }
+    let d = compute_d(amp, &balances)?;
+
+    // dF/dx_i = Ann + D^(n+1) / (n^n * prod(x) * x_i)
+    let n_coins = balances.len() as u128;
+    let ann = amp as u128 * n_coins;
+    let mut d_product = d;
+    for balance in balances.iter() {
+        d_product = mul_div(d_product, d, mul(*balance, n_coins)?)?;
+    }
+    let quote_amount = mul_div(
+        pools[quote].u128(),
+        mul(ann, balances[base])? + d_product,
+        mul(ann, balances[quote])? + d_product,
+    )?;
+
+    Ok((pools[base], Uint128(quote_amount)))
+}
+
+/// Whether `balances` after a flash swap keep `D` at least at `reserves`, after the swap fee on
+/// the amounts `repaid`.
+pub fn flash_swap_repaid(
+    amp: u64,
+    reserves: &[Uint128],
+    balances: &[Uint128],
+    repaid: &[Uint128],
+    scales: &[u128],
+) -> StdResult<bool> {
+    let mut before = vec![];
+    let mut after = vec![];
+    for i in 0..reserves.len() {
+        before.push(mul(mul(reserves[i].u128(), FEE_DENOMINATOR)?, scales[i])?);
+        let adjusted = mul(balances[i].u128(), FEE_DENOMINATOR)?
+            .saturating_sub(mul(repaid[i].u128(), FEE_NUMERATOR)?);
+        after.push(mul(adjusted, scales[i])?);
+    }
+
+    if after.contains(&0) {
+        return Ok(false);
+    }
+    Ok(compute_d(amp, &after)? >= compute_d(amp, &before)?)
+}
+
+fn normalize(pools: &[Uint128], scales: &[u128]) -> StdResult<Vec<u128>> {
+    pools
+        .iter()
+        .zip(scales.iter())
+        .map(|(pool, scale)| mul(pool.u128(), *scale))
+        .collect()
+}
@@ -1,8 +1,285 @@
-use common::pair::Pair;
-use cosmwasm_std::Storage;
-use cosmwasm_storage::{singleton, singleton_read, ReadonlySingleton, Singleton};
+use common::pair::{
+    AssetMeta, ContractLink, ContractStatus, Pair, ReserveVisibility, SwapLimits, SwapMode,
+};
+use cosmwasm_std::{Binary, CanonicalAddr, Storage, Uint128};
+use cosmwasm_storage::{
+    bucket, bucket_read, singleton, singleton_read, Bucket, ReadonlyBucket, ReadonlySingleton,
+    Singleton,
+};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
 
 pub static CONFIG_KEY: &[u8] = b"pool";
+pub static FACTORY_KEY: &[u8] = b"factory";
+pub static RESERVES_KEY: &[u8] = b"reserves";
+pub static PREFIX_SHARES: &[u8] = b"shares";
+pub static SHARE_SUPPLY_KEY: &[u8] = b"share_supply";
+pub static BALANCES_KEY: &[u8] = b"balances";
+pub static PRICES_KEY: &[u8] = b"prices";
+pub static PREFIX_OBSERVATIONS: &[u8] = b"observations";
+pub static FLASH_SWAP_KEY: &[u8] = b"flash_swap";
+pub static CURVE_KEY: &[u8] = b"curve";
+pub static CONCENTRATED_KEY: &[u8] = b"concentrated";
+pub static PREFIX_TICKS: &[u8] = b"ticks";
+pub static PREFIX_POSITIONS: &[u8] = b"positions";
+pub static ORDER_BOOK_KEY: &[u8] = b"order_book";
+pub static PREFIX_ORDERS: &[u8] = b"orders";
+/// Ids of each owner's open orders, by canonical address.
+pub static PREFIX_OWNER_ORDERS: &[u8] = b"owner_orders";
+pub static PRNG_SEED_KEY: &[u8] = b"prng_seed";
+pub static PREFIX_VIEWING_KEYS: &[u8] = b"viewing_keys";
+pub static FEE_GROWTH_KEY: &[u8] = b"fee_growth";
+pub static PREFIX_LP_FEES: &[u8] = b"lp_fees";
+pub static SWAP_MODE_KEY: &[u8] = b"swap_mode";
+pub static BATCH_KEY: &[u8] = b"batch";
+pub static PADDING_BLOCK_SIZE_KEY: &[u8] = b"padding_block_size";
+pub static RESERVE_VISIBILITY_KEY: &[u8] = b"reserve_visibility";
+pub static DELAYED_RESERVES_KEY: &[u8] = b"delayed_reserves";
+pub static STATUS_KEY: &[u8] = b"status";
+pub static SWAP_LIMITS_KEY: &[u8] = b"swap_limits";
+/// Prefix of each address's `HistoryEntry` append store, followed by the canonical address.
+pub static PREFIX_HISTORY: &[u8] = b"history";
+/// Prefix of `secret_toolkit::permit::RevokedPermits`.
+pub const PREFIX_REVOKED_PERMITS: &str = "revoked_permits";
+
+/// Size of the observation ring buffer.
+pub const OBSERVATION_CAPACITY: u32 = 64;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Reserves {
+    /// In the same order as `Pair::assets`.
+    pub amounts: Vec<Uint128>,
+    pub total_share: Uint128,
+}
+
+/// Time-weighted price accumulators, see `math::accumulate_price`.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, JsonSchema)]
+pub struct Prices {
+    /// Sum of each asset's price in the next asset, wrapping around to the first, times the
+    /// seconds it held. In the same order as `Pair::assets`.
+    pub price_cumulative: Vec<Uint128>,
+    /// Block time of the last reserve change.
+    pub last_update: u64,
+    /// Observations recorded so far, the next one goes in slot
+    /// `observation_count % OBSERVATION_CAPACITY`.
+    pub observation_count: u32,
+}
+
+/// The cumulative prices at `timestamp`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Observation {
+    pub timestamp: u64,
+    pub price_cumulative: Vec<Uint128>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum CurveState {
+    ConstantProduct,
+    StableSwap {
+        /// Token decimals, in the same order as `Pair::assets`.
+        decimals: Vec<u8>,
+        amp: Amplification,
+    },
+    Weighted {
+        /// In the same order as `Pair::assets`.
+        weights: Vec<u64>,
+    },
+    /// Liquidity is provided as `RangePosition`s, see `ConcentratedPool`.
+    Concentrated {
+        tick_spacing: u32,
+    },
+}
+
+/// An amplification coefficient moving linearly from `initial` to `future`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Amplification {
+    pub initial: u64,
+    pub future: u64,
+    pub initial_time: u64,
+    pub future_time: u64,
+}
+
+impl Amplification {
+    pub fn at(&self, time: u64) -> u64 {
+        if time >= self.future_time {
+            return self.future;
+        }
+
+        let elapsed = time.saturating_sub(self.initial_time) as u128;
+        let duration = (self.future_time - self.initial_time) as u128;
+        let amp = if self.future > self.initial {
+            self.initial as u128 + (self.future - self.initial) as u128 * elapsed / duration
+        } else {
+            self.initial as u128 - (self.initial - self.future) as u128 * elapsed / duration
+        };
+        amp as u64
+    }
+}
+
+/// A flash swap in progress, which locks the reserves until it finishes.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct FlashSwap {
+    /// In the same order as `Pair::assets`.
+    pub amounts_out: Vec<Uint128>,
+    pub repaid: Vec<Uint128>,
+}
+
+/// Price and active liquidity of a concentrated liquidity pool. Square root prices are of the
+/// first asset in the second, in Q64.64 fixed point, see `concentrated`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ConcentratedPool {
+    pub sqrt_price: Uint128,
+    /// The highest tick at or below `sqrt_price`.
+    pub tick: i32,
+    /// Liquidity of the positions whose range contains `tick`.
+    pub liquidity: Uint128,
+    /// Fees earned per unit of liquidity over the pool's lifetime, in Q64.64. Wraps on
+    /// overflow, like every fee growth.
+    pub fee_growth_global: Vec<Uint128>,
+    /// Ticks that bound at least one position, sorted.
+    pub initialized_ticks: Vec<i32>,
+    pub position_count: u64,
+}
+
+/// Liquidity starting or ending at a tick.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, JsonSchema)]
+pub struct Tick {
+    /// Liquidity of the positions with this tick as their lower bound, which becomes active
+    /// when the price crosses it upwards.
+    pub liquidity_added: Uint128,
+    /// Liquidity of the positions with this tick as their upper bound.
+    pub liquidity_removed: Uint128,
+    /// Fee growth on the other side of this tick from the current one.
+    pub fee_growth_outside: Vec<Uint128>,
+}
+
+/// Liquidity provided between two ticks.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct RangePosition {
+    pub owner: CanonicalAddr,
+    pub lower_tick: i32,
+    pub upper_tick: i32,
+    pub liquidity: Uint128,
+    /// Fee growth inside the range as of the last update of this position.
+    pub fee_growth_inside_last: Vec<Uint128>,
+    /// Fees earned up to the last update and not collected yet.
+    pub fees_owed: Vec<Uint128>,
+}
+
+/// Open limit orders, best priced first within each direction. Kept private, queries only
+/// show their depth.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, JsonSchema)]
+pub struct OrderBook {
+    pub open: Vec<OpenOrder>,
+    pub order_count: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct OpenOrder {
+    pub id: u64,
+    pub offer_index: u8,
+    pub ask_index: u8,
+    /// Ask amount per offered unit, scaled by `math::PRICE_PRECISION` and rounded up.
+    pub price: Uint128,
+}
+
+/// Escrowed offer to swap `offer_amount` for at least `ask_amount`, at that rate for any part.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct LimitOrder {
+    pub owner: CanonicalAddr,
+    pub offer_index: u8,
+    pub ask_index: u8,
+    pub offer_amount: Uint128,
+    pub ask_amount: Uint128,
+    /// Still escrowed, zero once the order is filled or cancelled.
+    pub offer_remaining: Uint128,
+    /// Paid out to the owner by fills so far.
+    pub received: Uint128,
+}
+
+/// Swap fees earned by a liquidity provider's share, which stay in the pool.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct LpFees {
+    /// Fee growth per share as of the last change of the provider's share.
+    pub fee_growth_last: Vec<Uint128>,
+    /// Fees earned since the provider's first deposit, up to the last change of their share.
+    pub fees_accrued: Vec<Uint128>,
+}
+
+/// The reserves a `ReserveVisibility::Delayed` pair shows, and the newer ones waiting to be
+/// shown.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct DelayedReserves {
+    pub visible: Reserves,
+    /// Oldest first, at most one per block.
+    pub pending: Vec<ReserveSnapshot>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ReserveSnapshot {
+    pub height: u64,
+    pub reserves: Reserves,
+}
+
+/// Swaps queued in batch auction mode during `epoch`, see `common::pair::SwapMode`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Batch {
+    pub epoch: u64,
+    pub swaps: Vec<SwapRequest>,
+}
+
+/// A swap as received from the offer token, which batch auction pairs queue.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct SwapRequest {
+    pub sender: CanonicalAddr,
+    pub recipient: CanonicalAddr,
+    pub offer_index: u8,
+    pub offer_amount: Uint128,
+    /// A queued swap is refunded if the clearing price returns less.
+    pub expected_return: Option<Uint128>,
+    pub callback: Option<Binary>,
+}
+
+/// A swap, deposit or withdrawal in an address's private history.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct HistoryEntry {
+    pub action: HistoryAction,
+    pub block_height: u64,
+    pub block_time: u64,
+}
+
+/// Amounts of every asset are in the same order as `Pair::assets`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum HistoryAction {
+    /// Swaps by the address, including fills of its limit orders.
+    Swap {
+        offer_asset: AssetMeta,
+        offer_amount: Uint128,
+        ask_asset: AssetMeta,
+        return_amount: Uint128,
+        /// Return per offered unit, scaled by `math::PRICE_PRECISION`.
+        price: Uint128,
+    },
+    /// Liquidity provided to the address, as a share or, with `position_id`, as a range
+    /// position's liquidity.
+    AddLiquidity {
+        amounts: Vec<Uint128>,
+        share: Uint128,
+        position_id: Option<u64>,
+    },
+    /// Withdrawals, with the fees of a range position included in `amounts`.
+    RemoveLiquidity {
+        amounts: Vec<Uint128>,
+        share: Uint128,
+        position_id: Option<u64>,
+    },
+    CollectFees {
+        position_id: u64,
+        amounts: Vec<Uint128>,
+    },
+}
 
 pub fn config<S: Storage>(storage: &mut S) -> Singleton<S, Pair> {
     singleton(storage, CONFIG_KEY)
@@ -11,3 +288,228 @@ pub fn config<S: Storage>(storage: &mut S) -> Singleton<S, Pair> {
 pub fn config_read<S: Storage>(storage: &S) -> ReadonlySingleton<S, Pair> {
     singleton_read(storage, CONFIG_KEY)
 }
+
+pub fn factory<S: Storage>(storage: &mut S) -> Singleton<S, ContractLink> {
+    singleton(storage, FACTORY_KEY)
+}
+
+pub fn factory_read<S: Storage>(storage: &S) -> ReadonlySingleton<S, ContractLink> {
+    singleton_read(storage, FACTORY_KEY)
+}
+
+pub fn reserves<S: Storage>(storage: &mut S) -> Singleton<S, Reserves> {
+    singleton(storage, RESERVES_KEY)
+}
+
+pub fn reserves_read<S: Storage>(storage: &S) -> ReadonlySingleton<S, Reserves> {
+    singleton_read(storage, RESERVES_KEY)
+}
+
+pub fn prices<S: Storage>(storage: &mut S) -> Singleton<S, Prices> {
+    singleton(storage, PRICES_KEY)
+}
+
+pub fn prices_read<S: Storage>(storage: &S) -> ReadonlySingleton<S, Prices> {
+    singleton_read(storage, PRICES_KEY)
+}
+
+/// Price observations, keyed by big-endian ring buffer slot.
+pub fn observations<S: Storage>(storage: &mut S) -> Bucket<S, Observation> {
+    bucket(PREFIX_OBSERVATIONS, storage)
+}
+
+pub fn observations_read<S: Storage>(storage: &S) -> ReadonlyBucket<S, Observation> {
+    bucket_read(PREFIX_OBSERVATIONS, storage)
+}
+
+pub fn flash_swap<S: Storage>(storage: &mut S) -> Singleton<S, FlashSwap> {
+    singleton(storage, FLASH_SWAP_KEY)
+}
+
+pub fn flash_swap_read<S: Storage>(storage: &S) -> ReadonlySingleton<S, FlashSwap> {
+    singleton_read(storage, FLASH_SWAP_KEY)
+}
+
+pub fn curve<S: Storage>(storage: &mut S) -> Singleton<S, CurveState> {
+    singleton(storage, CURVE_KEY)
+}
+
+pub fn curve_read<S: Storage>(storage: &S) -> ReadonlySingleton<S, CurveState> {
+    singleton_read(storage, CURVE_KEY)
+}
+
+/// Liquidity shares, keyed by canonical address.
+pub fn shares<S: Storage>(storage: &mut S) -> Bucket<S, Uint128> {
+    bucket(PREFIX_SHARES, storage)
+}
+
+pub fn shares_read<S: Storage>(storage: &S) -> ReadonlyBucket<S, Uint128> {
+    bucket_read(PREFIX_SHARES, storage)
+}
+
+/// Sum of every `shares` balance, kept apart from `Reserves::total_share` so the two can be
+/// checked against each other.
+pub fn share_supply<S: Storage>(storage: &mut S) -> Singleton<S, Uint128> {
+    singleton(storage, SHARE_SUPPLY_KEY)
+}
+
+pub fn share_supply_read<S: Storage>(storage: &S) -> ReadonlySingleton<S, Uint128> {
+    singleton_read(storage, SHARE_SUPPLY_KEY)
+}
+
+/// Tokens the pair holds by its own bookkeeping, everything received less everything paid out,
+/// in the same order as `Pair::assets`. Besides the reserves this covers open orders and queued
+/// batch swaps.
+pub fn balances<S: Storage>(storage: &mut S) -> Singleton<S, Vec<Uint128>> {
+    singleton(storage, BALANCES_KEY)
+}
+
+pub fn balances_read<S: Storage>(storage: &S) -> ReadonlySingleton<S, Vec<Uint128>> {
+    singleton_read(storage, BALANCES_KEY)
+}
+
+pub fn concentrated<S: Storage>(storage: &mut S) -> Singleton<S, ConcentratedPool> {
+    singleton(storage, CONCENTRATED_KEY)
+}
+
+pub fn concentrated_read<S: Storage>(storage: &S) -> ReadonlySingleton<S, ConcentratedPool> {
+    singleton_read(storage, CONCENTRATED_KEY)
+}
+
+/// Initialized ticks, keyed by big-endian tick.
+pub fn ticks<S: Storage>(storage: &mut S) -> Bucket<S, Tick> {
+    bucket(PREFIX_TICKS, storage)
+}
+
+pub fn ticks_read<S: Storage>(storage: &S) -> ReadonlyBucket<S, Tick> {
+    bucket_read(PREFIX_TICKS, storage)
+}
+
+/// Concentrated liquidity positions, keyed by big-endian id.
+pub fn positions<S: Storage>(storage: &mut S) -> Bucket<S, RangePosition> {
+    bucket(PREFIX_POSITIONS, storage)
+}
+
+pub fn positions_read<S: Storage>(storage: &S) -> ReadonlyBucket<S, RangePosition> {
+    bucket_read(PREFIX_POSITIONS, storage)
+}
+
+pub fn order_book<S: Storage>(storage: &mut S) -> Singleton<S, OrderBook> {
+    singleton(storage, ORDER_BOOK_KEY)
+}
+
+pub fn order_book_read<S: Storage>(storage: &S) -> ReadonlySingleton<S, OrderBook> {
+    singleton_read(storage, ORDER_BOOK_KEY)
+}
+
+/// Limit orders, keyed by big-endian id. Kept after they close.
+pub fn orders<S: Storage>(storage: &mut S) -> Bucket<S, LimitOrder> {
+    bucket(PREFIX_ORDERS, storage)
+}
+
+pub fn orders_read<S: Storage>(storage: &S) -> ReadonlyBucket<S, LimitOrder> {
+    bucket_read(PREFIX_ORDERS, storage)
+}
+
+pub fn owner_orders<S: Storage>(storage: &mut S) -> Bucket<S, Vec<u64>> {
+    bucket(PREFIX_OWNER_ORDERS, storage)
+}
+
+pub fn owner_orders_read<S: Storage>(storage: &S) -> ReadonlyBucket<S, Vec<u64>> {
+    bucket_read(PREFIX_OWNER_ORDERS, storage)
+}
+
+pub fn prng_seed<S: Storage>(storage: &mut S) -> Singleton<S, Binary> {
+    singleton(storage, PRNG_SEED_KEY)
+}
+
+pub fn prng_seed_read<S: Storage>(storage: &S) -> ReadonlySingleton<S, Binary> {
+    singleton_read(storage, PRNG_SEED_KEY)
+}
+
+/// Hashed viewing keys, keyed by canonical address.
+pub fn viewing_keys<S: Storage>(storage: &mut S) -> Bucket<S, Binary> {
+    bucket(PREFIX_VIEWING_KEYS, storage)
+}
+
+pub fn viewing_keys_read<S: Storage>(storage: &S) -> ReadonlyBucket<S, Binary> {
+    bucket_read(PREFIX_VIEWING_KEYS, storage)
+}
+
+/// Swap fees earned per share over the pool's lifetime, in Q64.64 and in the same order as
+/// `Pair::assets`. Wraps on overflow like the concentrated liquidity fee growth.
+pub fn fee_growth<S: Storage>(storage: &mut S) -> Singleton<S, Vec<Uint128>> {
+    singleton(storage, FEE_GROWTH_KEY)
+}
+
+pub fn fee_growth_read<S: Storage>(storage: &S) -> ReadonlySingleton<S, Vec<Uint128>> {
+    singleton_read(storage, FEE_GROWTH_KEY)
+}
+
+/// Liquidity provider fees, keyed by canonical address.
+pub fn lp_fees<S: Storage>(storage: &mut S) -> Bucket<S, LpFees> {
+    bucket(PREFIX_LP_FEES, storage)
+}
+
+pub fn lp_fees_read<S: Storage>(storage: &S) -> ReadonlyBucket<S, LpFees> {
+    bucket_read(PREFIX_LP_FEES, storage)
+}
+
+pub fn swap_mode<S: Storage>(storage: &mut S) -> Singleton<S, SwapMode> {
+    singleton(storage, SWAP_MODE_KEY)
+}
+
+pub fn swap_mode_read<S: Storage>(storage: &S) -> ReadonlySingleton<S, SwapMode> {
+    singleton_read(storage, SWAP_MODE_KEY)
+}
+
+/// Responses are padded to a multiple of this many bytes.
+pub fn padding_block_size<S: Storage>(storage: &mut S) -> Singleton<S, u32> {
+    singleton(storage, PADDING_BLOCK_SIZE_KEY)
+}
+
+pub fn padding_block_size_read<S: Storage>(storage: &S) -> ReadonlySingleton<S, u32> {
+    singleton_read(storage, PADDING_BLOCK_SIZE_KEY)
+}
+
+pub fn reserve_visibility<S: Storage>(storage: &mut S) -> Singleton<S, ReserveVisibility> {
+    singleton(storage, RESERVE_VISIBILITY_KEY)
+}
+
+pub fn reserve_visibility_read<S: Storage>(storage: &S) -> ReadonlySingleton<S, ReserveVisibility> {
+    singleton_read(storage, RESERVE_VISIBILITY_KEY)
+}
+
+pub fn status<S: Storage>(storage: &mut S) -> Singleton<S, ContractStatus> {
+    singleton(storage, STATUS_KEY)
+}
+
+pub fn status_read<S: Storage>(storage: &S) -> ReadonlySingleton<S, ContractStatus> {
+    singleton_read(storage, STATUS_KEY)
+}
+
+pub fn swap_limits<S: Storage>(storage: &mut S) -> Singleton<S, SwapLimits> {
+    singleton(storage, SWAP_LIMITS_KEY)
+}
+
+pub fn swap_limits_read<S: Storage>(storage: &S) -> ReadonlySingleton<S, SwapLimits> {
+    singleton_read(storage, SWAP_LIMITS_KEY)
+}
+
+/// Only present in `ReserveVisibility::Delayed` mode.
+pub fn delayed_reserves<S: Storage>(storage: &mut S) -> Singleton<S, DelayedReserves> {
+    singleton(storage, DELAYED_RESERVES_KEY)
+}
+
+pub fn delayed_reserves_read<S: Storage>(storage: &S) -> ReadonlySingleton<S, DelayedReserves> {
+    singleton_read(storage, DELAYED_RESERVES_KEY)
+}
+
+/// Only present while swaps are queued.
+pub fn batch<S: Storage>(storage: &mut S) -> Singleton<S, Batch> {
+    singleton(storage, BATCH_KEY)
+}
+
+pub fn batch_read<S: Storage>(storage: &S) -> ReadonlySingleton<S, Batch> {
+    singleton_read(storage, BATCH_KEY)
+}
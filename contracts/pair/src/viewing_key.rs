@@ -0,0 +1,53 @@
+use cosmwasm_std::{Binary, Env};
+use sha2::{Digest, Sha256};
+
+/// Prefix of generated keys, as in the SNIP-20 reference implementation.
+pub const VIEWING_KEY_PREFIX: &str = "api_key_";
+
+/// Seed for the first generated key. Nothing about it is secret, the keys get their
+/// unpredictability from the entropy of every `CreateViewingKey` mixed into the seed.
+pub fn initial_seed(env: &Env) -> Binary {
+    let mut hasher = Sha256::new();
+    hasher.update(env.contract.address.as_str().as_bytes());
+    hasher.update(env.message.sender.as_str().as_bytes());
+    hasher.update(env.block.height.to_be_bytes());
+    hasher.update(env.block.time.to_be_bytes());
+    Binary(hasher.finalize().to_vec())
+}
+
+/// A new key from `seed`, the caller's `entropy` and the block, along with the seed for the
+/// next one.
+pub fn generate(seed: &Binary, env: &Env, entropy: &str) -> (String, Binary) {
+    let mut hasher = Sha256::new();
+    hasher.update(seed.as_slice());
+    hasher.update(env.message.sender.as_str().as_bytes());
+    hasher.update(env.block.height.to_be_bytes());
+    hasher.update(env.block.time.to_be_bytes());
+    hasher.update(entropy.as_bytes());
+    let key = hasher.finalize();
+
+    let encoded = format!("{}{}", VIEWING_KEY_PREFIX, Binary(key.to_vec()).to_base64());
+    let next_seed = Sha256::new().chain(seed.as_slice()).chain(key).finalize();
+    (encoded, Binary(next_seed.to_vec()))
+}
+
+/// What gets stored in place of `key`.
+pub fn hash(key: &str) -> Binary {
+    Binary(Sha256::digest(key.as_bytes()).to_vec())
+}
+
+/// Whether `key` hashes to `stored`, comparing in constant time. Without a stored key it still
+/// hashes, so a missing key takes as long to reject as a wrong one.
+pub fn matches(key: &str, stored: Option<&Binary>) -> bool {
+    let hashed = hash(key);
+    let expected = match stored {
+        Some(stored) => stored.as_slice(),
+        None => &[0u8; 32],
+    };
+    let diff = hashed
+        .as_slice()
+        .iter()
+        .zip(expected)
+        .fold(0, |diff, (a, b)| diff | (a ^ b));
+    stored.is_some() && hashed.len() == expected.len() && diff == 0
+}
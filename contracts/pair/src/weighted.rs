@@ -0,0 +1,262 @@
+use cosmwasm_std::{StdError, StdResult, Uint128};
+
+use crate::math::{mul, mul_div, mul_div_up, FEE_DENOMINATOR, FEE_NUMERATOR};
+
+/// Weights are percentages, each at least 2.
+pub const TOTAL_WEIGHT: u64 = 100;
+pub const MIN_WEIGHT: u64 = 2;
+/// A swap may offer at most half of the offer reserve, which keeps `pow` within the range its
+/// series converges quickly in. The same goes for the deposits, shares and withdrawals of a
+/// single asset.
+pub const MAX_IN_RATIO_DENOMINATOR: u128 = 2;
+
+const ONE: u128 = 1_000_000_000_000_000_000;
+const POW_PRECISION: u128 = 100_000_000;
+const MAX_SERIES_TERMS: u128 = 1_000;
+
+/// Amount of the ask asset returned for `offer_amount`, keeping the weighted product
+/// `offer_pool^w_offer * ask_pool^w_ask` constant after fees. `weights` are the offer and ask
+/// weights.
+pub fn compute_swap(
+    offer_pool: Uint128,
+    ask_pool: Uint128,
+    offer_amount: Uint128,
+    weights: [u64; 2],
+) -> StdResult<Uint128> {
+    let offer_after_fee =
+        mul(offer_amount.u128(), FEE_DENOMINATOR - FEE_NUMERATOR)? / FEE_DENOMINATOR;
+    out_given_in(offer_pool, ask_pool, Uint128(offer_after_fee), weights)
+}
+
+/// Like `compute_swap`, without taking the fee.
+pub fn out_given_in(
+    offer_pool: Uint128,
+    ask_pool: Uint128,
+    offer_amount: Uint128,
+    weights: [u64; 2],
+) -> StdResult<Uint128> {
+    let (offer_pool, ask_pool, offer_amount) =
+        (offer_pool.u128(), ask_pool.u128(), offer_amount.u128());
+    if offer_pool == 0 || ask_pool == 0 {
+        return Err(StdError::generic_err("Pool has no liquidity"));
+    }
+    if offer_amount > offer_pool / MAX_IN_RATIO_DENOMINATOR {
+        return Err(StdError::generic_err(
+            "Swap amount is more than half of the offer reserve",
+        ));
+    }
+
+    // Rounded up, so that the trader receives slightly less.
+    let new_offer_pool = offer_pool + offer_amount;
+    let base = (mul_div(offer_pool, ONE, new_offer_pool)? + 1).min(ONE);
+    let exponent = mul_div(weights[0] as u128, ONE, weights[1] as u128)?;
+    let ratio = pow(base, exponent)?.min(ONE);
+
+    Ok(Uint128(mul_div(ask_pool, ONE - ratio, ONE)?))
+}
+
+/// Whether `balances` after a flash swap are worth at least `reserves` along the weighted
+/// curve, after the swap fee on the amounts `repaid`. At most one asset may be left short.
+pub fn flash_swap_repaid(
+    reserves: &[Uint128],
+    balances: &[Uint128],
+    repaid: &[Uint128],
+    weights: &[u64],
+) -> StdResult<bool> {
+    let mut adjusted = vec![];
+    for (balance, repaid) in balances.iter().zip(repaid.iter()) {
+        adjusted.push(
+            balance
+                .u128()
+                .saturating_sub(mul(repaid.u128(), FEE_NUMERATOR)? / FEE_DENOMINATOR),
+        );
+    }
+
+    let mut deficits = (0..reserves.len()).filter(|&i| adjusted[i] < reserves[i].u128());
+    let deficit = match (deficits.next(), deficits.next()) {
+        (None, _) => return Ok(true),
+        (Some(i), None) => i,
+        _ => return Ok(false),
+    };
+
+    // The other assets have to pay for the short one, as fee-less swaps in turn.
+    let mut deficit_pool = reserves[deficit];
+    let mut covered = 0u128;
+    for surplus in (0..reserves.len()).filter(|&i| i != deficit) {
+        let amount = adjusted[surplus] - reserves[surplus].u128();
+        if amount == 0 {
+            continue;
+        }
+        let out = out_given_in(
+            reserves[surplus],
+            deficit_pool,
+            Uint128(amount.min(reserves[surplus].u128() / MAX_IN_RATIO_DENOMINATOR)),
+            [weights[surplus], weights[deficit]],
+        )?;
+        deficit_pool = (deficit_pool - out)?;
+        covered += out.u128();
+    }
+    Ok(covered >= reserves[deficit].u128() - adjusted[deficit])
+}
+
+/// Shares minted for depositing only `amount` of the asset with `weight` and `reserve`. The part
+/// of the deposit a proportional deposit would have had to swap first pays the swap fee.
+pub fn share_for_single_deposit(
+    reserve: Uint128,
+    total_share: Uint128,
+    amount: Uint128,
+    weight: u64,
+) -> StdResult<Uint128> {
+    let (reserve, amount) = (reserve.u128(), amount.u128());
+    if amount > reserve / MAX_IN_RATIO_DENOMINATOR {
+        return Err(StdError::generic_err(
+            "Deposit is more than half of the reserve",
+        ));
+    }
+
+    let amount = amount - swap_fee(amount, weight)?;
+    // (reserve / (reserve + amount))^weight, rounded up so that fewer shares are minted.
+    let base = (mul_div(reserve, ONE, reserve + amount)? + 1).min(ONE);
+    let exponent = mul_div(weight as u128, ONE, TOTAL_WEIGHT as u128)?;
+    let ratio = (pow(base, exponent)? + POW_PRECISION).min(ONE);
+
+    Ok(Uint128(mul_div(total_share.u128(), ONE - ratio, ratio)?))
+}
+
+/// Amount of the asset with `weight` and `reserve` that `share` redeems for on its own, after
+/// the swap fee on the part a proportional withdrawal would have had to swap.
+pub fn single_withdrawal(
+    reserve: Uint128,
+    total_share: Uint128,
+    share: Uint128,
+    weight: u64,
+) -> StdResult<Uint128> {
+    let (reserve, total_share, share) = (reserve.u128(), total_share.u128(), share.u128());
+    if share > total_share / MAX_IN_RATIO_DENOMINATOR {
+        return Err(StdError::generic_err(
+            "Cannot redeem more than half of the shares for a single asset",
+        ));
+    }
+
+    // 1 - (1 - share / total_share)^(1 / weight), with the power rounded up.
+    let base = mul_div_up(total_share - share, ONE, total_share)?;
+    let exponent = mul_div(TOTAL_WEIGHT as u128, ONE, weight as u128)?;
+    let ratio = (pow(base, exponent)? + POW_PRECISION).min(ONE);
+    let amount = mul_div(reserve, ONE - ratio, ONE)?;
+    if amount > reserve / MAX_IN_RATIO_DENOMINATOR {
+        return Err(StdError::generic_err(
+            "Withdrawal is more than half of the reserve",
+        ));
+    }
+
+    Ok(Uint128(amount - swap_fee(amount, weight)?))
+}
+
+/// The part of `amount` of the asset with `weight` that a deposit or withdrawal of it alone
+/// swaps for the other assets.
+pub fn swapped_part(amount: Uint128, weight: u64) -> StdResult<Uint128> {
+    Ok(Uint128(mul_div(
+        amount.u128(),
+        (TOTAL_WEIGHT - weight) as u128,
+        TOTAL_WEIGHT as u128,
+    )?))
+}
+
+/// Whether a share is worth at least as much of the weighted product of the reserves `after` as
+/// it was `before`, within the precision of `pow`. This is what deposits and withdrawals of a
+/// single asset keep, rather than the amount of every asset per share.
+pub fn share_value_held(
+    before: &[Uint128],
+    before_share: Uint128,
+    after: &[Uint128],
+    after_share: Uint128,
+    weights: &[u64],
+) -> StdResult<bool> {
+    if before
+        .iter()
+        .chain(after.iter())
+        .any(|amount| amount.is_zero())
+    {
+        return Ok(false);
+    }
+
+    // The product of every reserve's growth to the power of its weight, each rounded up.
+    let mut growth = ONE;
+    for ((old, new), weight) in before.iter().zip(after.iter()).zip(weights.iter()) {
+        let exponent = mul_div(*weight as u128, ONE, TOTAL_WEIGHT as u128)?;
+        let factor = if new >= old {
+            let inverse = pow(mul_div(old.u128(), ONE, new.u128())?, exponent)?;
+            mul_div(ONE, ONE, inverse.max(1))?
+        } else {
+            let base = mul_div_up(new.u128(), ONE, old.u128())?;
+            (pow(base, exponent)? + POW_PRECISION).min(ONE)
+        };
+        growth = mul_div(growth, factor, ONE)?;
+    }
+    Ok(growth >= mul_div(after_share.u128(), ONE, before_share.u128())?)
+}
+
+fn swap_fee(amount: u128, weight: u64) -> StdResult<u128> {
+    let swapped = swapped_part(Uint128(amount), weight)?.u128();
+    Ok(mul(swapped, FEE_NUMERATOR)? / FEE_DENOMINATOR)
+}
+
+/// `base^exponent` in 18 decimal fixed point, for `base` at most one.
+fn pow(base: u128, exponent: u128) -> StdResult<u128> {
+    let whole = pow_int(base, exponent / ONE)?;
+    let fraction = exponent % ONE;
+    if fraction == 0 {
+        return Ok(whole);
+    }
+
+    mul_div(whole, pow_series(base, fraction)?, ONE)
+}
+
+fn pow_int(mut base: u128, mut exponent: u128) -> StdResult<u128> {
+    let mut result = ONE;
+    while exponent > 0 {
+        if exponent % 2 == 1 {
+            result = mul_div(result, base, ONE)?;
+        }
+        base = mul_div(base, base, ONE)?;
+        exponent /= 2;
+    }
+    Ok(result)
+}
+
+/// Binomial series of `base^exponent` for a fractional `exponent`, as in Balancer's `bpowApprox`.
+fn pow_series(base: u128, exponent: u128) -> StdResult<u128> {
+    // base <= 1, so `x = 1 - base` and every power of `x` is subtracted or added in turn.
+    let x = ONE - base;
+    let mut term = ONE;
+    let mut sum = ONE as i128;
+    let mut negative_term = false;
+    for k in 1..=MAX_SERIES_TERMS {
+        let big_k = k * ONE;
+        let (c, c_negative) = if exponent >= big_k - ONE {
+            (exponent - (big_k - ONE), false)
+        } else {
+            (big_k - ONE - exponent, true)
+        };
+        term = mul_div(term, mul_div(c, x, ONE)?, big_k)?;
+        if term == 0 {
+            break;
+        }
+
+        // (-x)^k flips the sign every term, and so does a negative `c`.
+        negative_term = !negative_term;
+        if c_negative {
+            negative_term = !negative_term;
+        }
+        if negative_term {
+            sum -= term as i128;
+        } else {
+            sum += term as i128;
+        }
+        if term < POW_PRECISION {
+            break;
+        }
+    }
+
+    Ok(sum.max(0) as u128)
+}
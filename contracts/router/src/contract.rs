@@ -0,0 +1,737 @@
+use common::pair::{
+    Asset, AssetMeta, ContractLink, FactoryQueryMsg, PairInfo, PairQueryMsg, PairReceiveMsg,
+    SimulationResponse, SwapMode,
+};
+use cosmwasm_std::{
+    from_binary, log, to_binary, Api, Binary, CosmosMsg, Env, Extern, HandleResponse, HumanAddr,
+    InitResponse, Querier, QueryRequest, StdError, StdResult, Storage, Uint128, WasmQuery,
+};
+use secret_toolkit::snip20;
+
+use crate::{
+    msg::{HandleMsg, InitMsg, QueryMsg, QuoteResponse, ReceiveMsg, SwapOperation},
+    state::{config, config_read, route, route_read, Config, Hop, Route},
+};
+
+pub const BLOCK_SIZE: usize = 256;
+pub const DEFAULT_MAX_HOPS: u8 = 3;
+pub const MAX_HOPS: u8 = 4;
+
+pub fn init<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    _env: Env,
+    msg: InitMsg,
+) -> StdResult<InitResponse> {
+    config(&mut deps.storage).save(&Config {
+        factory: msg.factory,
+    })?;
+
+    Ok(InitResponse::default())
+}
+
+pub fn handle<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    msg: HandleMsg,
+) -> StdResult<HandleResponse> {
+    match msg {
+        HandleMsg::Receive {
+            from, amount, msg, ..
+        } => try_receive(deps, env, from, amount, msg),
+        HandleMsg::RegisterTokens { tokens } => try_register_tokens(env, tokens),
+    }
+}
+
+pub fn query<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    msg: QueryMsg,
+) -> StdResult<Binary> {
+    match msg {
+        QueryMsg::Config {} => to_binary(&config_read(&deps.storage).load()?),
+        QueryMsg::Quote {
+            offer_asset,
+            ask_asset,
+            amount,
+            max_hops,
+        } => to_binary(&query_quote(
+            deps,
+            offer_asset,
+            ask_asset,
+            amount,
+            max_hops,
+        )?),
+    }
+}
+
+pub fn query_quote<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    offer_asset: AssetMeta,
+    ask_asset: AssetMeta,
+    amount: Uint128,
+    max_hops: Option<u8>,
+) -> StdResult<QuoteResponse> {
+    let factory = config_read(&deps.storage).load()?.factory;
+    let max_hops = max_hops.unwrap_or(DEFAULT_MAX_HOPS).min(MAX_HOPS);
+
+    let mut search = RouteSearch {
+        querier: &deps.querier,
+        factory,
+        ask_asset,
+        path: vec![],
+        best: None,
+    };
+    let mut visited = vec![offer_asset.clone()];
+    search.explore(&offer_asset, amount, max_hops, &mut visited)?;
+
+    search.best.ok_or_else(|| StdError::not_found("Route"))
+}
+
+/// Depth-first search over the factory's pairs, simulating each hop with the output of the
+/// previous one. Pairs whose simulation fails, e.g. for lack of liquidity, and batch auction
+/// pairs are left out.
+struct RouteSearch<'a, Q: Querier> {
+    querier: &'a Q,
+    factory: ContractLink,
+    ask_asset: AssetMeta,
+    path: Vec<SwapOperation>,
+    best: Option<QuoteResponse>,
+}
+
+impl<'a, Q: Querier> RouteSearch<'a, Q> {
+    fn explore(
+        &mut self,
+        offer_asset: &AssetMeta,
+        amount: Uint128,
+        hops_left: u8,
+        visited: &mut Vec<AssetMeta>,
+    ) -> StdResult<()> {
+        if hops_left == 0 || amount.is_zero() {
+            return Ok(());
+        }
+
+        let pairs = pairs_by_token(self.querier, &self.factory, offer_asset)?;
+
+        // Routes are executed through two-asset pairs only, see `try_start_route`.
+        for info in pairs.into_iter().filter(|info| info.pair.assets.len() == 2) {
+            let next_asset = match info.pair.assets.iter().find(|asset| *asset != offer_asset) {
+                Some(asset) => asset.clone(),
+                None => continue,
+            };
+            if visited.contains(&next_asset) {
+                continue;
+            }
+            match swap_mode(self.querier, &info) {
+                Ok(SwapMode::Instant) => {}
+                _ => continue,
+            }
+
+            let simulation: StdResult<SimulationResponse> =
+                self.querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
+                    contract_addr: info.pair.contract_addr.clone(),
+                    callback_code_hash: info.code_hash.clone(),
+                    msg: to_binary(&PairQueryMsg::Simulation {
+                        offer: Asset {
+                            meta: offer_asset.clone(),
+                            amount,
+                        },
+                        ask_asset: Some(next_asset.clone()),
+                    })?,
+                }));
+            let simulation = match simulation {
+                Ok(simulation) => simulation,
+                Err(_) => continue,
+            };
+
+            self.path.push(SwapOperation {
+                pair_addr: info.pair.contract_addr,
+                offer_asset: offer_asset.clone(),
+                ask_asset: next_asset.clone(),
+            });
+            if next_asset == self.ask_asset {
+                let is_better = self
+                    .best
+                    .as_ref()
+                    .map(|best| simulation.return_amount > best.return_amount)
+                    .unwrap_or(true);
+                if is_better {
+                    self.best = Some(QuoteResponse {
+                        operations: self.path.clone(),
+                        return_amount: simulation.return_amount,
+                    });
+                }
+            } else {
+                visited.push(next_asset.clone());
+                self.explore(
+                    &next_asset,
+                    simulation.return_amount,
+                    hops_left - 1,
+                    visited,
+                )?;
+                visited.pop();
+            }
+            self.path.pop();
+        }
+
+        Ok(())
+    }
+}
+
+pub fn try_receive<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    from: HumanAddr,
+    amount: Uint128,
+    msg: Option<Binary>,
+) -> StdResult<HandleResponse> {
+    let msg: ReceiveMsg = from_binary(&msg.ok_or_else(|| StdError::generic_err("Missing msg"))?)?;
+
+    match msg {
+        ReceiveMsg::Swap {
+            operations,
+            minimum_receive,
+            to,
+        } => try_start_route(
+            deps,
+            env,
+            operations,
+            amount,
+            minimum_receive,
+            to.unwrap_or(from),
+        ),
+        ReceiveMsg::Hop {} => try_continue_route(deps, env, amount),
+    }
+}
+
+pub fn try_start_route<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    operations: Vec<SwapOperation>,
+    amount: Uint128,
+    minimum_receive: Uint128,
+    to: HumanAddr,
+) -> StdResult<HandleResponse> {
+    if route_read(&deps.storage).may_load()?.is_some() {
+        return Err(StdError::generic_err("A route is already in progress"));
+    }
+    let first = operations
+        .first()
+        .ok_or_else(|| StdError::generic_err("No swap operations"))?;
+    if token_info(&first.offer_asset).0 != env.message.sender {
+        return Err(StdError::generic_err(format!(
+            "Sent token does not match the first offer asset {}",
+            first.offer_asset
+        )));
+    }
+    if operations
+        .windows(2)
+        .any(|pair| pair[0].ask_asset != pair[1].offer_asset)
+    {
+        return Err(StdError::generic_err(
+            "Each operation must offer the asset asked by the previous one",
+        ));
+    }
+
+    // Only swap with pairs the factory created, of the operation's assets, that swap right away.
+    let factory = config_read(&deps.storage).load()?.factory;
+    let mut hops = vec![];
+    for operation in operations {
+        let info = pairs_by_token(&deps.querier, &factory, &operation.offer_asset)?
+            .into_iter()
+            .find(|info| {
+                info.pair.contract_addr == operation.pair_addr
+                    && info.pair.assets.contains(&operation.ask_asset)
+            })
+            .ok_or_else(|| {
+                StdError::generic_err(format!(
+                    "{} is not a pair of {} and {}",
+                    operation.pair_addr, operation.offer_asset, operation.ask_asset
+                ))
+            })?;
+        if swap_mode(&deps.querier, &info)? != SwapMode::Instant {
+            return Err(StdError::generic_err(format!(
+                "{} settles swaps in batches, which routes can't wait for",
+                operation.pair_addr
+            )));
+        }
+        hops.push(Hop {
+            pair_addr: operation.pair_addr,
+            offer_asset: operation.offer_asset,
+            ask_asset: operation.ask_asset,
+        });
+    }
+
+    let hop = hops.remove(0);
+    route(&mut deps.storage).save(&Route {
+        hops,
+        expected_asset: hop.ask_asset.clone(),
+        minimum_receive,
+        to,
+    })?;
+
+    Ok(HandleResponse {
+        messages: vec![swap_msg(&env, &hop, amount)?],
+        log: vec![log("action", "route"), log("hop", &hop.pair_addr)],
+        data: None,
+    })
+}
+
+pub fn try_continue_route<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    amount: Uint128,
+) -> StdResult<HandleResponse> {
+    let mut current = route_read(&deps.storage)
+        .may_load()?
+        .ok_or_else(StdError::unauthorized)?;
+    let (token_addr, token_code_hash) = token_info(&current.expected_asset);
+    if token_addr != env.message.sender {
+        return Err(StdError::unauthorized());
+    }
+
+    if current.hops.is_empty() {
+        if amount < current.minimum_receive {
+            return Err(StdError::generic_err(format!(
+                "Route returned {}, less than the minimum {}",
+                amount, current.minimum_receive
+            )));
+        }
+        route(&mut deps.storage).remove();
+
+        let transfer = snip20::transfer_msg(
+            current.to.clone(),
+            amount,
+            None,
+            None,
+            BLOCK_SIZE,
+            token_code_hash,
+            token_addr,
+        )?;
+
+        return Ok(HandleResponse {
+            messages: vec![transfer],
+            log: vec![
+                log("action", "route_complete"),
+                log("return_amount", amount),
+                log("to", current.to),
+            ],
+            data: None,
+        });
+    }
+
+    let hop = current.hops.remove(0);
+    current.expected_asset = hop.ask_asset.clone();
+    route(&mut deps.storage).save(&current)?;
+
+    Ok(HandleResponse {
+        messages: vec![swap_msg(&env, &hop, amount)?],
+        log: vec![log("action", "route"), log("hop", &hop.pair_addr)],
+        data: None,
+    })
+}
+
+pub fn try_register_tokens(env: Env, tokens: Vec<AssetMeta>) -> StdResult<HandleResponse> {
+    let mut messages = vec![];
+    for token in tokens.iter() {
+        let (contract_addr, token_code_hash) = token_info(token);
+        messages.push(snip20::register_receive_msg(
+            env.contract_code_hash.clone(),
+            None,
+            BLOCK_SIZE,
+            token_code_hash,
+            contract_addr,
+        )?);
+    }
+
+    Ok(HandleResponse {
+        messages,
+        log: vec![log("action", "register_tokens")],
+        data: None,
+    })
+}
+
+/// Every pair of the factory's that pools `token`, of any version.
+fn pairs_by_token<Q: Querier>(
+    querier: &Q,
+    factory: &ContractLink,
+    token: &AssetMeta,
+) -> StdResult<Vec<PairInfo>> {
+    querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
+        contract_addr: factory.address.clone(),
+        callback_code_hash: factory.code_hash.clone(),
+        msg: to_binary(&FactoryQueryMsg::PairsByToken {
+            token: token.clone(),
+        })?,
+    }))
+}
+
+/// The pair's `SwapMode`. Batch auction pairs pay out when their batch settles, not within the
+/// route.
+fn swap_mode<Q: Querier>(querier: &Q, info: &PairInfo) -> StdResult<SwapMode> {
+    querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
+        contract_addr: info.pair.contract_addr.clone(),
+        callback_code_hash: info.code_hash.clone(),
+        msg: to_binary(&PairQueryMsg::SwapMode {})?,
+    }))
+}
+
+/// Sends `amount` of the hop's offer asset to its pair, asking for the output to come back to
+/// the router with a `Hop` callback.
+fn swap_msg(env: &Env, hop: &Hop, amount: Uint128) -> StdResult<CosmosMsg> {
+    let (contract_addr, token_code_hash) = token_info(&hop.offer_asset);
+    snip20::send_msg(
+        hop.pair_addr.clone(),
+        amount,
+        Some(to_binary(&PairReceiveMsg::Swap {
+            ask_asset: Some(hop.ask_asset.clone()),
+            expected_return: None,
+            to: Some(env.contract.address.clone()),
+            callback: Some(to_binary(&ReceiveMsg::Hop {})?),
+        })?),
+        None,
+        None,
+        BLOCK_SIZE,
+        token_code_hash,
+        contract_addr,
+    )
+}
+
+fn token_info(asset: &AssetMeta) -> (HumanAddr, String) {
+    match asset {
+        AssetMeta::Token {
+            contract_addr,
+            token_code_hash,
+        } => (contract_addr.clone(), token_code_hash.clone()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use common::pair::{ContractLink, Pair};
+    use cosmwasm_std::{
+        from_slice,
+        testing::{mock_env, MockApi, MockStorage},
+        Empty, QuerierResult,
+    };
+
+    use super::*;
+
+    /// Answers factory queries from a fixed list of pairs, each of which returns its fixed
+    /// rate times the offer amount. Pairs with a rate of zero fail to simulate, pairs of version
+    /// "batch" are batch auction pairs.
+    struct FactoryQuerier {
+        pairs: Vec<(Pair, u128)>,
+    }
+
+    impl Querier for FactoryQuerier {
+        fn raw_query(&self, bin_request: &[u8]) -> QuerierResult {
+            let request: QueryRequest<Empty> = from_slice(bin_request).unwrap();
+            let (contract_addr, msg) = match request {
+                QueryRequest::Wasm(WasmQuery::Smart {
+                    contract_addr, msg, ..
+                }) => (contract_addr, msg),
+                _ => panic!("unexpected query"),
+            };
+
+            if contract_addr != HumanAddr::from("factory") {
+                let (pair, rate) = self
+                    .pairs
+                    .iter()
+                    .find(|(pair, _)| pair.contract_addr == contract_addr)
+                    .unwrap();
+                return Ok(match from_binary(&msg).unwrap() {
+                    PairQueryMsg::Simulation { .. } if *rate == 0 => {
+                        Err(StdError::generic_err("Pool has no liquidity"))
+                    }
+                    PairQueryMsg::Simulation { offer, .. } => to_binary(&SimulationResponse {
+                        return_amount: Uint128(offer.amount.u128() * rate),
+                    }),
+                    PairQueryMsg::SwapMode {} if pair.version == "batch" => {
+                        to_binary(&SwapMode::BatchAuction { epoch_blocks: 10 })
+                    }
+                    PairQueryMsg::SwapMode {} => to_binary(&SwapMode::Instant),
+                });
+            }
+
+            Ok(match from_binary(&msg).unwrap() {
+                FactoryQueryMsg::Pair { assets_meta, .. } => self
+                    .pairs
+                    .iter()
+                    .map(|(pair, _)| pair)
+                    .find(|pair| pair.has_assets(&assets_meta))
+                    .ok_or_else(|| StdError::not_found("Pair not found"))
+                    .and_then(to_binary),
+                FactoryQueryMsg::PairsByToken { token } => to_binary(
+                    &self
+                        .pairs
+                        .iter()
+                        .filter(|(pair, _)| pair.assets.contains(&token))
+                        .map(|(pair, _)| PairInfo {
+                            pair: pair.clone(),
+                            code_hash: "pair_hash".to_string(),
+                        })
+                        .collect::<Vec<_>>(),
+                ),
+            })
+        }
+    }
+
+    fn token(name: &str) -> AssetMeta {
+        AssetMeta::Token {
+            contract_addr: HumanAddr::from(name),
+            token_code_hash: format!("{}_hash", name),
+        }
+    }
+
+    fn pair(a: &str, b: &str) -> Pair {
+        versioned_pair(a, b, "1.0.0")
+    }
+
+    fn versioned_pair(a: &str, b: &str, version: &str) -> Pair {
+        let address = match version {
+            "1.0.0" => format!("pair_{}_{}", a, b),
+            _ => format!("pair_{}_{}_{}", a, b, version),
+        };
+        Pair::new(
+            &[token(a), token(b)],
+            version.to_string(),
+            HumanAddr::from(address),
+            MockApi::new(20),
+        )
+    }
+
+    fn operation(pair_addr: &str, offer: &str, ask: &str) -> SwapOperation {
+        SwapOperation {
+            pair_addr: HumanAddr::from(pair_addr),
+            offer_asset: token(offer),
+            ask_asset: token(ask),
+        }
+    }
+
+    fn init_router() -> Extern<MockStorage, MockApi, FactoryQuerier> {
+        let mut deps = Extern {
+            storage: MockStorage::default(),
+            api: MockApi::new(20),
+            querier: FactoryQuerier {
+                pairs: vec![
+                    (pair("token_a", "token_b"), 2),
+                    (pair("token_c", "token_b"), 3),
+                    (pair("token_a", "token_c"), 5),
+                    (pair("token_c", "token_d"), 1),
+                    (versioned_pair("token_a", "token_d", "1.0.0"), 0),
+                    (versioned_pair("token_a", "token_d", "2.0.0"), 4),
+                    (versioned_pair("token_b", "token_d", "batch"), 10),
+                ],
+            },
+        };
+        let msg = InitMsg {
+            factory: ContractLink {
+                address: HumanAddr::from("factory"),
+                code_hash: "factory_hash".to_string(),
+            },
+        };
+        let _res = init(&mut deps, mock_env("creator", &[]), msg).unwrap();
+        deps
+    }
+
+    fn receive(amount: u128, msg: &ReceiveMsg) -> HandleMsg {
+        HandleMsg::Receive {
+            sender: HumanAddr::from("trader"),
+            from: HumanAddr::from("trader"),
+            amount: Uint128(amount),
+            msg: Some(to_binary(msg).unwrap()),
+        }
+    }
+
+    fn swap(minimum_receive: u128) -> ReceiveMsg {
+        ReceiveMsg::Swap {
+            operations: vec![
+                operation("pair_token_a_token_b", "token_a", "token_b"),
+                operation("pair_token_c_token_b", "token_b", "token_c"),
+            ],
+            minimum_receive: Uint128(minimum_receive),
+            to: None,
+        }
+    }
+
+    #[test]
+    fn multi_hop_swap() {
+        let mut deps = init_router();
+
+        let res = handle(
+            &mut deps,
+            mock_env("token_b", &[]),
+            receive(1_000, &swap(90)),
+        );
+        assert!(res.is_err());
+        let msg = ReceiveMsg::Swap {
+            operations: vec![operation("pair_token_a_token_c", "token_a", "token_b")],
+            minimum_receive: Uint128(1),
+            to: None,
+        };
+        let res = handle(&mut deps, mock_env("token_a", &[]), receive(1_000, &msg));
+        assert_eq!(
+            res.unwrap_err(),
+            StdError::generic_err("pair_token_a_token_c is not a pair of token_a and token_b")
+        );
+
+        let res = handle(
+            &mut deps,
+            mock_env("token_a", &[]),
+            receive(1_000, &swap(90)),
+        )
+        .unwrap();
+        assert_eq!(
+            res.messages,
+            vec![swap_msg(
+                &mock_env("token_a", &[]),
+                &Hop {
+                    pair_addr: HumanAddr::from("pair_token_a_token_b"),
+                    offer_asset: token("token_a"),
+                    ask_asset: token("token_b"),
+                },
+                Uint128(1_000),
+            )
+            .unwrap()]
+        );
+
+        let res = handle(
+            &mut deps,
+            mock_env("token_c", &[]),
+            receive(500, &ReceiveMsg::Hop {}),
+        );
+        assert_eq!(res.unwrap_err(), StdError::unauthorized());
+
+        let res = handle(
+            &mut deps,
+            mock_env("token_b", &[]),
+            receive(500, &ReceiveMsg::Hop {}),
+        )
+        .unwrap();
+        assert_eq!(res.messages.len(), 1);
+
+        let res = handle(
+            &mut deps,
+            mock_env("token_c", &[]),
+            receive(89, &ReceiveMsg::Hop {}),
+        );
+        assert!(res.is_err());
+
+        let res = handle(
+            &mut deps,
+            mock_env("token_c", &[]),
+            receive(90, &ReceiveMsg::Hop {}),
+        )
+        .unwrap();
+        assert_eq!(
+            res.messages,
+            vec![snip20::transfer_msg(
+                HumanAddr::from("trader"),
+                Uint128(90),
+                None,
+                None,
+                BLOCK_SIZE,
+                "token_c_hash".to_string(),
+                HumanAddr::from("token_c"),
+            )
+            .unwrap()]
+        );
+        assert!(route_read(&deps.storage).may_load().unwrap().is_none());
+    }
+
+    #[test]
+    fn quote_best_route() {
+        let mut deps = init_router();
+
+        let msg = QueryMsg::Quote {
+            offer_asset: token("token_a"),
+            ask_asset: token("token_c"),
+            amount: Uint128(100),
+            max_hops: None,
+        };
+        let quote: QuoteResponse = from_binary(&query(&deps, msg).unwrap()).unwrap();
+        assert_eq!(quote.return_amount, Uint128(600));
+        assert_eq!(
+            quote.operations,
+            vec![
+                operation("pair_token_a_token_b", "token_a", "token_b"),
+                operation("pair_token_c_token_b", "token_b", "token_c"),
+            ]
+        );
+
+        let msg = QueryMsg::Quote {
+            offer_asset: token("token_a"),
+            ask_asset: token("token_c"),
+            amount: Uint128(100),
+            max_hops: Some(1),
+        };
+        let quote: QuoteResponse = from_binary(&query(&deps, msg).unwrap()).unwrap();
+        assert_eq!(quote.return_amount, Uint128(500));
+        assert_eq!(quote.operations.len(), 1);
+
+        // The pair of the older version fails to simulate and is skipped, and the route names
+        // the pair it was quoted on.
+        let msg = QueryMsg::Quote {
+            offer_asset: token("token_a"),
+            ask_asset: token("token_d"),
+            amount: Uint128(100),
+            max_hops: Some(1),
+        };
+        let quote: QuoteResponse = from_binary(&query(&deps, msg).unwrap()).unwrap();
+        assert_eq!(quote.return_amount, Uint128(400));
+        assert_eq!(
+            quote.operations,
+            vec![operation(
+                "pair_token_a_token_d_2.0.0",
+                "token_a",
+                "token_d"
+            )]
+        );
+
+        let msg = ReceiveMsg::Swap {
+            operations: quote.operations,
+            minimum_receive: Uint128(400),
+            to: None,
+        };
+        let res = handle(&mut deps, mock_env("token_a", &[]), receive(100, &msg)).unwrap();
+        assert_eq!(res.log[1], log("hop", "pair_token_a_token_d_2.0.0"));
+    }
+
+    #[test]
+    fn batch_auction_pairs() {
+        let mut deps = init_router();
+
+        // Through the batch auction pair token_a would return 2_000 token_d.
+        let msg = QueryMsg::Quote {
+            offer_asset: token("token_a"),
+            ask_asset: token("token_d"),
+            amount: Uint128(100),
+            max_hops: Some(2),
+        };
+        let quote: QuoteResponse = from_binary(&query(&deps, msg).unwrap()).unwrap();
+        assert_eq!(quote.return_amount, Uint128(500));
+        assert_eq!(
+            quote.operations,
+            vec![
+                operation("pair_token_a_token_c", "token_a", "token_c"),
+                operation("pair_token_c_token_d", "token_c", "token_d"),
+            ]
+        );
+
+        let msg = ReceiveMsg::Swap {
+            operations: vec![
+                operation("pair_token_a_token_b", "token_a", "token_b"),
+                operation("pair_token_b_token_d_batch", "token_b", "token_d"),
+            ],
+            minimum_receive: Uint128(1),
+            to: None,
+        };
+        let res = handle(&mut deps, mock_env("token_a", &[]), receive(100, &msg));
+        assert_eq!(
+            res.unwrap_err(),
+            StdError::generic_err(
+                "pair_token_b_token_d_batch settles swaps in batches, which routes can't wait for"
+            )
+        );
+        assert!(route_read(&deps.storage).may_load().unwrap().is_none());
+    }
+}
@@ -0,0 +1,70 @@
+use common::pair::{AssetMeta, ContractLink};
+use cosmwasm_std::{Binary, HumanAddr, Uint128};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct InitMsg {
+    pub factory: ContractLink,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum HandleMsg {
+    Receive {
+        sender: HumanAddr,
+        from: HumanAddr,
+        amount: Uint128,
+        msg: Option<Binary>,
+    },
+    /// Registers the router as a receiver of each token, which it needs before routing them.
+    RegisterTokens { tokens: Vec<AssetMeta> },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ReceiveMsg {
+    /// Swaps the sent token through `operations`, in order, and fails unless the last one
+    /// returns at least `minimum_receive`.
+    Swap {
+        operations: Vec<SwapOperation>,
+        minimum_receive: Uint128,
+        /// Defaults to the sender.
+        to: Option<HumanAddr>,
+    },
+    /// Output of a hop, sent back by the pair.
+    Hop {},
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct SwapOperation {
+    /// The factory may have a pair of the same assets for each pair code version, so the
+    /// operation names the one to swap with.
+    pub pair_addr: HumanAddr,
+    pub offer_asset: AssetMeta,
+    pub ask_asset: AssetMeta,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum QueryMsg {
+    Config {},
+    /// Finds the route of at most `max_hops` swaps that returns the most `ask_asset` for
+    /// `amount` of `offer_asset`.
+    Quote {
+        offer_asset: AssetMeta,
+        ask_asset: AssetMeta,
+        amount: Uint128,
+        /// Defaults to 3, capped at 4.
+        max_hops: Option<u8>,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct QuoteResponse {
+    pub operations: Vec<SwapOperation>,
+    pub return_amount: Uint128,
+}
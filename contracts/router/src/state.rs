@@ -0,0 +1,47 @@
+use common::pair::{AssetMeta, ContractLink};
+use cosmwasm_std::{HumanAddr, Storage, Uint128};
+use cosmwasm_storage::{singleton, singleton_read, ReadonlySingleton, Singleton};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+pub static CONFIG_KEY: &[u8] = b"config";
+pub static ROUTE_KEY: &[u8] = b"route";
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Config {
+    pub factory: ContractLink,
+}
+
+/// A swap in progress. Only exists within the transaction that started it.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Route {
+    /// Hops not started yet.
+    pub hops: Vec<Hop>,
+    /// Asset the router expects back from the hop in flight.
+    pub expected_asset: AssetMeta,
+    pub minimum_receive: Uint128,
+    pub to: HumanAddr,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Hop {
+    pub pair_addr: HumanAddr,
+    pub offer_asset: AssetMeta,
+    pub ask_asset: AssetMeta,
+}
+
+pub fn config<S: Storage>(storage: &mut S) -> Singleton<S, Config> {
+    singleton(storage, CONFIG_KEY)
+}
+
+pub fn config_read<S: Storage>(storage: &S) -> ReadonlySingleton<S, Config> {
+    singleton_read(storage, CONFIG_KEY)
+}
+
+pub fn route<S: Storage>(storage: &mut S) -> Singleton<S, Route> {
+    singleton(storage, ROUTE_KEY)
+}
+
+pub fn route_read<S: Storage>(storage: &S) -> ReadonlySingleton<S, Route> {
+    singleton_read(storage, ROUTE_KEY)
+}
@@ -1,6 +1,6 @@
 use core::fmt;
 
-use cosmwasm_std::{Api, CanonicalAddr, HumanAddr, StdResult, Uint128};
+use cosmwasm_std::{Api, Binary, CanonicalAddr, HumanAddr, StdError, StdResult, Uint128};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
@@ -33,13 +33,20 @@ impl PartialEq for Asset {
 #[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum AssetMeta {
-    Token { contract_addr: HumanAddr },
+    Token {
+        contract_addr: HumanAddr,
+        token_code_hash: String,
+    },
 }
 impl AssetMeta {
     pub fn to_raw<A: Api>(&self, api: A) -> StdResult<AssetMetaRaw> {
         match self {
-            AssetMeta::Token { contract_addr } => Ok(AssetMetaRaw::Token {
+            AssetMeta::Token {
+                contract_addr,
+                token_code_hash,
+            } => Ok(AssetMetaRaw::Token {
                 contract_addr: api.canonical_address(contract_addr)?,
+                token_code_hash: token_code_hash.clone(),
             }),
         }
     }
@@ -47,9 +54,14 @@ impl AssetMeta {
 impl PartialEq for AssetMeta {
     fn eq(&self, other: &Self) -> bool {
         match (self, other) {
-            (AssetMeta::Token { contract_addr: a }, AssetMeta::Token { contract_addr: b }) => {
-                a == b
-            }
+            (
+                AssetMeta::Token {
+                    contract_addr: a, ..
+                },
+                AssetMeta::Token {
+                    contract_addr: b, ..
+                },
+            ) => a == b,
         }
     }
 }
@@ -57,7 +69,7 @@ impl PartialEq for AssetMeta {
 impl fmt::Display for AssetMeta {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            AssetMeta::Token { contract_addr } => write!(f, "{}", contract_addr),
+            AssetMeta::Token { contract_addr, .. } => write!(f, "{}", contract_addr),
         }
     }
 }
@@ -80,73 +92,135 @@ impl AssetRaw {
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum AssetMetaRaw {
-    Token { contract_addr: CanonicalAddr },
+    Token {
+        contract_addr: CanonicalAddr,
+        token_code_hash: String,
+    },
 }
 
 impl AssetMetaRaw {
     pub fn to_human<A: Api>(&self, api: A) -> StdResult<AssetMeta> {
         match self {
-            AssetMetaRaw::Token { contract_addr } => Ok(AssetMeta::Token {
+            AssetMetaRaw::Token {
+                contract_addr,
+                token_code_hash,
+            } => Ok(AssetMeta::Token {
                 contract_addr: api.human_address(contract_addr)?,
+                token_code_hash: token_code_hash.clone(),
             }),
         }
     }
     pub fn as_bytes(&self) -> &[u8] {
         match self {
-            AssetMetaRaw::Token { contract_addr } => contract_addr.as_slice(),
+            AssetMetaRaw::Token { contract_addr, .. } => contract_addr.as_slice(),
         }
     }
 }
 
+/// Most assets a pool can hold.
+pub const MAX_ASSETS: usize = 8;
+
+/// Checks that a pool of `assets` would hold between 2 and `MAX_ASSETS` distinct assets.
+pub fn validate_assets(assets: &[AssetMeta]) -> StdResult<()> {
+    if assets.len() < 2 || assets.len() > MAX_ASSETS {
+        return Err(StdError::generic_err(format!(
+            "A pool holds between 2 and {} assets",
+            MAX_ASSETS
+        )));
+    }
+    for (i, asset) in assets.iter().enumerate() {
+        if assets[..i].contains(asset) {
+            return Err(StdError::generic_err(format!("{} is listed twice", asset)));
+        }
+    }
+    Ok(())
+}
+
+/// Block size the responses of the factory and its pairs are padded to by default, hiding
+/// their exact length.
+pub const DEFAULT_PADDING_BLOCK_SIZE: u32 = 256;
+
+pub fn validate_padding_block_size(block_size: u32) -> StdResult<()> {
+    if block_size == 0 {
+        return Err(StdError::generic_err("Padding block size must not be zero"));
+    }
+    Ok(())
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
 pub struct Pair {
     pub id: Vec<u8>,
-    pub assets: [AssetMeta; 2],
+    pub assets: Vec<AssetMeta>,
     pub contract_addr: HumanAddr,
+    /// Version of the pair code this pair was instantiated from.
+    pub version: String,
 }
 
 impl Pair {
-    pub fn new<A: Api>(assets: &[AssetMeta; 2], contract_addr: HumanAddr, api: A) -> Self {
-        let assets_raw = [
-            assets[0].to_raw(api).unwrap(),
-            assets[1].to_raw(api).unwrap(),
-        ];
-        let id = [assets_raw[0].as_bytes(), assets_raw[1].as_bytes()].concat();
+    pub fn new<A: Api>(
+        assets: &[AssetMeta],
+        version: String,
+        contract_addr: HumanAddr,
+        api: A,
+    ) -> Self {
+        let assets_raw: Vec<AssetMetaRaw> = assets
+            .iter()
+            .map(|asset| asset.to_raw(api).unwrap())
+            .collect();
+        let id = assets_raw
+            .iter()
+            .flat_map(|asset| asset.as_bytes().iter().copied())
+            .collect();
 
         Self {
             id,
-            assets: assets.clone(),
+            assets: assets.to_vec(),
             contract_addr,
+            version,
         }
     }
+    /// Whether this pair pools exactly `assets`, in any order.
+    pub fn has_assets(&self, assets: &[AssetMeta]) -> bool {
+        self.assets.len() == assets.len() && assets.iter().all(|asset| self.assets.contains(asset))
+    }
     pub fn to_raw<A: Api>(&self, api: A) -> StdResult<PairRaw> {
         Ok(PairRaw {
             id: self.id.clone(),
-            assets: [self.assets[0].to_raw(api)?, self.assets[1].to_raw(api)?],
+            assets: self
+                .assets
+                .iter()
+                .map(|asset| asset.to_raw(api))
+                .collect::<StdResult<_>>()?,
             contract_addr: api.canonical_address(&self.contract_addr)?,
+            version: self.version.clone(),
         })
     }
 }
 impl PartialEq for Pair {
     fn eq(&self, other: &Self) -> bool {
-        self.assets[0] == other.assets[0] && self.assets[1] == other.assets[1]
-            || self.assets[0] == other.assets[1] && self.assets[1] == other.assets[0]
+        self.has_assets(&other.assets)
     }
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct PairRaw {
     pub id: Vec<u8>,
-    pub assets: [AssetMetaRaw; 2],
+    pub assets: Vec<AssetMetaRaw>,
     pub contract_addr: CanonicalAddr,
+    pub version: String,
 }
 
 impl PairRaw {
     pub fn to_human<A: Api>(&self, api: A) -> StdResult<Pair> {
         Ok(Pair {
             id: self.id.clone(),
-            assets: [self.assets[0].to_human(api)?, self.assets[1].to_human(api)?],
+            assets: self
+                .assets
+                .iter()
+                .map(|asset| asset.to_human(api))
+                .collect::<StdResult<_>>()?,
             contract_addr: api.human_address(&self.contract_addr)?,
+            version: self.version.clone(),
         })
     }
 }
@@ -154,5 +228,221 @@ impl PairRaw {
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub struct PairInitMsg {
-    pub assets_meta: [AssetMeta; 2],
+    pub assets_meta: Vec<AssetMeta>,
+    pub version: String,
+    pub factory: ContractLink,
+    pub curve: Curve,
+    #[serde(default)]
+    pub swap_mode: SwapMode,
+    /// Defaults to `DEFAULT_PADDING_BLOCK_SIZE`.
+    pub padding_block_size: Option<u32>,
+}
+
+/// The invariant a pair trades along.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Curve {
+    /// `x * y = k`, for two assets only.
+    #[default]
+    ConstantProduct,
+    /// Curve's StableSwap invariant for pegged assets, flatter around the peg the higher the
+    /// amplification coefficient `amp`.
+    StableSwap { amp: u64 },
+    /// Balancer's constant mean `x^w_x * y^w_y * ... = k`, with weights in percent, in the
+    /// same order as the assets.
+    Weighted { weights: Vec<u64> },
+    /// Uniswap V3's concentrated liquidity, provided within price ranges as positions between
+    /// ticks `tick_spacing` apart. Starts at a price of `1.0001^initial_tick` of the first asset
+    /// in the second. For two assets only.
+    Concentrated {
+        tick_spacing: u32,
+        initial_tick: i32,
+    },
+}
+
+/// When a pair's swaps trade against its pool.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SwapMode {
+    /// Each swap trades right away.
+    #[default]
+    Instant,
+    /// Swaps are queued for epochs of `epoch_blocks` blocks, then settled together at a single
+    /// clearing price by the pair's `Settle`, so their order within an epoch doesn't matter. Swap
+    /// outputs arrive with the settlement, so these pairs take no swap callbacks and routes
+    /// can't go through them. For two assets only.
+    BatchAuction { epoch_blocks: u64 },
+}
+
+/// What a pair's public queries reveal of its reserves, simulations included. The price
+/// accumulators of the `Prices` and `Twap` queries still follow the exact ratio of the reserves,
+/// so this hides the depth of the pool but not its price.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ReserveVisibility {
+    #[default]
+    Exact,
+    /// Each amount rounded down to its `significant_digits` leading digits.
+    Rounded { significant_digits: u8 },
+    /// The reserves as of at least `blocks` blocks before the pair's latest transaction.
+    Delayed { blocks: u64 },
+}
+
+/// Basis points in a whole.
+pub const MAX_BPS: u16 = 10_000;
+
+/// Caps on a single swap against a pair, in basis points.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct SwapLimits {
+    /// How far a swap may push the offer asset's spot price down, the price the pair's TWAP
+    /// accumulates.
+    pub max_price_impact_bps: u16,
+    /// How much may be offered, as a fraction of the offer asset's reserve.
+    pub max_trade_size_bps: Option<u16>,
+}
+
+impl Default for SwapLimits {
+    /// No caps at all.
+    fn default() -> Self {
+        SwapLimits {
+            max_price_impact_bps: MAX_BPS,
+            max_trade_size_bps: None,
+        }
+    }
+}
+
+/// How much of a pair is open. Set by the factory admin, for example to freeze swaps while a bug
+/// in the curve math is fixed without locking liquidity providers in.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ContractStatus {
+    #[default]
+    Normal,
+    /// Only withdrawals: removing liquidity, collecting fees, cancelling orders and refunding
+    /// queued batch swaps.
+    StopTrading,
+    /// Nothing that moves funds.
+    StopAll,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ContractLink {
+    pub address: HumanAddr,
+    pub code_hash: String,
+}
+
+/// Factory handles called by pairs.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum FactoryHandleMsg {
+    /// Sent by a freshly instantiated pair so the factory learns its address.
+    RegisterPair {},
+}
+
+/// Factory queries used by other contracts.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum FactoryQueryMsg {
+    Pair {
+        assets_meta: Vec<AssetMeta>,
+        version: Option<String>,
+    },
+    PairsByToken {
+        token: AssetMeta,
+    },
+}
+
+/// A registered pair along with the code hash needed to call it.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PairInfo {
+    pub pair: Pair,
+    pub code_hash: String,
+}
+
+/// Pair queries used by other contracts.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum PairQueryMsg {
+    Simulation {
+        offer: Asset,
+        /// Required for pools of more than two assets.
+        ask_asset: Option<AssetMeta>,
+    },
+    /// Returns the pair's `SwapMode`.
+    SwapMode {},
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct SimulationResponse {
+    pub return_amount: Uint128,
+}
+
+/// Messages a pair accepts through SNIP-20 `Send`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum PairReceiveMsg {
+    Swap {
+        /// Required for pools of more than two assets.
+        ask_asset: Option<AssetMeta>,
+        /// Fails the swap if it would return less.
+        expected_return: Option<Uint128>,
+        /// Defaults to the sender.
+        to: Option<HumanAddr>,
+        /// If set, the output is delivered with SNIP-20 `Send` carrying this msg, so that `to`
+        /// gets a `Receive` callback.
+        callback: Option<Binary>,
+    },
+    /// Pays back a flash swap from within its callback.
+    RepayFlashSwap {},
+    /// Escrows the amount sent as a limit order, filled against the pool, in one go or in
+    /// parts, whenever that returns at least `ask_amount` for the whole amount, pro rata for
+    /// parts. The order's id is logged. The amount sent must be at least a basis point of the
+    /// pool's reserve of it.
+    PlaceOrder {
+        /// Required for pools of more than two assets.
+        ask_asset: Option<AssetMeta>,
+        ask_amount: Uint128,
+    },
+}
+
+/// Callback a pair executes on the receiver of a flash swap, after sending it `amounts`. The
+/// receiver must pay the pair back with `PairReceiveMsg::RepayFlashSwap` before returning.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum FlashSwapReceiverMsg {
+    FlashSwapCallback {
+        sender: HumanAddr,
+        /// In the same order as `Pair::assets`.
+        amounts: Vec<Uint128>,
+        msg: Binary,
+    },
+}
+
+/// Pair handles called by the factory.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum PairHandleMsg {
+    /// Withdraws all of `owner`'s liquidity and provides it to `new_pair` on their behalf,
+    /// failing if `new_pair` mints them fewer than `min_share` liquidity tokens.
+    MigrateLiquidity {
+        owner: HumanAddr,
+        new_pair: ContractLink,
+        min_share: Option<Uint128>,
+    },
+    /// Moves a StableSwap pair's amplification coefficient linearly to `future_amp` by
+    /// `future_time`.
+    RampAmplification {
+        future_amp: u64,
+        future_time: u64,
+    },
+    SetReserveVisibility {
+        visibility: ReserveVisibility,
+    },
+    SetStatus {
+        status: ContractStatus,
+    },
+    SetSwapLimits {
+        limits: SwapLimits,
+    },
 }